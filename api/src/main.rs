@@ -1,16 +1,23 @@
-use std::sync::{Arc, Mutex};
+use std::{collections::HashMap, sync::Arc, time::Duration};
 
 use axum::{
     Json, Router,
-    extract::{Path, State},
+    body::Bytes,
+    extract::{DefaultBodyLimit, Path, Query, State},
+    http::{HeaderMap, StatusCode, header},
+    response::{IntoResponse, Response},
     routing::{get, put},
 };
 use chrono::{DateTime, Utc};
 // use rusty_rolodex::{core::domain::AppState, domain::Contact, prelude::AppError};
+use rolodex_cli::cli::{get_attachments, get_history, get_store, modify_contacts};
 use rolodex_core::{
-    domain::Contact,
+    attachment::{self, MAX_ATTACHMENT_SIZE},
+    domain::{Contact, ContactFilter, ContactPatch},
     error::AppError,
-    store::{ContactStore, FileStore},
+    group::{self, Group},
+    history::ChangeSource,
+    store::ContactStore,
 };
 use serde::{Deserialize, Serialize};
 use uuid::Uuid;
@@ -38,7 +45,8 @@ pub struct ApiResponse {
 
 #[tokio::main]
 async fn main() -> Result<(), AppError> {
-    let store = Arc::new(Mutex::new(FileStore::new("contacts.json")));
+    // Requests queue behind a CLI run holding the file lock instead of failing
+    let store = get_store(Duration::from_secs(10))?;
 
     // build our application with a single route
     let app = Router::new()
@@ -48,15 +56,29 @@ async fn main() -> Result<(), AppError> {
             "/contacts/{contact_id}",
             put(edit_contact).delete(delete_contact),
         )
+        .route(
+            "/contacts/{contact_id}/photo",
+            get(get_photo)
+                .put(put_photo)
+                .layer(DefaultBodyLimit::max(MAX_ATTACHMENT_SIZE)),
+        )
+        .route("/groups", get(get_groups).post(post_group))
+        .route("/groups/{group_id}", get(get_group).delete(delete_group))
+        .route(
+            "/groups/{group_id}/members/{contact_id}",
+            put(add_group_member).delete(remove_group_member),
+        )
         .with_state(store);
 
-    async fn get_contacts(State(state): State<Arc<Mutex<FileStore>>>) -> Json<Vec<Contact>> {
-        let guard = state.lock().unwrap();
-        let imported_contacts = guard.load().expect("Panic happened!");
-
-        let data = imported_contacts.into_values().collect();
-
-        Json(data)
+    async fn get_contacts(
+        State(store): State<Arc<dyn ContactStore>>,
+        Query(filter): Query<ContactFilter>,
+    ) -> Response {
+        blocking(move || match store.list(&filter) {
+            Ok(data) => Json(data).into_response(),
+            Err(e) => error_response(StatusCode::INTERNAL_SERVER_ERROR, e.to_string()),
+        })
+        .await
     }
 
     // async fn get_all(store: axum::extract::State<Arc<FileStore>>) -> Json<Vec<Contact>> {
@@ -65,122 +87,372 @@ async fn main() -> Result<(), AppError> {
     // }
 
     async fn post_contacts(
-        State(state): State<Arc<Mutex<FileStore>>>,
+        State(store): State<Arc<dyn ContactStore>>,
         Json(mut payload): Json<Vec<Contact>>,
-    ) -> Json<ApiResponse> {
-        println!("Axum payload:{:?}", payload.clone());
-
-        let guard = state.lock().unwrap();
-
-        //Get the current contacts
-        let mut contacts = guard.load().expect("Panic happened when fetching contacts");
-
-        for contact in payload.iter_mut() {
-            let uu_id = Uuid::new_v4();
-            contacts.insert(
-                uu_id,
-                Contact {
-                    id: uu_id,
-                    name: contact.name.clone(),
-                    phone: contact.phone.clone(),
-                    email: contact.email.clone(),
-                    tags: contact.tags.clone(),
-                    created_at: contact.created_at,
-                    updated_at: contact.updated_at,
+    ) -> Response {
+        blocking(move || {
+            println!("Axum payload:{:?}", payload.clone());
+
+            for contact in payload.iter_mut() {
+                contact.id = Uuid::new_v4();
+                contact.normalize_name();
+            }
+            // One save for the whole batch, with its history written under
+            // the same lock
+            let result = modify_contacts(
+                store.as_ref(),
+                &get_history(),
+                ChangeSource::Api,
+                |contacts| {
+                    contacts.begin_batch();
+                    for contact in &payload {
+                        contacts.insert(contact.clone());
+                    }
+                    contacts.end_batch();
+                    Ok(())
                 },
             );
-        }
-        guard.save(contacts).expect("Panic occurred!");
+            if let Err(e) = result {
+                return error_response(StatusCode::INTERNAL_SERVER_ERROR, e.to_string());
+            }
 
-        println!("Axum payload after inserting:{:?}", payload);
+            println!("Axum payload after inserting:{:?}", payload);
 
-        let api_response = ApiResponse {
-            status: "success".to_string(),
-            message: "Contact created successfully".to_string(),
-            data: Some(payload),
-        };
+            let api_response = ApiResponse {
+                status: "success".to_string(),
+                message: "Contact created successfully".to_string(),
+                data: Some(payload),
+            };
 
-        Json(api_response)
+            Json(api_response).into_response()
+        })
+        .await
     }
 
     async fn delete_contact(
-        State(state): State<Arc<Mutex<FileStore>>>,
+        State(store): State<Arc<dyn ContactStore>>,
         Path(contact_id): Path<Uuid>,
-    ) -> Json<ApiResponse> {
-        //
-        let guard = state.lock().unwrap();
+    ) -> Response {
+        blocking(move || {
+            // Like the CLI's delete, links from other contacts go with it
+            let result = modify_contacts(
+                store.as_ref(),
+                &get_history(),
+                ChangeSource::Api,
+                |contacts| {
+                    contacts.delete(contact_id)?;
+                    Ok(contacts.items[&contact_id].clone())
+                },
+            );
 
-        //Get the contacts
-        let mut contacts = guard.load().expect("Error occurred while getting contacts");
+            match result {
+                Ok(trashed) => {
+                    let api_response = ApiResponse {
+                        status: "success".to_string(),
+                        message: format!("Contact with id:{} deleted!", contact_id),
+                        data: Some(vec![trashed]),
+                    };
+                    Json(api_response).into_response()
+                }
+                Err(AppError::NotFound(_)) => error_response(
+                    StatusCode::NOT_FOUND,
+                    format!("Contact with id: {} not found", contact_id),
+                ),
+                Err(e) => error_response(StatusCode::INTERNAL_SERVER_ERROR, e.to_string()),
+            }
+        })
+        .await
+    }
 
-        let result = contacts.remove(&contact_id);
+    async fn edit_contact(
+        State(store): State<Arc<dyn ContactStore>>,
+        Path(contact_id): Path<Uuid>,
+        headers: HeaderMap,
+        Json(payload): Json<Contact>,
+    ) -> Response {
+        blocking(move || {
+            let expected_revision = match parse_if_match(&headers) {
+                Ok(revision) => revision,
+                Err(message) => return error_response(StatusCode::BAD_REQUEST, message),
+            };
 
-        let api_response: ApiResponse;
+            // Empty fields in the payload leave the stored value untouched
+            let patch = ContactPatch {
+                name: Some(payload.name).filter(|n| !n.is_empty()),
+                phone: Some(payload.phone).filter(|p| !p.is_empty()),
+                email: Some(payload.email).filter(|e| !e.is_empty()),
+                addresses: Some(payload.addresses).filter(|a| !a.is_empty()),
+                organization: payload.organization,
+                title: payload.title,
+                department: payload.department,
+                notes: payload.notes,
+                custom_fields: Some(payload.custom_fields).filter(|f| !f.is_empty()),
+                birthday: payload.birthday,
+                anniversary: payload.anniversary,
+                dates: Some(payload.dates).filter(|d| !d.is_empty()),
+                tags: None,
+                expected_revision,
+            };
 
-        if let Some(response) = result {
-            guard.save(contacts).expect("Panic occured");
+            // Patched and recorded under the store lock, so the history entry's
+            // `before` is what this request replaced
+            let result = modify_contacts(
+                store.as_ref(),
+                &get_history(),
+                ChangeSource::Api,
+                |contacts| {
+                    contacts.update(contact_id, None, None, None, vec![], &patch)?;
+                    Ok(contacts.items[&contact_id].clone())
+                },
+            );
+            match result {
+                Ok(data) => {
+                    let api_response = ApiResponse {
+                        status: "success".to_string(),
+                        message: format!("Contact with id:{} updated!", contact_id),
+                        data: Some(vec![data.clone()]),
+                    };
+                    (
+                        [(header::ETAG, format!("\"{}\"", data.revision))],
+                        Json(api_response),
+                    )
+                        .into_response()
+                }
+                Err(AppError::Conflict(message)) => error_response(StatusCode::CONFLICT, message),
+                Err(AppError::Validation(message)) => {
+                    error_response(StatusCode::BAD_REQUEST, message)
+                }
+                Err(AppError::NotFound(_)) => error_response(
+                    StatusCode::NOT_FOUND,
+                    format!("Contact with id: {} not found", contact_id),
+                ),
+                Err(e) => error_response(StatusCode::INTERNAL_SERVER_ERROR, e.to_string()),
+            }
+        })
+        .await
+    }
 
-            api_response = ApiResponse {
-                status: "success".to_string(),
-                message: format!("Contact with id:{} deleted!", contact_id),
-                data: Some(vec![response]),
+    async fn get_photo(
+        State(store): State<Arc<dyn ContactStore>>,
+        Path(contact_id): Path<Uuid>,
+    ) -> Response {
+        blocking(move || {
+            let contact = match store.get(contact_id) {
+                Ok(contact) => contact.filter(|c| !c.is_deleted()),
+                Err(e) => return error_response(StatusCode::INTERNAL_SERVER_ERROR, e.to_string()),
             };
-            Json(api_response)
-        } else {
-            api_response = ApiResponse {
-                status: "error".to_string(),
-                message: format!("Contact with id: {} not found", contact_id),
-                data: None,
+            let Some((photo, revision)) = contact.and_then(|c| Some((c.photo?, c.revision))) else {
+                return error_response(
+                    StatusCode::NOT_FOUND,
+                    format!("Contact with id: {} has no photo", contact_id),
+                );
             };
-            Json(api_response)
-        }
+
+            match get_attachments().get(&photo.hash) {
+                Ok(bytes) => (
+                    [
+                        (header::CONTENT_TYPE, photo.media_type),
+                        (header::ETAG, format!("\"{}\"", revision)),
+                    ],
+                    bytes,
+                )
+                    .into_response(),
+                Err(e) => error_response(StatusCode::INTERNAL_SERVER_ERROR, e.to_string()),
+            }
+        })
+        .await
     }
 
-    async fn edit_contact(
-        State(state): State<Arc<Mutex<FileStore>>>,
+    /// The body is the raw image. The previous photo stays on disk for `undo`
+    /// until `attachment gc` finds nothing refers to it.
+    async fn put_photo(
+        State(store): State<Arc<dyn ContactStore>>,
         Path(contact_id): Path<Uuid>,
-        Json(payload): Json<Contact>,
-    ) -> Json<ApiResponse> {
-        let guard = state.lock().unwrap();
-
-        //Get the contacts
-        let mut contacts = guard.load().expect("Error occurred");
-
-        //Get the contact by ID
-        let contact = contacts.get(&contact_id).cloned();
+        headers: HeaderMap,
+        body: Bytes,
+    ) -> Response {
+        blocking(move || {
+            let expected_revision = match parse_if_match(&headers) {
+                Ok(revision) => revision,
+                Err(message) => return error_response(StatusCode::BAD_REQUEST, message),
+            };
+            let before = match store.get(contact_id) {
+                Ok(Some(contact)) if !contact.is_deleted() => contact,
+                Ok(_) => {
+                    return error_response(
+                        StatusCode::NOT_FOUND,
+                        format!("Contact with id: {} not found", contact_id),
+                    );
+                }
+                Err(e) => return error_response(StatusCode::INTERNAL_SERVER_ERROR, e.to_string()),
+            };
 
-        let api_response: ApiResponse;
+            let media_type = attachment::media_type("", &body);
+            if !media_type.starts_with("image/") {
+                return error_response(
+                    StatusCode::UNSUPPORTED_MEDIA_TYPE,
+                    format!("Expected an image, got {}", media_type),
+                );
+            }
+            let name = format!("photo.{}", media_type.trim_start_matches("image/"));
+            let attachments = get_attachments();
 
-        if let Some(mut data) = contact {
-            if !payload.name.is_empty() {
-                data.name = payload.name;
+            // The file is stored under the lock, so `attachment gc` can't remove
+            // it before the contact refers to it
+            let revision = expected_revision.unwrap_or(before.revision);
+            let result = modify_contacts(
+                store.as_ref(),
+                &get_history(),
+                ChangeSource::Api,
+                |contacts| {
+                    let stored = contacts.items.get(&contact_id).map(|c| c.revision);
+                    if stored != Some(revision) {
+                        return Err(AppError::Conflict(format!(
+                            "Contact {} was changed by someone else (revision {}, expected {})",
+                            contact_id,
+                            stored.unwrap_or_default(),
+                            revision
+                        )));
+                    }
+                    let photo = attachments.put(&name, &body)?;
+                    contacts.set_photo(contact_id, Some(photo))?;
+                    Ok(contacts.items[&contact_id].clone())
+                },
+            );
+            match result {
+                Ok(contact) => (
+                    [(header::ETAG, format!("\"{}\"", contact.revision))],
+                    Json(contact.photo),
+                )
+                    .into_response(),
+                Err(AppError::Conflict(message)) => error_response(StatusCode::CONFLICT, message),
+                Err(AppError::Validation(message)) => {
+                    error_response(StatusCode::BAD_REQUEST, message)
+                }
+                Err(AppError::NotFound(_)) => error_response(
+                    StatusCode::NOT_FOUND,
+                    format!("Contact with id: {} not found", contact_id),
+                ),
+                Err(e) => error_response(StatusCode::INTERNAL_SERVER_ERROR, e.to_string()),
             }
-            if !payload.email.is_empty() {
-                data.email = payload.email;
+        })
+        .await
+    }
+
+    async fn get_groups(State(store): State<Arc<dyn ContactStore>>) -> Response {
+        blocking(move || match load_live_groups(store.as_ref()) {
+            Ok(groups) => {
+                let mut groups: Vec<Group> = groups.into_values().collect();
+                groups.sort_by_key(|g| g.name.to_lowercase());
+                Json(groups).into_response()
             }
-            if !payload.phone.is_empty() {
-                data.phone = payload.phone;
+            Err(e) => error_response(StatusCode::INTERNAL_SERVER_ERROR, e.to_string()),
+        })
+        .await
+    }
+
+    async fn post_group(
+        State(store): State<Arc<dyn ContactStore>>,
+        Json(payload): Json<NewGroup>,
+    ) -> Response {
+        blocking(move || {
+            let mut created = None;
+            let result = store.modify_groups(&mut |groups| {
+                created = Some(group::create(
+                    groups,
+                    &payload.name,
+                    payload.description.clone(),
+                )?);
+                Ok(())
+            });
+
+            match (result, created) {
+                (Ok(()), Some(group)) => (StatusCode::CREATED, Json(group)).into_response(),
+                (Err(AppError::Validation(message)), _) => {
+                    error_response(StatusCode::BAD_REQUEST, message)
+                }
+                (Err(e), _) => error_response(StatusCode::INTERNAL_SERVER_ERROR, e.to_string()),
+                (Ok(()), None) => error_response(
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    "Group was not created".to_string(),
+                ),
             }
-            data.updated_at = Utc::now();
+        })
+        .await
+    }
 
-            contacts.insert(data.id, data.clone());
+    async fn get_group(
+        State(store): State<Arc<dyn ContactStore>>,
+        Path(group_id): Path<Uuid>,
+    ) -> Response {
+        blocking(move || match load_live_groups(store.as_ref()) {
+            Ok(mut groups) => match groups.remove(&group_id) {
+                Some(group) => Json(group).into_response(),
+                None => error_response(
+                    StatusCode::NOT_FOUND,
+                    format!("Group with id: {} not found", group_id),
+                ),
+            },
+            Err(e) => error_response(StatusCode::INTERNAL_SERVER_ERROR, e.to_string()),
+        })
+        .await
+    }
 
-            guard.save(contacts).expect("Panic occurred");
+    async fn delete_group(
+        State(store): State<Arc<dyn ContactStore>>,
+        Path(group_id): Path<Uuid>,
+    ) -> Response {
+        blocking(move || {
+            let mut removed = None;
+            let result = store.modify_groups(&mut |groups| {
+                removed = groups.remove(&group_id);
+                Ok(())
+            });
 
-            api_response = ApiResponse {
-                status: "success".to_string(),
-                message: format!("Contact with id:{} updated!", contact_id),
-                data: Some(vec![data]),
-            };
-            Json(api_response)
-        } else {
-            api_response = ApiResponse {
-                status: "error".to_string(),
-                message: format!("Contact with id: {} not found", contact_id),
-                data: None,
-            };
-            Json(api_response)
-        }
+            match (result, removed) {
+                (Ok(()), Some(group)) => Json(group).into_response(),
+                (Ok(()), None) => error_response(
+                    StatusCode::NOT_FOUND,
+                    format!("Group with id: {} not found", group_id),
+                ),
+                (Err(e), _) => error_response(StatusCode::INTERNAL_SERVER_ERROR, e.to_string()),
+            }
+        })
+        .await
+    }
+
+    async fn add_group_member(
+        State(store): State<Arc<dyn ContactStore>>,
+        Path((group_id, contact_id)): Path<(Uuid, Uuid)>,
+    ) -> Response {
+        blocking(move || {
+            match store.get(contact_id) {
+                Ok(Some(contact)) if !contact.is_deleted() => {}
+                Ok(_) => {
+                    return error_response(
+                        StatusCode::NOT_FOUND,
+                        format!("Contact with id: {} not found", contact_id),
+                    );
+                }
+                Err(e) => return error_response(StatusCode::INTERNAL_SERVER_ERROR, e.to_string()),
+            }
+            update_group(&store, group_id, |group| {
+                group.add_member(contact_id);
+            })
+        })
+        .await
+    }
+
+    async fn remove_group_member(
+        State(store): State<Arc<dyn ContactStore>>,
+        Path((group_id, contact_id)): Path<(Uuid, Uuid)>,
+    ) -> Response {
+        blocking(move || {
+            update_group(&store, group_id, |group| {
+                group.remove_member(contact_id);
+            })
+        })
+        .await
     }
 
     // run our app with hyper, listening globally on port 3000
@@ -190,3 +462,87 @@ async fn main() -> Result<(), AppError> {
     axum::serve(listener, app).await.unwrap();
     Ok(())
 }
+
+/// Body of `POST /groups`.
+#[derive(Debug, Deserialize)]
+struct NewGroup {
+    name: String,
+    #[serde(default)]
+    description: Option<String>,
+}
+
+/// Runs a handler's store work on tokio's blocking pool: the stores do file
+/// and SQLite I/O and may wait up to 10s for the lock, which must not stall
+/// the threads serving other requests.
+async fn blocking(work: impl FnOnce() -> Response + Send + 'static) -> Response {
+    tokio::task::spawn_blocking(work)
+        .await
+        .unwrap_or_else(|e| error_response(StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))
+}
+
+/// The groups as clients see them: trashed and purged contacts are left out
+/// of `members` (trashed ones come back if they are restored).
+fn load_live_groups(store: &dyn ContactStore) -> Result<HashMap<Uuid, Group>, AppError> {
+    let contacts = store.load()?;
+    let mut groups = store.load_groups()?;
+    for group in groups.values_mut() {
+        group.members = group.live_members(&contacts).iter().map(|c| c.id).collect();
+    }
+    Ok(groups)
+}
+
+/// Applies `change` to one group and responds with the result.
+fn update_group(
+    store: &Arc<dyn ContactStore>,
+    group_id: Uuid,
+    mut change: impl FnMut(&mut Group),
+) -> Response {
+    let mut updated = None;
+    let result = store.modify_groups(&mut |groups| {
+        if let Some(group) = groups.get_mut(&group_id) {
+            change(group);
+            updated = Some(group.clone());
+        }
+        Ok(())
+    });
+
+    match (result, updated) {
+        (Ok(()), Some(group)) => Json(group).into_response(),
+        (Ok(()), None) => error_response(
+            StatusCode::NOT_FOUND,
+            format!("Group with id: {} not found", group_id),
+        ),
+        (Err(e), _) => error_response(StatusCode::INTERNAL_SERVER_ERROR, e.to_string()),
+    }
+}
+
+/// Reads the expected revision from `If-Match` (`"3"`, `W/"3"` or `*` for any).
+fn parse_if_match(headers: &HeaderMap) -> Result<Option<u64>, String> {
+    let Some(value) = headers.get(header::IF_MATCH) else {
+        return Ok(None);
+    };
+
+    let value = value
+        .to_str()
+        .map_err(|_| "Invalid If-Match header".to_string())?
+        .trim();
+    if value == "*" {
+        return Ok(None);
+    }
+
+    value
+        .trim_start_matches("W/")
+        .trim_matches('"')
+        .parse::<u64>()
+        .map(Some)
+        .map_err(|_| format!("If-Match must be a contact revision, got {}", value))
+}
+
+fn error_response(status: StatusCode, message: String) -> Response {
+    let api_response = ApiResponse {
+        status: "error".to_string(),
+        message,
+        data: None,
+    };
+    (status, Json(api_response)).into_response()
+}
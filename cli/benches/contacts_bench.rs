@@ -2,7 +2,11 @@ use std::collections::HashMap;
 
 use chrono::Utc;
 use criterion::{Criterion, criterion_group, criterion_main};
-use rolodex_core::domain::{Contact, Contacts};
+use rolodex_core::{
+    domain::{Contact, Contacts},
+    email::Email,
+    phone::Phone,
+};
 use uuid::Uuid;
 
 fn sample_contacts(n: usize) -> Contacts {
@@ -17,11 +21,12 @@ fn sample_contacts(n: usize) -> Contacts {
             Contact {
                 id,
                 name: format!("User{}", i),
-                phone: vec![format!("232323323211")],
-                email: format!("user{}@example.com", i),
+                phone: vec![Phone::from("232323323211")],
+                email: vec![Email::new(&format!("user{}@example.com", i))],
                 tags: vec!["work".into()],
                 created_at: now,
                 updated_at: now,
+                ..Default::default()
             },
         );
     }
@@ -2,7 +2,11 @@ use std::collections::HashMap;
 
 use chrono::Utc;
 use criterion::{Criterion, black_box, criterion_group, criterion_main};
-use rolodex_core::domain::{Contact, ContactsIndex};
+use rolodex_core::{
+    domain::{Contact, ContactsIndex},
+    email::Email,
+    phone::Phone,
+};
 use uuid::Uuid;
 
 fn build_contacts(count: usize) -> HashMap<Uuid, Contact> {
@@ -17,11 +21,12 @@ fn build_contacts(count: usize) -> HashMap<Uuid, Contact> {
             Contact {
                 id,
                 name: format!("Person{}", i),
-                email: format!("person{}@mail.com", i),
-                phone: vec![format!("232323323211")],
+                email: vec![Email::new(&format!("person{}@mail.com", i))],
+                phone: vec![Phone::from("232323323211")],
                 tags: vec!["bench".into()],
                 created_at: now,
                 updated_at: now,
+                ..Default::default()
             },
         );
     }
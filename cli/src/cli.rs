@@ -1,12 +1,34 @@
 use chrono::Utc;
-use clap::{Parser, Subcommand};
-use rolodex_core::domain::{Contact, Contacts, export_csv, import_csv};
+use clap::{Args, Parser, Subcommand};
+use rolodex_core::address::{Address, AddressLabel};
+use rolodex_core::attachment::{self, AttachmentStore};
+use rolodex_core::csv_profile::CsvProfile;
+use rolodex_core::dates::{LabelledDate, PartialDate};
+use rolodex_core::domain::{
+    Contact, ContactFilter, ContactPatch, Contacts, RejectWriter, export_csv_with_profile,
+    for_each_csv_row, print_search_results, upcoming_dates,
+};
+use rolodex_core::email::{Email, EmailLabel, format_emails};
 use rolodex_core::error::AppError;
-use rolodex_core::store::{ContactStore, FileStore, MemStore, MergePolicy, RemoteStore};
+use rolodex_core::group;
+use rolodex_core::helpers::parse_age;
+use rolodex_core::history::{ChangeRecord, ChangeSource, HistoryLog, revert};
+use rolodex_core::phone::{Phone, PhoneLabel, format_phones};
+use rolodex_core::relation::RelationKind;
+use rolodex_core::store::{
+    ContactStore, FileStore, JournalStore, MemStore, MergePolicy, RemoteStore, SqliteStore,
+};
+use rolodex_core::stream::{self, ContactWriter, Format};
+use rolodex_core::tags;
 use rolodex_core::validation::{
     ValidationResponse, validate_email, validate_name, validate_phone_number,
 };
+use rolodex_core::vcard::{Version as VcardVersion, export_vcf, import_vcf};
+use std::collections::HashMap;
 use std::env;
+use std::path::Path;
+use std::sync::Arc;
+use std::time::Duration;
 use uuid::Uuid;
 
 // use crate::domain::{Contact, Contacts, export_csv, import_csv};
@@ -23,29 +45,73 @@ use uuid::Uuid;
 struct Cli {
     #[command(subcommand)]
     command: Commands,
+    /// Seconds to wait when another process holds the lock on the contacts file
+    #[arg(long, global = true, default_value_t = 0)]
+    wait: u64,
 }
 
 #[derive(Subcommand)]
 enum Commands {
     /// Add a new contact
     Add {
+        /// Full name; split into parts automatically unless they are given below
+        #[arg(long, required_unless_present_any = ["given_name", "family_name"])]
+        name: Option<String>,
         #[arg(long)]
-        name: String,
+        given_name: Option<String>,
+        #[arg(long)]
+        middle_name: Option<String>,
+        #[arg(long)]
+        family_name: Option<String>,
+        #[arg(long)]
+        prefix: Option<String>,
+        #[arg(long)]
+        suffix: Option<String>,
+        #[arg(long)]
+        nickname: Option<String>,
         #[arg(long)]
         phone: String,
+        /// mobile, work, home, fax or other
+        #[arg(long, default_value = "mobile")]
+        phone_label: PhoneLabel,
+        /// Primary address
         #[arg(long)]
         email: String,
+        /// personal, work or other
+        #[arg(long, default_value = "personal")]
+        email_label: EmailLabel,
+        /// Further addresses, comma separated
+        #[arg(long, value_delimiter = ',')]
+        other_email: Vec<String>,
+        #[arg(long)]
+        company: Option<String>,
+        #[arg(long)]
+        title: Option<String>,
+        #[arg(long)]
+        department: Option<String>,
+        #[command(flatten)]
+        address: Box<AddressArgs>,
+        #[command(flatten)]
+        dates: Box<DateArgs>,
         #[arg(long, value_delimiter = ',')]
         tags: Vec<String>,
     },
     /// List contacts (optionally filter/sort)
     List {
         #[arg(long)]
-        sort: Option<String>, // "name" or "email"
+        sort: Option<String>, // "name", "family_name", "given_name" or "email"
         #[arg(long)]
         tag: Option<String>,
         #[arg(long)]
         domain: Option<String>,
+        /// Organization contains this text
+        #[arg(long)]
+        company: Option<String>,
+        #[arg(long)]
+        city: Option<String>,
+        /// Group name or id
+        #[arg(long)]
+        group: Option<String>,
         // #[arg(long)]
         // created_at: Option<String>,
         // #[arg(long)]
@@ -58,6 +124,23 @@ enum Commands {
         // #[arg(long)]
         // phone: Option<String>,
     },
+    /// Link a contact to another, e.g. `--kind manager` for their manager
+    Link {
+        #[arg(long)]
+        id: Uuid,
+        #[arg(long)]
+        to: Uuid,
+        /// spouse, assistant, manager, referred-by or colleague
+        #[arg(long)]
+        kind: RelationKind,
+    },
+    /// Remove the links from a contact to another
+    Unlink {
+        #[arg(long)]
+        id: Uuid,
+        #[arg(long)]
+        to: Uuid,
+    },
     Update {
         #[arg(long)]
         id: Uuid,
@@ -70,22 +153,78 @@ enum Commands {
         new_name: Option<String>,
         #[arg(long)]
         new_phone: Option<String>,
+        /// Replaces the primary address
         #[arg(long)]
         new_email: Option<String>,
+        /// Adds addresses next to the primary one, comma separated
+        #[arg(long, value_delimiter = ',')]
+        add_email: Vec<String>,
+        /// Sets a custom field; repeatable. `key=` removes the field
+        #[arg(long = "set", value_name = "KEY=VALUE", value_parser = parse_custom_field)]
+        set: Vec<(String, String)>,
+        #[command(flatten)]
+        dates: Box<DateArgs>,
     },
     ExportCsv {
         #[arg(long, default_value = "contacts.csv")]
         path: String,
+        /// Fill the `groups` column with each contact's groups
+        #[arg(long)]
+        with_groups: bool,
+        /// Column layout: rolodex, google, outlook, or a .toml/.json profile
+        #[arg(long, default_value = "rolodex")]
+        profile: String,
     },
+    /// Merge the rows of a CSV file into the contacts, like `sync`
     ImportCsv {
         #[arg(long, default_value = "contacts.csv")]
         path: String,
+        /// Column layout: rolodex, google, outlook, or a .toml/.json profile
+        #[arg(long, default_value = "rolodex")]
+        profile: String,
+        #[arg(long, value_enum, default_value_t = MergePolicy::Keep)]
+        policy: MergePolicy,
+        /// Import the valid rows even if some are rejected
+        #[arg(long)]
+        skip_invalid: bool,
+        /// Write rejected rows here, with the reason in an `error` column
+        #[arg(long)]
+        reject_file: Option<String>,
+    },
+    /// Write every contact to a vCard file, photos included
+    ExportVcf {
+        #[arg(long, default_value = "contacts.vcf")]
+        path: String,
+        /// Add each contact's groups to its CATEGORIES
+        #[arg(long)]
+        with_groups: bool,
+        /// vCard version to write: 3.0 | 4.0
+        #[arg(long, default_value = "3.0")]
+        vcard_version: VcardVersion,
+    },
+    /// Merge the cards of a vCard file into the contacts, like `sync`
+    ImportVcf {
+        #[arg(long, default_value = "contacts.vcf")]
+        path: String,
+        #[arg(long, value_enum, default_value_t = MergePolicy::Keep)]
+        policy: MergePolicy,
     },
     Search {
         #[arg(long)]
         name: Option<String>,
+        /// Any format; matched on the normalized number
+        #[arg(long)]
+        phone: Option<String>,
         #[arg(long)]
         domain: Option<String>,
+        /// Organization contains this text
+        #[arg(long)]
+        company: Option<String>,
+        #[arg(long)]
+        city: Option<String>,
+        /// Notes contain this text
+        #[arg(long)]
+        note: Option<String>,
         #[arg(long)]
         fuzzy: Option<String>,
         #[arg(long)]
@@ -94,59 +233,491 @@ enum Commands {
     Sync {
         #[arg(long)]
         file: String,
-        #[arg(long, default_value = "keep")]
-        policy: String,
+        #[arg(long, value_enum, default_value_t = MergePolicy::Keep)]
+        policy: MergePolicy,
     },
+    /// Send the contacts to REMOTE_URL, or write them to a file
     Export {
+        /// A URL, or a file path (`.json`, `.jsonl`/`.ndjson`, `.csv`)
         #[arg(long)]
         to: String,
+        /// json | ndjson | csv; taken from the file extension if left out
+        #[arg(long)]
+        format: Option<Format>,
     },
+    /// Fetch contacts from a URL, or merge them from a file like `sync`
     Import {
+        /// A URL, or a JSON or NDJSON file path
         #[arg(long)]
         from: String,
+        /// json | ndjson; taken from the file extension if left out
+        #[arg(long)]
+        format: Option<Format>,
+        #[arg(long, value_enum, default_value_t = MergePolicy::Keep)]
+        policy: MergePolicy,
+    },
+    /// Collapse the journal (STORE_TYPE=journal) into a single snapshot
+    Compact,
+    /// Show the change timeline of a contact
+    History {
+        #[arg(long)]
+        id: Uuid,
+    },
+    /// Revert the most recent operations (a whole sync or import counts as one)
+    Undo {
+        #[arg(long, default_value_t = 1)]
+        steps: usize,
+    },
+    /// Upgrade a contacts file (JSON or the old contacts.txt) to the current schema
+    Migrate {
+        #[arg(long, default_value = "contacts.json")]
+        path: String,
+        /// Only report what would change
+        #[arg(long)]
+        dry_run: bool,
+    },
+    /// Manage deleted contacts
+    Trash {
+        #[command(subcommand)]
+        command: TrashCommands,
+    },
+    /// Birthdays, anniversaries and other dates coming up
+    Upcoming {
+        #[arg(long, default_value_t = 14)]
+        days: i64,
+        #[arg(long)]
+        tag: Option<String>,
+    },
+    /// Free-form notes on a contact
+    Note {
+        #[command(subcommand)]
+        command: NoteCommands,
+    },
+    /// Named groups of contacts
+    Group {
+        #[command(subcommand)]
+        command: GroupCommands,
+    },
+    /// Hierarchical tags such as work/project-x
+    Tags {
+        #[command(subcommand)]
+        command: TagsCommands,
+    },
+    /// A contact's avatar image
+    Photo {
+        #[command(subcommand)]
+        command: PhotoCommands,
+    },
+    /// Small files such as business card scans attached to a contact
+    Attachment {
+        #[command(subcommand)]
+        command: AttachmentCommands,
     },
 }
 
-// fn get_store() -> Box<dyn ContactStore> {
-//     match env::var("STORE_TYPE")
-//         .unwrap_or("file".to_string())
-//         .as_str()
-//     {
-//         "mem" => Box::new(MemStore::new()),
-//         _ => Box::new(FileStore),
-//     }
-// }
-
-fn get_store() -> Box<dyn ContactStore> {
-    let binding = env::var("STORE_TYPE").unwrap_or("file".to_string());
-    let env = binding.as_str();
-
-    match env {
-        "mem" => Box::new(MemStore::new()),
-        "remote" => Box::new(RemoteStore::new()),
-        _ => Box::new(FileStore::new("contacts.json")),
+#[derive(Subcommand)]
+enum PhotoCommands {
+    /// Set or replace the photo from an image file
+    Set {
+        #[arg(long)]
+        id: Uuid,
+        #[arg(long)]
+        file: String,
+    },
+    /// Write the photo to a file
+    Save {
+        #[arg(long)]
+        id: Uuid,
+        #[arg(long)]
+        out: String,
+    },
+    /// Remove the photo
+    Remove {
+        #[arg(long)]
+        id: Uuid,
+    },
+}
+
+#[derive(Subcommand)]
+enum AttachmentCommands {
+    /// Attach a file
+    Add {
+        #[arg(long)]
+        id: Uuid,
+        #[arg(long)]
+        file: String,
+    },
+    /// List a contact's attachments
+    List {
+        #[arg(long)]
+        id: Uuid,
+    },
+    /// Write an attachment to a file
+    Save {
+        #[arg(long)]
+        id: Uuid,
+        /// File name or hash of the attachment
+        #[arg(long)]
+        name: String,
+        #[arg(long)]
+        out: String,
+    },
+    /// Remove an attachment
+    Remove {
+        #[arg(long)]
+        id: Uuid,
+        /// File name or hash of the attachment
+        #[arg(long)]
+        name: String,
+    },
+    /// Delete stored files no contact refers to any more
+    Gc,
+}
+
+#[derive(Subcommand)]
+enum TagsCommands {
+    /// List tags as a tree with the number of contacts under each
+    List,
+    /// Rename a tag and its children on every contact
+    Rename { old: String, new: String },
+    /// Move every contact tagged FROM onto the existing tag INTO
+    Merge { from: String, into: String },
+}
+
+#[derive(Subcommand)]
+enum GroupCommands {
+    /// Create an empty group
+    Create {
+        #[arg(long)]
+        name: String,
+        #[arg(long)]
+        description: Option<String>,
+    },
+    /// Add a contact to a group
+    Add {
+        /// Group name or id
+        #[arg(long)]
+        group: String,
+        #[arg(long)]
+        id: Uuid,
+    },
+    /// Remove a contact from a group
+    Remove {
+        /// Group name or id
+        #[arg(long)]
+        group: String,
+        #[arg(long)]
+        id: Uuid,
+    },
+    /// List groups and their sizes
+    List,
+    /// Show a group and its members
+    Show {
+        /// Group name or id
+        #[arg(long)]
+        group: String,
+    },
+    /// Delete a group; its contacts are kept
+    Delete {
+        /// Group name or id
+        #[arg(long)]
+        group: String,
+    },
+}
+
+#[derive(Subcommand)]
+enum NoteCommands {
+    /// Append a line to a contact's notes
+    Add {
+        #[arg(long)]
+        id: Uuid,
+        #[arg(long)]
+        text: String,
+    },
+}
+
+#[derive(Subcommand)]
+enum TrashCommands {
+    /// List contacts in the trash
+    List,
+    /// Bring a contact back from the trash
+    Restore {
+        #[arg(long)]
+        id: Uuid,
+    },
+    /// Permanently delete contacts that have been in the trash longer than this (e.g. 30d)
+    Purge {
+        #[arg(long, default_value = "30d")]
+        older_than: String,
+    },
+}
+
+/// The last component of `path`, as an attachment's file name.
+fn file_name(path: &str) -> String {
+    Path::new(path)
+        .file_name()
+        .map(|n| n.to_string_lossy().into_owned())
+        .unwrap_or(path.to_string())
+}
+
+/// Parses `key=value` for `update --set`.
+fn parse_custom_field(value: &str) -> Result<(String, String), String> {
+    let (key, value) = value
+        .split_once('=')
+        .ok_or(format!("Expected KEY=VALUE, got '{}'", value))?;
+    let key = key.trim();
+    if key.is_empty() {
+        return Err("Custom field name is empty".to_string());
     }
+    Ok((key.to_string(), value.trim().to_string()))
+}
+
+/// Names of the live contacts, for printing the links of `contacts`. The
+/// store is loaded once, and only if one of them has links at all.
+fn live_names(
+    store: &dyn ContactStore,
+    contacts: &[Contact],
+) -> Result<HashMap<Uuid, String>, AppError> {
+    if contacts.iter().all(|c| c.relations.is_empty()) {
+        return Ok(HashMap::new());
+    }
+    Ok(store
+        .load()?
+        .into_values()
+        .filter(|c| !c.is_deleted())
+        .map(|c| (c.id, c.name))
+        .collect())
+}
+
+fn parse_date(value: &str) -> Result<PartialDate, String> {
+    value.parse().map_err(|e: AppError| e.to_string())
+}
+
+fn parse_labelled_date(value: &str) -> Result<LabelledDate, String> {
+    value.parse().map_err(|e: AppError| e.to_string())
+}
+
+/// Date flags of `add` and `update`.
+#[derive(Args)]
+struct DateArgs {
+    /// YYYY-MM-DD, or MM-DD when the year is unknown
+    #[arg(long, value_parser = parse_date)]
+    birthday: Option<PartialDate>,
+    #[arg(long, value_parser = parse_date)]
+    anniversary: Option<PartialDate>,
+    /// Any other date to remember, e.g. `work=2015-03-01`; repeatable.
+    /// On update these replace dates with the same label
+    #[arg(long = "date", value_name = "LABEL=DATE", value_parser = parse_labelled_date)]
+    dates: Vec<LabelledDate>,
+}
+
+/// Postal address flags of `add`.
+#[derive(Args)]
+struct AddressArgs {
+    #[arg(long)]
+    street: Option<String>,
+    #[arg(long)]
+    city: Option<String>,
+    /// State, province or county
+    #[arg(long)]
+    region: Option<String>,
+    #[arg(long)]
+    postal_code: Option<String>,
+    #[arg(long)]
+    country: Option<String>,
+    /// home, work or other
+    #[arg(long, default_value = "home")]
+    address_label: AddressLabel,
+}
+
+impl AddressArgs {
+    fn into_address(self) -> Option<Address> {
+        let address = Address {
+            label: self.address_label,
+            street: self.street,
+            city: self.city,
+            region: self.region,
+            postal_code: self.postal_code,
+            country: self.country,
+        };
+        (!address.is_empty()).then_some(address)
+    }
+}
+
+/// Picks the storage backend from `STORE_TYPE` (`mem`, `remote`, `sqlite`, `journal`,
+/// `jsonl`, default `file`).
+/// Shared by the CLI and the API server so both always talk to the same backend.
+/// `lock_wait` bounds how long a write waits for another process's file lock.
+pub fn get_store(lock_wait: Duration) -> Result<Arc<dyn ContactStore>, AppError> {
+    let store_type = store_type();
+    let path = store_path(&store_type);
+
+    let store: Arc<dyn ContactStore> = match store_type.as_str() {
+        "mem" => Arc::new(MemStore::new()),
+        "remote" => Arc::new(RemoteStore::new()),
+        "sqlite" => Arc::new(SqliteStore::open(path)?),
+        "journal" => Arc::new(JournalStore::new(path).with_lock_timeout(lock_wait)),
+        _ => Arc::new(FileStore::new(path).with_lock_timeout(lock_wait)),
+    };
+    Ok(store)
+}
+
+fn store_type() -> String {
+    env::var("STORE_TYPE").unwrap_or("file".to_string())
+}
+
+/// The file each `STORE_TYPE` keeps its contacts in, and names its other
+/// files after. `mem` and `remote` keep none, but get a name of their own so
+/// they share nothing with a store on disk.
+fn store_path(store_type: &str) -> &'static str {
+    match store_type {
+        "mem" => "contacts.mem",
+        "remote" => "contacts.remote",
+        "sqlite" => "contacts.db",
+        "journal" => "contacts.journal",
+        "jsonl" => "contacts.jsonl",
+        _ => "contacts.json",
+    }
+}
+
+/// The change log written next to the contacts store by both the CLI and the API.
+pub fn get_history() -> HistoryLog {
+    HistoryLog::new("contacts.history.jsonl")
+}
+
+/// Photos and attachment files of the store `get_store` picks. Each store
+/// has its own directory, since `attachment gc` keeps only the files its
+/// contacts refer to; the default file store's is `attachments/`, where it
+/// has always been.
+pub fn get_attachments() -> AttachmentStore {
+    match store_path(&store_type()) {
+        "contacts.json" => AttachmentStore::next_to("contacts.json"),
+        path => AttachmentStore::new(format!("{}.attachments", path)),
+    }
+}
+
+fn is_url(location: &str) -> bool {
+    location.starts_with("http://") || location.starts_with("https://")
+}
+
+/// Deletes attachment files nothing can bring back: no contact, live or
+/// trashed, refers to them, and neither does any version in the history that
+/// `undo` could restore. Runs under the store lock, like every edit that
+/// stores a file, so a file is never collected between its `put` and the save
+/// that refers to it.
+pub fn collect_attachments(
+    store: &dyn ContactStore,
+    history: &HistoryLog,
+    attachments: &AttachmentStore,
+) -> Result<(), AppError> {
+    let mut removed = Vec::new();
+    store.modify(&mut |items| {
+        let versions = history.versions()?;
+        removed = attachments.collect_garbage(items.values().chain(&versions))?;
+        Ok(())
+    })?;
+    if !removed.is_empty() {
+        println!(
+            "🧹 Removed {} unreferenced attachment file(s)",
+            removed.len()
+        );
+    }
+    Ok(())
+}
+
+/// Runs `change` on the contacts while the store is locked, so a write from
+/// another process in between can't be lost. The change records go into
+/// `history` before the store is saved, so no saved change is missing from
+/// the log.
+pub fn modify_contacts<T>(
+    store: &dyn ContactStore,
+    history: &HistoryLog,
+    source: ChangeSource,
+    mut change: impl FnMut(&mut Contacts) -> Result<T, AppError>,
+) -> Result<T, AppError> {
+    let mut result = None;
+    store.modify(&mut |items| {
+        let mut contacts = Contacts::new(std::mem::take(items)).with_source(source);
+        let outcome = change(&mut contacts);
+        let changes = contacts.take_changes();
+        *items = contacts.items;
+        result = Some(outcome?);
+        history.append(&changes)
+    })?;
+    Ok(result.expect("modify ran the change"))
 }
 
 pub fn run_command_cli() -> Result<(), AppError> {
     let cli = Cli::parse();
-    let store = get_store();
+    let store = get_store(Duration::from_secs(cli.wait))?;
+    let history = get_history();
+    let attachments = get_attachments();
     // let store = FsStore::new("contacts.json");
 
-    let mut contacts = Contacts::new(store.load()?);
-
     match cli.command {
         Commands::Add {
             name,
+            given_name,
+            middle_name,
+            family_name,
+            prefix,
+            suffix,
+            nickname,
             phone,
+            phone_label,
             email,
+            email_label,
+            other_email,
+            company,
+            title,
+            department,
+            address,
+            dates,
             tags,
         } => {
+            let mut new_contact = Contact::new(
+                name.as_deref().unwrap_or_default(),
+                &phone,
+                &email,
+                tags.clone(),
+                Utc::now(),
+                Utc::now(),
+            );
+            new_contact.phone = vec![Phone::with_label(&phone, phone_label)];
+            new_contact.set_primary_email(Email::with_label(&email, email_label));
+            new_contact
+                .email
+                .extend(other_email.iter().map(|e| Email::new(e)));
+            if given_name.is_some() || family_name.is_some() {
+                new_contact.prefix = prefix;
+                new_contact.given_name = given_name;
+                new_contact.middle_name = middle_name;
+                new_contact.family_name = family_name;
+                new_contact.suffix = suffix;
+                if name.is_none() {
+                    new_contact.normalize_name();
+                }
+            } else {
+                // The parts parsed from --name, with the ones given swapped in
+                new_contact.prefix = prefix.or(new_contact.prefix.take());
+                new_contact.middle_name = middle_name.or(new_contact.middle_name.take());
+                new_contact.suffix = suffix.or(new_contact.suffix.take());
+            }
+            new_contact.nickname = nickname;
+            new_contact.organization = company;
+            new_contact.title = title;
+            new_contact.department = department;
+            new_contact.addresses = (*address).into_address().into_iter().collect();
+            new_contact.birthday = dates.birthday;
+            new_contact.anniversary = dates.anniversary;
+            new_contact.dates = dates.dates;
+            let name = new_contact.name.clone();
+
             if !validate_name(&name) {
                 return Err(AppError::Validation(ValidationResponse::check_name()));
             }
 
-            if !validate_email(&email) {
+            if !new_contact.email.iter().all(|e| validate_email(&e.address)) {
                 return Err(AppError::Validation(ValidationResponse::check_email()));
             }
 
@@ -156,36 +727,48 @@ pub fn run_command_cli() -> Result<(), AppError> {
                 ));
             }
 
-            // let mut contacts = store.load()?;
-            let new_contact =
-                Contact::new(&name, &phone, &email, tags.clone(), Utc::now(), Utc::now());
-
-            contacts.add(new_contact)?;
-            store
-                .save(contacts.items.clone())
-                .map_err(|e| AppError::Parse(format!("Error occurred: {}", e)))?;
+            modify_contacts(store.as_ref(), &history, ChangeSource::Cli, |contacts| {
+                contacts.add(new_contact.clone())
+            })?;
             println!("✅ Added contact: {} ({})", name, email);
         }
-        Commands::List { sort, tag, domain } => {
-            //Chain filters using iterator
-            let mut filtered_contacts: Vec<&Contact> = contacts
-                .iter()
-                .filter(|c| tag.as_ref().is_none_or(|t| c.has_tag(t)))
-                .filter(|c| domain.as_ref().is_none_or(|d| c.has_domain(d)))
-                .collect();
+        Commands::List {
+            sort,
+            tag,
+            domain,
+            company,
+            city,
+            group,
+        } => {
+            let filter = ContactFilter {
+                tag,
+                domain,
+                company,
+                city,
+                ..Default::default()
+            };
+            let mut contacts = store.list(&filter)?;
+            if let Some(key) = group {
+                let groups = store.load_groups()?;
+                let group = group::find(&groups, &key)?;
+                contacts.retain(|c| group.members.contains(&c.id));
+            }
+            let names = live_names(store.as_ref(), &contacts)?;
+            let mut filtered_contacts: Vec<&Contact> = contacts.iter().collect();
 
             filtered_contacts.sort_by(|a, b| a.name.cmp(&b.name));
 
             if let Some(sort_key) = sort {
                 match sort_key.as_str() {
                     "name" => filtered_contacts.sort_by(|a, b| a.name.cmp(&b.name)),
-                    "email" => filtered_contacts.sort_by(|a, b| a.email.cmp(&b.email)),
-                    "created_at" => {
-                        filtered_contacts.sort_by(|a, b| a.created_at.cmp(&b.created_at))
-                    }
-                    "updated_at" => {
-                        filtered_contacts.sort_by(|a, b| a.updated_at.cmp(&b.updated_at))
+                    "family_name" | "surname" => {
+                        filtered_contacts.sort_by_key(|c| c.family_sort_key())
                     }
+                    "given_name" => filtered_contacts
+                        .sort_by_key(|c| c.given_name.as_deref().unwrap_or(&c.name).to_lowercase()),
+                    "email" => filtered_contacts.sort_by_key(|c| c.primary_email().to_lowercase()),
+                    "created_at" => filtered_contacts.sort_by_key(|c| c.created_at),
+                    "updated_at" => filtered_contacts.sort_by_key(|c| c.updated_at),
                     _ => println!("⚠️ Unsupported sort key: {}", sort_key),
                 }
             }
@@ -194,87 +777,693 @@ pub fn run_command_cli() -> Result<(), AppError> {
                 println!("No contacts found.");
             } else {
                 for c in filtered_contacts {
-                    println!("📇 {} | {} | {}", c.name, c.phone.join(", "), c.email);
+                    println!(
+                        "📇 {} | {} | {}",
+                        c.name,
+                        format_phones(&c.phone),
+                        format_emails(&c.email)
+                    );
+                    if let Some(job) = c.job_line() {
+                        println!("   {}", job);
+                    }
+                    for line in c.relation_lines(|id| names.get(&id).cloned()) {
+                        println!("   🔗 {}", line);
+                    }
                 }
             }
         }
         Commands::Delete { id } => {
-            contacts.delete(id)?;
-            store.save(contacts.items.clone())?;
+            // Links from other contacts are dropped too, so save them all
+            let name = modify_contacts(store.as_ref(), &history, ChangeSource::Cli, |contacts| {
+                contacts.delete(id)?;
+                Ok(contacts.items[&id].name.clone())
+            })?;
+            println!("🗑️ Moved contact to the trash: {}", name);
+        }
+        Commands::Link { id, to, kind } => {
+            let (name, to_name) =
+                modify_contacts(store.as_ref(), &history, ChangeSource::Cli, |contacts| {
+                    contacts.link(id, kind, to)?;
+                    Ok((
+                        contacts.items[&id].name.clone(),
+                        contacts.items[&to].name.clone(),
+                    ))
+                })?;
+            println!("🔗 {} is now {}'s {}", to_name, name, kind);
+        }
+        Commands::Unlink { id, to } => {
+            let name = modify_contacts(store.as_ref(), &history, ChangeSource::Cli, |contacts| {
+                contacts.unlink(id, to)?;
+                Ok(contacts.items[&id].name.clone())
+            })?;
+            println!("✂️ Unlinked {} from {}", name, to);
         }
         Commands::Update {
             id,
             new_name,
             new_phone,
             new_email,
+            add_email,
+            set,
+            dates,
         } => {
-            contacts.update(id, new_name, new_phone, new_email)?;
-            store.save(contacts.items.clone())?;
+            let DateArgs {
+                birthday,
+                anniversary,
+                dates,
+            } = *dates;
+            let patch = ContactPatch {
+                custom_fields: (!set.is_empty()).then(|| set.into_iter().collect()),
+                birthday,
+                anniversary,
+                dates: (!dates.is_empty()).then_some(dates),
+                ..Default::default()
+            };
+            modify_contacts(store.as_ref(), &history, ChangeSource::Cli, |contacts| {
+                contacts.update(
+                    id,
+                    new_name.clone(),
+                    new_phone.clone(),
+                    new_email.as_deref().map(Email::new),
+                    add_email.iter().map(|e| Email::new(e)).collect(),
+                    &patch,
+                )
+            })?;
         }
-        Commands::ExportCsv { path } => {
-            let contacts = store.load()?;
-
-            let vec_contacts: Vec<Contact> = contacts.values().cloned().collect();
-            export_csv(&path, &vec_contacts)?;
+        Commands::ExportCsv {
+            path,
+            with_groups,
+            profile,
+        } => {
+            let profile = CsvProfile::load(&profile)?;
+            let contacts = store.list(&ContactFilter::default())?;
+            let groups = if with_groups {
+                store.load_groups()?
+            } else {
+                HashMap::new()
+            };
+            export_csv_with_profile(&path, &contacts, &groups, &profile)?;
             println!("✅ Exported {} contacts to {}", contacts.len(), path);
         }
-        Commands::ImportCsv { path } => {
-            // let mut contacts = store.load()?;
-            let imported = import_csv(&path)?;
+        Commands::ExportVcf {
+            path,
+            with_groups,
+            vcard_version,
+        } => {
+            let mut contacts = store.list(&ContactFilter::default())?;
+            contacts.sort_by_key(|c| c.family_sort_key());
+            let groups = if with_groups {
+                store.load_groups()?
+            } else {
+                HashMap::new()
+            };
+            export_vcf(&path, &contacts, &attachments, &groups, vcard_version)?;
+            println!("✅ Exported {} contacts to {}", contacts.len(), path);
+        }
+        Commands::ImportVcf { path, policy } => {
+            // Card photos are stored under the lock, like every other edit
+            let (merged, read) =
+                modify_contacts(store.as_ref(), &history, ChangeSource::Import, |contacts| {
+                    let imported = import_vcf(&path, &attachments)?;
+                    let read = imported.len();
+                    Ok((contacts.merge_contacts(imported, policy)?, read))
+                })?;
+            println!(
+                "✅ Imported {} of {} cards from {} using policy: {}",
+                merged, read, path, policy
+            );
+        }
+        Commands::ImportCsv {
+            path,
+            profile,
+            policy,
+            skip_invalid,
+            reject_file,
+        } => {
+            let profile = CsvProfile::load(&profile)?;
 
-            // contacts.extend(imported);
-            for mut c in imported {
-                c.id = Uuid::new_v4();
-                contacts.items.insert(c.id, c);
-            }
-            store.save(contacts.items)?;
-            println!("✅ Imported contacts from {}", path);
+            // Rows go straight from the file into the merge; a failure
+            // (including bad rows without --skip-invalid) rolls it all back
+            let mut rejected = 0;
+            let counts =
+                modify_contacts(store.as_ref(), &history, ChangeSource::Import, |contacts| {
+                    rejected = 0;
+                    contacts.merge_stream(policy, |merge| {
+                        let mut rejects = None;
+                        for_each_csv_row(&path, &profile, &mut |headers, row| match row {
+                            Ok(contact) => merge(contact),
+                            Err(row) => {
+                                eprintln!("⚠️  Line {}: {}", row.line, row.reason);
+                                rejected += 1;
+                                if rejects.is_none()
+                                    && let Some(reject_file) = &reject_file
+                                {
+                                    rejects =
+                                        Some(RejectWriter::create(reject_file, &profile, headers)?);
+                                }
+                                if let Some(rejects) = rejects.as_mut() {
+                                    rejects.write(&row)?;
+                                }
+                                Ok(())
+                            }
+                        })?;
+                        if let Some(rejects) = rejects {
+                            rejects.finish()?;
+                            println!(
+                                "📝 Wrote {} rejected rows to {}",
+                                rejected,
+                                reject_file.as_deref().unwrap_or_default()
+                            );
+                        }
+                        if rejected > 0 && !skip_invalid {
+                            return Err(AppError::Validation(format!(
+                                "{} invalid rows in {}; nothing was imported. \
+                                 Fix them or pass --skip-invalid",
+                                rejected, path
+                            )));
+                        }
+                        Ok(())
+                    })
+                })?;
+            println!(
+                "✅ {} rows accepted, {} rejected, {} duplicates; \
+                 {} imported from {} using policy: {}",
+                counts.read, rejected, counts.duplicates, counts.merged, path, policy
+            );
         }
         Commands::Search {
             name,
+            phone,
             domain,
+            company,
+            city,
+            note,
             fuzzy,
             concurrent,
         } => {
-            let _d = contacts.search(name, domain, fuzzy, concurrent)?;
+            if fuzzy.is_some() || concurrent.is_some() {
+                let contacts = Contacts::new(store.load()?);
+                let _d = contacts.search(name, domain, fuzzy, concurrent)?;
+            } else {
+                // Exact lookups are answered by the store (indexed in SQLite)
+                let mut matches = Vec::new();
+                if name.is_some() {
+                    matches.extend(store.list(&ContactFilter {
+                        name,
+                        ..Default::default()
+                    })?);
+                }
+                if domain.is_some() {
+                    matches.extend(store.list(&ContactFilter {
+                        domain,
+                        ..Default::default()
+                    })?);
+                }
+                if phone.is_some() {
+                    matches.extend(store.list(&ContactFilter {
+                        phone,
+                        ..Default::default()
+                    })?);
+                }
+                if company.is_some() {
+                    matches.extend(store.list(&ContactFilter {
+                        company,
+                        ..Default::default()
+                    })?);
+                }
+                if city.is_some() {
+                    matches.extend(store.list(&ContactFilter {
+                        city,
+                        ..Default::default()
+                    })?);
+                }
+                if note.is_some() {
+                    matches.extend(store.list(&ContactFilter {
+                        note,
+                        ..Default::default()
+                    })?);
+                }
+                let names = live_names(store.as_ref(), &matches)?;
+                print_search_results(&matches, |id| names.get(&id).cloned());
+            }
         }
         Commands::Sync { file, policy } => {
-            let merge_policy = match policy.as_str() {
-                "keep" => MergePolicy::Keep,
-                "overwrite" => MergePolicy::Overwrite,
-                "duplicate" => MergePolicy::Duplicate,
-                _ => {
-                    eprintln!(
-                        "❌ Invalid policy '{}'. Use: keep | overwrite | duplicate",
-                        policy
-                    );
-                    return Ok(());
-                }
-            };
-            // store.merge_from_file(&file, merge_policy)?;
-            contacts.merge_from_file(&file, merge_policy)?;
-            // contacts.sync_from_file(&file, merge_policy)?;
-            store.save(contacts.items)?;
+            modify_contacts(store.as_ref(), &history, ChangeSource::Sync, |contacts| {
+                contacts.merge_from_file(&file, policy)
+            })?;
             println!("✅ Sync complete using policy: {}", policy);
         }
-        Commands::Export { to } => {
-            // contacts.export_to_remote(to).await?;
+        Commands::Export { to, format } => {
+            if !is_url(&to) {
+                let format = format.unwrap_or_else(|| Format::from_path(&to));
+                let mut contacts = store.list(&ContactFilter::default())?;
+                contacts.sort_by_key(|c| (c.family_sort_key(), c.id));
+                let mut writer =
+                    ContactWriter::create(&to, format, &CsvProfile::default(), contacts.len())?;
+                for c in &contacts {
+                    writer.write(c)?;
+                }
+                let written = writer.finish()?;
+                println!("✅ Exported {} contacts to {} as {}", written, to, format);
+                return Ok(());
+            }
+            if format.is_some_and(|f| f != Format::Json) {
+                return Err(AppError::Validation(
+                    "Remote stores only take JSON".to_string(),
+                ));
+            }
+            let contacts = Contacts::new(store.load()?);
             contacts.export_to_remote(to)?;
         }
-        Commands::Import { from } => {
-            // contacts.async_check(from).await?;
-            contacts.import_from_remote(from)?;
-            store.save(contacts.items)?;
+        Commands::Import {
+            from,
+            format,
+            policy,
+        } => {
+            if !is_url(&from) {
+                let format = format.unwrap_or_else(|| Format::from_path(&from));
+
+                let counts =
+                    modify_contacts(store.as_ref(), &history, ChangeSource::Import, |contacts| {
+                        contacts.merge_stream(policy, |merge| {
+                            stream::read_contacts_as(&from, format, merge).map(|_| ())
+                        })
+                    })?;
+                println!(
+                    "✅ Read {} contacts from {} ({} duplicates); {} imported using policy: {}",
+                    counts.read, from, counts.duplicates, counts.merged, policy
+                );
+                return Ok(());
+            }
+
+            modify_contacts(store.as_ref(), &history, ChangeSource::Import, |contacts| {
+                contacts.begin_batch();
+                contacts.import_from_remote(from.clone())
+            })?;
+        }
+        Commands::Compact => {
+            store.compact()?;
+            println!("✅ Journal compacted");
+        }
+        Commands::History { id } => {
+            let records = history.for_contact(id)?;
+            if records.is_empty() {
+                println!("No history for {}", id);
+            }
+            for record in records {
+                print_change(&record);
+            }
+        }
+        Commands::Migrate { path, dry_run } => {
+            let source = FileStore::new(&path).with_legacy_text();
+            let report = source.check_schema()?;
+            // contacts.txt is upgraded into contacts.json next to it
+            let target = match Path::new(&path).extension() {
+                Some(extension) if extension == "txt" => Path::new(&path).with_extension("json"),
+                _ => Path::new(&path).to_path_buf(),
+            };
+
+            println!(
+                "{}: schema version {} -> {} ({} contacts)",
+                path, report.from_version, report.to_version, report.contacts
+            );
+            for step in &report.steps {
+                println!("  - {}", step);
+            }
+
+            if report.is_current() {
+                println!("✅ Already up to date");
+            } else if dry_run {
+                println!("Dry run: {} was not written", target.display());
+            } else if target == Path::new(&path) {
+                // Loading upgrades the contacts; writing them back stores the new schema
+                source
+                    .with_lock_timeout(Duration::from_secs(cli.wait))
+                    .modify(&mut |_| Ok(()))?;
+                println!("✅ Migrated {}", path);
+            } else if target.exists() {
+                return Err(AppError::Validation(format!(
+                    "{} already exists, not overwriting it",
+                    target.display()
+                )));
+            } else {
+                FileStore::new(&target).save(source.load()?)?;
+                println!("✅ Migrated {} into {}", path, target.display());
+            }
+        }
+        Commands::Upcoming { days, tag } => {
+            let contacts = store.list(&ContactFilter {
+                tag,
+                ..Default::default()
+            })?;
+            let upcoming = upcoming_dates(&contacts, Utc::now().date_naive(), days);
+            if upcoming.is_empty() {
+                println!("Nothing coming up in the next {} days.", days);
+            }
+            for u in upcoming {
+                let when = match u.days_until {
+                    0 => "today".to_string(),
+                    1 => "tomorrow".to_string(),
+                    n => format!("in {} days", n),
+                };
+                let years = u
+                    .years()
+                    .map(|y| format!(" ({} years)", y))
+                    .unwrap_or_default();
+                println!(
+                    "🎉 {} ({}): {} – {}{}",
+                    when,
+                    u.on.format("%a %d %b"),
+                    u.contact.name,
+                    u.label,
+                    years
+                );
+            }
+        }
+        Commands::Group { command } => match command {
+            GroupCommands::Create { name, description } => {
+                let mut created = None;
+                store.modify_groups(&mut |groups| {
+                    created = Some(group::create(groups, &name, description.clone())?);
+                    Ok(())
+                })?;
+                if let Some(group) = created {
+                    println!("✅ Created group: {} ({})", group.name, group.id);
+                }
+            }
+            GroupCommands::Add { group, id } => {
+                let contact = store
+                    .get(id)?
+                    .filter(|c| !c.is_deleted())
+                    .ok_or(AppError::NotFound("Contact not found".to_string()))?;
+                let mut name = String::new();
+                store.modify_groups(&mut |groups| {
+                    let group = group::find_mut(groups, &group)?;
+                    if !group.add_member(id) {
+                        return Err(AppError::Validation(format!(
+                            "{} is already in {}",
+                            contact.name, group.name
+                        )));
+                    }
+                    name = group.name.clone();
+                    Ok(())
+                })?;
+                println!("✅ Added {} to {}", contact.name, name);
+            }
+            GroupCommands::Remove { group, id } => {
+                let mut name = String::new();
+                store.modify_groups(&mut |groups| {
+                    let group = group::find_mut(groups, &group)?;
+                    if !group.remove_member(id) {
+                        return Err(AppError::Validation(format!(
+                            "{} is not in {}",
+                            id, group.name
+                        )));
+                    }
+                    name = group.name.clone();
+                    Ok(())
+                })?;
+                println!("✅ Removed {} from {}", id, name);
+            }
+            GroupCommands::List => {
+                let groups = store.load_groups()?;
+                if groups.is_empty() {
+                    println!("No groups yet.");
+                }
+                let contacts = store.load()?;
+                let mut groups: Vec<_> = groups.into_values().collect();
+                groups.sort_by_key(|g| g.name.to_lowercase());
+                for g in groups {
+                    println!(
+                        "👥 {} | {} member(s) | {}",
+                        g.name,
+                        g.live_members(&contacts).len(),
+                        g.description.as_deref().unwrap_or_default()
+                    );
+                }
+            }
+            GroupCommands::Show { group } => {
+                let groups = store.load_groups()?;
+                let group = group::find(&groups, &group)?;
+                println!("👥 {} ({})", group.name, group.id);
+                if let Some(description) = &group.description {
+                    println!("   {}", description);
+                }
+                let contacts = store.load()?;
+                let mut members = group.live_members(&contacts);
+                members.sort_by(|a, b| a.name.cmp(&b.name));
+                if members.is_empty() {
+                    println!("   No members.");
+                }
+                for c in members {
+                    println!(
+                        "   📇 {} | {} | {}",
+                        c.name,
+                        format_phones(&c.phone),
+                        format_emails(&c.email)
+                    );
+                }
+            }
+            GroupCommands::Delete { group } => {
+                let mut name = String::new();
+                store.modify_groups(&mut |groups| {
+                    let id = group::find(groups, &group)?.id;
+                    name = groups.remove(&id).map(|g| g.name).unwrap_or_default();
+                    Ok(())
+                })?;
+                println!("🗑️ Deleted group: {}", name);
+            }
+        },
+        Commands::Tags { command } => match command {
+            TagsCommands::List => {
+                let contacts = Contacts::new(store.load()?);
+                let counts = contacts.index.tag_counts();
+                if counts.is_empty() {
+                    println!("No tags yet.");
+                }
+                for (tag, count) in counts {
+                    let leaf = tag.rsplit(tags::TAG_SEPARATOR).next().unwrap_or(tag);
+                    println!("{}🏷️ {} ({})", "  ".repeat(tags::depth(tag)), leaf, count);
+                }
+            }
+            TagsCommands::Rename { old, new } => {
+                // Every tagged contact is rewritten in one save
+                let renamed =
+                    modify_contacts(store.as_ref(), &history, ChangeSource::Cli, |contacts| {
+                        contacts.rename_tag(&old, &new)
+                    })?;
+                println!("🏷️ Renamed {} to {} on {} contact(s)", old, new, renamed);
+            }
+            TagsCommands::Merge { from, into } => {
+                let merged =
+                    modify_contacts(store.as_ref(), &history, ChangeSource::Cli, |contacts| {
+                        contacts.merge_tags(&from, &into)
+                    })?;
+                println!("🏷️ Merged {} into {} on {} contact(s)", from, into, merged);
+            }
+        },
+        Commands::Photo { command } => match command {
+            PhotoCommands::Set { id, file } => {
+                let bytes = std::fs::read(&file)?;
+                let media_type = attachment::media_type(&file, &bytes);
+                if !media_type.starts_with("image/") {
+                    return Err(AppError::Validation(format!(
+                        "{} is not an image ({})",
+                        file, media_type
+                    )));
+                }
+                let name =
+                    modify_contacts(store.as_ref(), &history, ChangeSource::Cli, |contacts| {
+                        let photo = attachments.put(&file_name(&file), &bytes)?;
+                        contacts.set_photo(id, Some(photo))?;
+                        Ok(contacts.items[&id].name.clone())
+                    })?;
+                println!("🖼️ Set photo of {}", name);
+            }
+            PhotoCommands::Save { id, out } => {
+                let photo = store
+                    .get(id)?
+                    .ok_or(AppError::NotFound("Contact not found".to_string()))?
+                    .photo
+                    .ok_or(AppError::Parse("Contact has no photo".to_string()))?;
+                std::fs::write(&out, attachments.get(&photo.hash)?)?;
+                println!("✅ Saved photo to {}", out);
+            }
+            PhotoCommands::Remove { id } => {
+                let name =
+                    modify_contacts(store.as_ref(), &history, ChangeSource::Cli, |contacts| {
+                        contacts.set_photo(id, None)?;
+                        Ok(contacts.items[&id].name.clone())
+                    })?;
+                println!("🗑️ Removed photo of {}", name);
+            }
+        },
+        Commands::Attachment { command } => match command {
+            AttachmentCommands::Add { id, file } => {
+                let bytes = std::fs::read(&file)?;
+                let (attachment, name) =
+                    modify_contacts(store.as_ref(), &history, ChangeSource::Cli, |contacts| {
+                        let attachment = attachments.put(&file_name(&file), &bytes)?;
+                        contacts.attach(id, attachment.clone())?;
+                        Ok((attachment, contacts.items[&id].name.clone()))
+                    })?;
+                println!(
+                    "📎 Attached {} ({} bytes) to {}",
+                    attachment.name, attachment.size, name
+                );
+            }
+            AttachmentCommands::List { id } => {
+                let contact = store
+                    .get(id)?
+                    .ok_or(AppError::NotFound("Contact not found".to_string()))?;
+                if let Some(photo) = &contact.photo {
+                    println!(
+                        "🖼️ {} | {} | {} bytes",
+                        photo.name, photo.media_type, photo.size
+                    );
+                }
+                if contact.attachments.is_empty() {
+                    println!("No attachments.");
+                }
+                for a in &contact.attachments {
+                    println!(
+                        "📎 {} | {} | {} bytes | {}",
+                        a.name, a.media_type, a.size, a.hash
+                    );
+                }
+            }
+            AttachmentCommands::Save { id, name, out } => {
+                let contact = store
+                    .get(id)?
+                    .ok_or(AppError::NotFound("Contact not found".to_string()))?;
+                let attachment = contact
+                    .attachments
+                    .iter()
+                    .find(|a| a.hash.eq_ignore_ascii_case(&name) || a.name == name)
+                    .ok_or(AppError::Parse(format!(
+                        "{} has no attachment {}",
+                        contact.name, name
+                    )))?;
+                std::fs::write(&out, attachments.get(&attachment.hash)?)?;
+                println!("✅ Saved {} to {}", attachment.name, out);
+            }
+            AttachmentCommands::Remove { id, name } => {
+                let (removed, contact_name) =
+                    modify_contacts(store.as_ref(), &history, ChangeSource::Cli, |contacts| {
+                        let removed = contacts.detach(id, &name)?;
+                        Ok((removed, contacts.items[&id].name.clone()))
+                    })?;
+                println!("🗑️ Removed {} from {}", removed.name, contact_name);
+            }
+            AttachmentCommands::Gc => {
+                collect_attachments(store.as_ref(), &history, &attachments)?;
+                println!("✅ Attachments are clean");
+            }
+        },
+        Commands::Note { command } => match command {
+            NoteCommands::Add { id, text } => {
+                let name =
+                    modify_contacts(store.as_ref(), &history, ChangeSource::Cli, |contacts| {
+                        contacts.add_note(id, &text)?;
+                        Ok(contacts.items[&id].name.clone())
+                    })?;
+                println!("📝 Added note to {}", name);
+            }
+        },
+        Commands::Trash { command } => match command {
+            TrashCommands::List => {
+                let trashed = store.list(&ContactFilter {
+                    trashed: true,
+                    ..Default::default()
+                })?;
+                if trashed.is_empty() {
+                    println!("Trash is empty.");
+                }
+                for c in trashed {
+                    println!(
+                        "🗑️ {} | {} | {} | deleted {} | {}",
+                        c.name,
+                        format_phones(&c.phone),
+                        format_emails(&c.email),
+                        c.deleted_at.unwrap_or_default().format("%Y-%m-%d %H:%M"),
+                        c.id
+                    );
+                }
+            }
+            TrashCommands::Restore { id } => {
+                modify_contacts(store.as_ref(), &history, ChangeSource::Cli, |contacts| {
+                    contacts.restore(id)
+                })?;
+                println!("♻️ Restored contact {}", id);
+            }
+            TrashCommands::Purge { older_than } => {
+                let cutoff = Utc::now() - parse_age(&older_than)?;
+                let purged =
+                    modify_contacts(store.as_ref(), &history, ChangeSource::Cli, |contacts| {
+                        contacts.begin_batch();
+                        Ok(contacts.purge(cutoff))
+                    })?;
+                if !purged.is_empty() {
+                    store.modify_groups(&mut |groups| {
+                        group::forget(groups, &purged);
+                        Ok(())
+                    })?;
+                }
+                println!("🔥 Purged {} contact(s) from the trash", purged.len());
+                collect_attachments(store.as_ref(), &history, &attachments)?;
+            }
+        },
+        Commands::Undo { steps } => {
+            // Read under the lock, so a change saved meanwhile is either undone
+            // too or seen as a conflict
+            let undo_operation = Uuid::new_v4();
+            let mut undone = (0, 0);
+            store.modify(&mut |items| {
+                let operations = history.undoable(steps)?;
+                let changes = revert(items, &operations, undo_operation)?;
+                undone = (operations.len(), changes.len());
+                history.append(&changes)
+            })?;
+            if undone.0 == 0 {
+                println!("Nothing to undo.");
+                return Ok(());
+            }
+            println!(
+                "↩️ Undid {} operation(s), {} contact change(s)",
+                undone.0, undone.1
+            );
         }
     }
 
     Ok(())
 }
 
+fn print_change(record: &ChangeRecord) {
+    let state = record.after.as_ref().or(record.before.as_ref());
+    let summary = state
+        .map(|c| {
+            format!(
+                "{} | {} | {}",
+                c.name,
+                format_phones(&c.phone),
+                format_emails(&c.email)
+            )
+        })
+        .unwrap_or_default();
+
+    println!(
+        "🕓 {} [{}] {}: {}",
+        record.at.format("%Y-%m-%d %H:%M:%S"),
+        record.source,
+        record.action(),
+        summary
+    );
+}
+
 #[cfg(test)]
 mod tests {
 
-    use std::collections::HashMap;
+    use std::collections::{BTreeMap, HashMap};
 
     // use crate::store::mem::ContactsIndex;
 
@@ -368,10 +1557,11 @@ mod tests {
                 id: alice_id,
                 name: "Alice".to_string(),
                 phone: vec!["123".into()],
-                email: "alice@work.com".to_string(),
+                email: vec!["alice@work.com".into()],
                 tags: vec!["work".into()],
                 created_at: Utc::now(),
                 updated_at: Utc::now(),
+                ..Default::default()
             },
         );
 
@@ -381,10 +1571,11 @@ mod tests {
                 id: id2,
                 name: "Alicia".to_string(),
                 phone: vec!["123".into()],
-                email: "alicia@work.com".to_string(),
+                email: vec!["alicia@work.com".into()],
                 tags: vec!["work".into()],
                 created_at: Utc::now(),
                 updated_at: Utc::now(),
+                ..Default::default()
             },
         );
 
@@ -394,10 +1585,11 @@ mod tests {
                 id: id3,
                 name: "Bob".to_string(),
                 phone: vec!["456".into()],
-                email: "bob@personal.com".to_string(),
+                email: vec!["bob@personal.com".into()],
                 tags: vec!["personal".into()],
                 created_at: Utc::now(),
                 updated_at: Utc::now(),
+                ..Default::default()
             },
         );
 
@@ -407,10 +1599,11 @@ mod tests {
                 id: id4,
                 name: "Carol".to_string(),
                 phone: vec!["789".into()],
-                email: "carol@work.com".to_string(),
+                email: vec!["carol@work.com".into()],
                 tags: vec!["work".into()],
                 created_at: Utc::now(),
                 updated_at: Utc::now(),
+                ..Default::default()
             },
         );
 
@@ -465,6 +1658,215 @@ mod tests {
         assert_eq!(domain_ids.len(), 3);
     }
 
+    #[test]
+    fn test_domain_index_covers_every_address() {
+        let (_, contacts) = sample_contacts2();
+        let mut contacts = Contacts::new(contacts);
+        let bob = *contacts.index.lookup_name("Bob").iter().next().unwrap();
+
+        contacts
+            .update(
+                bob,
+                None,
+                None,
+                None,
+                vec!["bob@work.com".into()],
+                &ContactPatch::default(),
+            )
+            .unwrap();
+        assert_eq!(contacts.index.lookup_domain("work.com").len(), 4);
+        assert!(contacts.index.lookup_domain("personal.com").contains(&bob));
+        assert_eq!(contacts.items[&bob].primary_email(), "bob@personal.com");
+
+        // Already on the contact, so only the primary flag moves
+        contacts
+            .update(
+                bob,
+                None,
+                None,
+                Some("BOB@work.com".into()),
+                vec![],
+                &ContactPatch::default(),
+            )
+            .unwrap();
+        assert_eq!(contacts.items[&bob].primary_email(), "bob@work.com");
+        assert_eq!(contacts.items[&bob].email.len(), 2);
+        assert!(contacts.index.lookup_domain("personal.com").contains(&bob));
+
+        // Already the primary address, or added twice, so rejected
+        for (new_email, extra) in [
+            (Some("bob@work.com"), vec![]),
+            (None, vec!["BOB@personal.com"]),
+        ] {
+            let result = contacts.update(
+                bob,
+                None,
+                None,
+                new_email.map(Email::from),
+                extra.into_iter().map(Email::from).collect(),
+                &ContactPatch::default(),
+            );
+            assert!(result.is_err());
+        }
+    }
+
+    #[test]
+    fn test_custom_fields_and_notes() {
+        let (alice, contacts) = sample_contacts2();
+        let mut contacts = Contacts::new(contacts);
+
+        let set = |pairs: &[&str]| -> BTreeMap<String, String> {
+            pairs
+                .iter()
+                .map(|p| parse_custom_field(p).unwrap())
+                .collect()
+        };
+        let patch = |pairs: &[&str]| ContactPatch {
+            custom_fields: Some(set(pairs)),
+            ..Default::default()
+        };
+        contacts
+            .update(
+                alice,
+                None,
+                None,
+                None,
+                vec![],
+                &patch(&["tz=WAT", "slack = @alice"]),
+            )
+            .unwrap();
+        contacts
+            .update(alice, None, None, None, vec![], &patch(&["slack="]))
+            .unwrap();
+        assert_eq!(contacts.items[&alice].custom_fields, set(&["tz=WAT"]));
+        assert!(parse_custom_field("=x").is_err());
+        assert!(parse_custom_field("novalue").is_err());
+
+        contacts.add_note(alice, "Sent invoice #42").unwrap();
+        contacts.add_note(alice, "Paid").unwrap();
+        assert_eq!(
+            contacts.items[&alice].notes.as_deref(),
+            Some("Sent invoice #42\nPaid")
+        );
+        let by_note = ContactFilter {
+            note: Some("INVOICE".to_string()),
+            ..Default::default()
+        };
+        assert!(by_note.matches(&contacts.items[&alice]));
+        assert_eq!(contacts.take_changes().len(), 4);
+    }
+
+    #[test]
+    fn test_merge_keeps_note_lines() {
+        let (alice, contacts) = sample_contacts2();
+        let mut local = contacts[&alice].clone();
+        local.notes = Some("Call back on Monday\nLikes tea".to_string());
+        let mut imported = local.clone();
+        imported.notes = Some("Call back\nLikes tea".to_string());
+
+        let merged = rolodex_core::helpers::merge_contact_data(&local, &imported);
+        assert_eq!(
+            merged.notes.as_deref(),
+            Some("Call back on Monday\nLikes tea\nCall back")
+        );
+    }
+
+    #[test]
+    fn test_links_are_dropped_when_target_is_deleted() {
+        let (alice, contacts) = sample_contacts2();
+        let mut contacts = Contacts::new(contacts);
+        let bob = *contacts.index.lookup_name("Bob").iter().next().unwrap();
+
+        contacts.link(alice, RelationKind::Manager, bob).unwrap();
+        assert!(contacts.link(alice, RelationKind::Manager, bob).is_err());
+        assert!(contacts.link(alice, RelationKind::Spouse, alice).is_err());
+        let lines = contacts.items[&alice]
+            .relation_lines(|id| contacts.items.get(&id).map(|c| c.name.clone()));
+        assert_eq!(lines, vec!["manager: Bob"]);
+        contacts.take_changes();
+
+        contacts.delete(bob).unwrap();
+        assert!(contacts.items[&alice].relations.is_empty());
+        let changes = contacts.take_changes();
+        assert_eq!(changes.len(), 2);
+        assert_eq!(changes[0].operation, changes[1].operation);
+        assert!(contacts.unlink(alice, bob).is_err());
+    }
+
+    #[test]
+    fn test_upcoming_dates_window_and_order() {
+        let (alice, contacts) = sample_contacts2();
+        let mut contacts = Contacts::new(contacts);
+        let bob = *contacts.index.lookup_name("Bob").iter().next().unwrap();
+        let day = |s: &str| chrono::NaiveDate::parse_from_str(s, "%Y-%m-%d").unwrap();
+
+        let patch = ContactPatch {
+            birthday: Some(parse_date("1990-12-31").unwrap()),
+            dates: Some(vec![parse_labelled_date("met=--01-02").unwrap()]),
+            ..Default::default()
+        };
+        contacts
+            .update(alice, None, None, None, vec![], &patch)
+            .unwrap();
+        let patch = ContactPatch {
+            anniversary: Some(parse_date("--12-30").unwrap()),
+            ..Default::default()
+        };
+        contacts
+            .update(bob, None, None, None, vec![], &patch)
+            .unwrap();
+        assert!(parse_labelled_date("1990-01-01").is_err());
+
+        let upcoming = upcoming_dates(contacts.items.values(), day("2026-12-29"), 7);
+        let seen: Vec<_> = upcoming
+            .iter()
+            .map(|u| (u.contact.name.as_str(), u.label.as_str(), u.days_until))
+            .collect();
+        assert_eq!(
+            seen,
+            vec![
+                ("Bob", "anniversary", 1),
+                ("Alice", "birthday", 2),
+                ("Alice", "met", 4)
+            ]
+        );
+        assert_eq!(upcoming[1].years(), Some(36));
+        assert_eq!(upcoming[2].on, day("2027-01-02"));
+
+        assert!(upcoming_dates(contacts.items.values(), day("2026-06-01"), 14).is_empty());
+    }
+
+    #[test]
+    fn test_tag_index_rename_and_merge() {
+        let (alice, mut contacts) = sample_contacts2();
+        contacts.get_mut(&alice).unwrap().tags = vec!["work/project-x".into()];
+        let mut contacts = Contacts::new(contacts);
+        let bob = *contacts.index.lookup_name("Bob").iter().next().unwrap();
+
+        assert_eq!(contacts.index.lookup_tag("work").len(), 3);
+        assert_eq!(contacts.index.lookup_tag("work/project-x").len(), 1);
+        assert!(contacts.items[&alice].has_tag("work"));
+        assert_eq!(
+            contacts.index.tag_counts(),
+            vec![("personal", 1), ("work", 3), ("work/project-x", 1)]
+        );
+
+        assert!(contacts.rename_tag("work", "personal").is_err());
+        assert!(contacts.rename_tag("missing", "job").is_err());
+        assert_eq!(contacts.rename_tag("work", "job").unwrap(), 3);
+        assert_eq!(contacts.items[&alice].tags, vec!["job/project-x"]);
+        assert!(contacts.index.lookup_tag("work").is_empty());
+        let changes = contacts.take_changes();
+        assert_eq!(changes.len(), 3);
+        assert!(changes.iter().all(|c| c.operation == changes[0].operation));
+
+        assert!(contacts.merge_tags("job", "nowhere").is_err());
+        assert_eq!(contacts.merge_tags("personal", "job").unwrap(), 1);
+        assert_eq!(contacts.take_changes().len(), 1);
+        assert_eq!(contacts.items[&bob].tags, vec!["job"]);
+        assert_eq!(contacts.index.lookup_tag("job").len(), 4);
+    }
+
     // fuzzy search
     #[test]
     fn test_exact_match_name() {
@@ -494,7 +1896,7 @@ mod tests {
         let ids = index.index.lookup_name(&new_contact.name);
 
         if let Some(d) = ids.iter().next() {
-            let get_contact = index.items.get(&d).unwrap();
+            let get_contact = index.items.get(d).unwrap();
             assert_eq!(d.clone(), get_contact.id);
         }
     }
@@ -508,10 +1910,129 @@ mod tests {
         let ids = index.index.lookup_name("alice");
 
         if let Some(value) = ids.iter().next() {
-            let _ = index.delete(value.clone());
+            let _ = index.delete(*value);
         }
 
         let ids = index.index.lookup_name("alice");
         assert_eq!(ids.len(), 0);
     }
+
+    #[test]
+    fn test_trashed_contact_is_not_merged_back() {
+        let day_ago = Utc::now() - chrono::Duration::days(1);
+        let dave = Contact::new(
+            "Dave Lee",
+            "+1 202 555 0147",
+            "dave@work.com",
+            vec![],
+            day_ago,
+            day_ago,
+        );
+        let mut contacts = Contacts::new(HashMap::from([(dave.id, dave.clone())]));
+        contacts.delete(dave.id).unwrap();
+
+        // Another device's copy, with a fresh id and the number written differently
+        let copy = Contact::new(
+            "dave lee",
+            "+1-202-555-0147",
+            "dave@work.com",
+            vec![],
+            day_ago,
+            day_ago,
+        );
+        let merged = contacts.merge_single_contact(copy.clone(), &MergePolicy::Keep);
+        assert!(!merged.unwrap());
+
+        contacts.purge(Utc::now() + chrono::Duration::seconds(1));
+        let merged = contacts.merge_single_contact(copy, &MergePolicy::Keep);
+        assert!(merged.unwrap());
+    }
+
+    #[test]
+    fn test_lookup_by_name_part() {
+        let contact = Contact::new(
+            "Dr. Conan O'Brien Jr.",
+            "08123456789",
+            "conan@show.com",
+            vec![],
+            Utc::now(),
+            Utc::now(),
+        );
+        assert_eq!(contact.given_name.as_deref(), Some("Conan"));
+        assert_eq!(contact.family_name.as_deref(), Some("O'Brien"));
+        assert_eq!(contact.suffix.as_deref(), Some("Jr."));
+        assert_eq!(contact.initials(), "CO");
+
+        let contacts = Contacts::new(HashMap::from([(contact.id, contact.clone())]));
+        assert!(contacts.index.lookup_name("o'brien").contains(&contact.id));
+        assert!(
+            contacts
+                .index
+                .lookup_name("dr. conan o'brien jr.")
+                .contains(&contact.id)
+        );
+    }
+
+    #[test]
+    fn test_failed_merge_rolls_back_every_change() {
+        let mut contacts = sample_contacts();
+        let items = contacts.items.clone();
+        let mut existing = items.values().next().unwrap().clone();
+        existing.title = Some("Changed".to_string());
+        let new_contact = Contact::new(
+            "Zed",
+            "08123456700",
+            "zed@work.com",
+            vec![],
+            Utc::now(),
+            Utc::now(),
+        );
+
+        let result = contacts.merge_stream(MergePolicy::Overwrite, |merge| {
+            merge(existing.clone())?;
+            merge(new_contact.clone())?;
+            Err(AppError::Parse("Line 3: bad".to_string()))
+        });
+        assert!(result.is_err());
+        assert_eq!(contacts.items, items);
+        assert!(contacts.take_changes().is_empty());
+        assert!(contacts.index.lookup_name("zed").is_empty());
+    }
+
+    #[test]
+    fn test_attachment_gc_keeps_what_undo_needs() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = MemStore::new();
+        let history = HistoryLog::new(dir.path().join("contacts.history.jsonl"));
+        let attachments = AttachmentStore::new(dir.path().join("attachments"));
+
+        let contact = Contact::new(
+            "Alice",
+            "08123456789",
+            "alice@work.com",
+            vec![],
+            Utc::now(),
+            Utc::now(),
+        );
+        let id = contact.id;
+        store.insert(contact).unwrap();
+
+        let mut hashes = Vec::new();
+        for image in [b"old photo".as_slice(), b"new photo"] {
+            modify_contacts(&store, &history, ChangeSource::Cli, |contacts| {
+                let photo = attachments.put("photo.jpg", image)?;
+                hashes.push(photo.hash.clone());
+                contacts.set_photo(id, Some(photo)).map(|_| ())
+            })
+            .unwrap();
+        }
+        let stray = attachments.put("stray.txt", b"nobody").unwrap();
+
+        // The replaced photo is still in the history, so undo can restore it
+        collect_attachments(&store, &history, &attachments).unwrap();
+        assert!(attachments.get(&stray.hash).is_err());
+        for hash in &hashes {
+            assert!(attachments.get(hash).is_ok());
+        }
+    }
 }
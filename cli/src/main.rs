@@ -1,4 +1,5 @@
 use rolodex_cli::cli::run_command_cli;
+use rolodex_core::error::AppError;
 
 // #[tokio::main]
 // pub async fn main() {
@@ -11,6 +12,9 @@ use rolodex_cli::cli::run_command_cli;
 pub fn main() {
     if let Err(err) = run_command_cli() {
         eprintln!("Error: {}", err);
+        if let AppError::Locked(_) = err {
+            eprintln!("Hint: retry with --wait <SECONDS> to wait for the other process");
+        }
         std::process::exit(1);
     }
 }
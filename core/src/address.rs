@@ -0,0 +1,139 @@
+use std::{fmt, str::FromStr};
+
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum AddressLabel {
+    #[default]
+    Home,
+    Work,
+    Other,
+}
+
+impl fmt::Display for AddressLabel {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let label = match self {
+            AddressLabel::Home => "home",
+            AddressLabel::Work => "work",
+            AddressLabel::Other => "other",
+        };
+        write!(f, "{}", label)
+    }
+}
+
+impl FromStr for AddressLabel {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_lowercase().as_str() {
+            "home" => Ok(AddressLabel::Home),
+            "work" => Ok(AddressLabel::Work),
+            "other" => Ok(AddressLabel::Other),
+            _ => Err(format!(
+                "Unknown address label '{}'. Use: home | work | other",
+                s
+            )),
+        }
+    }
+}
+
+/// A postal address; every part is optional.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Address {
+    #[serde(default)]
+    pub label: AddressLabel,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub street: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub city: Option<String>,
+    /// State, province or county.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub region: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub postal_code: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub country: Option<String>,
+}
+
+/// Separates the parts of an address in a single CSV cell.
+const CELL_SEPARATOR: char = '|';
+
+impl Address {
+    fn parts(&self) -> [&Option<String>; 5] {
+        [
+            &self.street,
+            &self.city,
+            &self.region,
+            &self.postal_code,
+            &self.country,
+        ]
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.parts().iter().all(|p| p.is_none())
+    }
+
+    pub fn in_city(&self, city: &str) -> bool {
+        self.city
+            .as_deref()
+            .is_some_and(|c| c.trim().eq_ignore_ascii_case(city.trim()))
+    }
+
+    /// `street|city|region|postal_code|country`, with empty slots for missing parts.
+    pub fn to_cell(&self) -> String {
+        self.parts()
+            .map(|p| p.as_deref().unwrap_or_default())
+            .join(&CELL_SEPARATOR.to_string())
+    }
+
+    pub fn from_cell(cell: &str, label: AddressLabel) -> Self {
+        let mut parts = cell
+            .split(CELL_SEPARATOR)
+            .map(|p| Some(p.trim().to_string()).filter(|p| !p.is_empty()));
+        let mut next = || parts.next().flatten();
+
+        Self {
+            label,
+            street: next(),
+            city: next(),
+            region: next(),
+            postal_code: next(),
+            country: next(),
+        }
+    }
+}
+
+impl fmt::Display for Address {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let line = self
+            .parts()
+            .iter()
+            .filter_map(|p| p.as_deref())
+            .collect::<Vec<_>>()
+            .join(", ");
+        write!(f, "{} ({})", line, self.label)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_address_cell_round_trip() {
+        let address = Address {
+            label: AddressLabel::Work,
+            street: Some("1 Infinite Loop".to_string()),
+            city: Some("Cupertino".to_string()),
+            country: Some("US".to_string()),
+            ..Default::default()
+        };
+
+        let cell = address.to_cell();
+        assert_eq!(cell, "1 Infinite Loop|Cupertino|||US");
+        assert_eq!(Address::from_cell(&cell, AddressLabel::Work), address);
+        assert!(address.in_city("cupertino"));
+        assert!(Address::from_cell("||||", AddressLabel::Home).is_empty());
+    }
+}
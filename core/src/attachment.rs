@@ -0,0 +1,216 @@
+//! Photos and small files kept in a content-addressed directory: each file is
+//! stored once under its SHA-256 hash and contacts refer to it by that hash.
+
+use std::{
+    collections::HashSet,
+    fs,
+    path::{Path, PathBuf},
+};
+
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::{domain::Contact, error::AppError, store::write_atomic};
+
+/// Larger files belong in a document store, not next to `contacts.json`.
+pub const MAX_ATTACHMENT_SIZE: usize = 10 * 1024 * 1024;
+
+/// A file attached to a contact; the bytes live in an `AttachmentStore`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Attachment {
+    /// Hex SHA-256 of the content.
+    pub hash: String,
+    /// File name it was attached as.
+    pub name: String,
+    pub media_type: String,
+    pub size: u64,
+}
+
+/// `attachments/ab/abcdef…`: one file per distinct content, sharded by the
+/// first two hex digits.
+pub struct AttachmentStore {
+    dir: PathBuf,
+}
+
+impl AttachmentStore {
+    pub fn new(dir: impl AsRef<Path>) -> Self {
+        Self {
+            dir: dir.as_ref().to_path_buf(),
+        }
+    }
+
+    /// The `attachments` directory next to a contacts file.
+    pub fn next_to(contacts_path: impl AsRef<Path>) -> Self {
+        let parent = contacts_path
+            .as_ref()
+            .parent()
+            .unwrap_or_else(|| Path::new(""));
+        Self::new(parent.join("attachments"))
+    }
+
+    /// Stores `bytes` (once per distinct content) and describes them.
+    pub fn put(&self, name: &str, bytes: &[u8]) -> Result<Attachment, AppError> {
+        if bytes.is_empty() {
+            return Err(AppError::Validation("Attachment is empty".to_string()));
+        }
+        if bytes.len() > MAX_ATTACHMENT_SIZE {
+            return Err(AppError::Validation(format!(
+                "Attachment is {} bytes, the limit is {}",
+                bytes.len(),
+                MAX_ATTACHMENT_SIZE
+            )));
+        }
+
+        let hash = hash(bytes);
+        let path = self.path(&hash)?;
+        if !path.exists() {
+            fs::create_dir_all(path.parent().unwrap_or(&self.dir))?;
+            write_atomic(&path, bytes)?;
+        }
+
+        Ok(Attachment {
+            media_type: media_type(name, bytes).to_string(),
+            name: name.to_string(),
+            size: bytes.len() as u64,
+            hash,
+        })
+    }
+
+    pub fn get(&self, hash: &str) -> Result<Vec<u8>, AppError> {
+        let path = self.path(hash)?;
+        fs::read(&path).map_err(|e| match e.kind() {
+            std::io::ErrorKind::NotFound => AppError::Parse(format!(
+                "Attachment {} is missing from {}",
+                hash,
+                self.dir.display()
+            )),
+            _ => e.into(),
+        })
+    }
+
+    /// Where the content with `hash` is kept; rejects anything but a hex hash
+    /// so a hash can never name a path outside the directory.
+    pub fn path(&self, hash: &str) -> Result<PathBuf, AppError> {
+        if hash.len() != 64 || !hash.bytes().all(|b| b.is_ascii_hexdigit()) {
+            return Err(AppError::Parse(format!("Not an attachment hash: {}", hash)));
+        }
+        let hash = hash.to_ascii_lowercase();
+        Ok(self.dir.join(&hash[..2]).join(hash))
+    }
+
+    /// Deletes every stored file no contact in `contacts` refers to, trashed
+    /// ones included, and returns the removed hashes.
+    pub fn collect_garbage<'a>(
+        &self,
+        contacts: impl IntoIterator<Item = &'a Contact>,
+    ) -> Result<Vec<String>, AppError> {
+        let referenced: HashSet<&str> = contacts
+            .into_iter()
+            .flat_map(|c| c.attachment_hashes())
+            .collect();
+
+        let mut removed = Vec::new();
+        if !self.dir.exists() {
+            return Ok(removed);
+        }
+        for shard in fs::read_dir(&self.dir)? {
+            let shard = shard?.path();
+            if !shard.is_dir() {
+                continue;
+            }
+            for file in fs::read_dir(&shard)? {
+                let file = file?.path();
+                let Some(name) = file.file_name().and_then(|n| n.to_str()) else {
+                    continue;
+                };
+                // Skips half-written `.tmp` files of a concurrent `put`
+                if self.path(name).is_err() || referenced.contains(name) {
+                    continue;
+                }
+                fs::remove_file(&file)?;
+                removed.push(name.to_string());
+            }
+            // Only succeeds once the shard is empty
+            let _ = fs::remove_dir(&shard);
+        }
+        removed.sort_unstable();
+        Ok(removed)
+    }
+}
+
+/// Hex SHA-256 of `bytes`.
+pub fn hash(bytes: &[u8]) -> String {
+    Sha256::digest(bytes)
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect()
+}
+
+/// Sniffs common image and document formats, falling back to the extension.
+pub fn media_type(name: &str, bytes: &[u8]) -> &'static str {
+    match bytes {
+        [0x89, b'P', b'N', b'G', ..] => return "image/png",
+        [0xFF, 0xD8, 0xFF, ..] => return "image/jpeg",
+        [b'G', b'I', b'F', b'8', ..] => return "image/gif",
+        [b'%', b'P', b'D', b'F', ..] => return "application/pdf",
+        _ if bytes.starts_with(b"RIFF") && bytes.get(8..12) == Some(b"WEBP") => return "image/webp",
+        _ => {}
+    }
+    let extension = Path::new(name)
+        .extension()
+        .and_then(|e| e.to_str())
+        .unwrap_or_default()
+        .to_lowercase();
+    match extension.as_str() {
+        "png" => "image/png",
+        "jpg" | "jpeg" => "image/jpeg",
+        "gif" => "image/gif",
+        "webp" => "image/webp",
+        "pdf" => "application/pdf",
+        "txt" => "text/plain",
+        _ => "application/octet-stream",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::Utc;
+
+    use super::*;
+
+    #[test]
+    fn test_put_dedupes_and_garbage_is_collected() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = AttachmentStore::new(dir.path().join("attachments"));
+
+        let card = store.put("card.pdf", b"%PDF-1.4 card").unwrap();
+        let again = store.put("copy.pdf", b"%PDF-1.4 card").unwrap();
+        let photo = store.put("me.png", b"\x89PNG\r\n").unwrap();
+        assert_eq!(card.hash, again.hash);
+        assert_eq!(card.media_type, "application/pdf");
+        assert_eq!(photo.media_type, "image/png");
+        assert_eq!(store.get(&card.hash).unwrap(), b"%PDF-1.4 card");
+        assert!(store.get("../contacts.json").is_err());
+        assert!(store.put("empty", b"").is_err());
+
+        let mut contact = Contact::new(
+            "Alice",
+            "08123456789",
+            "alice@work.com",
+            vec![],
+            Utc::now(),
+            Utc::now(),
+        );
+        contact.photo = Some(photo.clone());
+        assert_eq!(
+            store.collect_garbage([&contact]).unwrap(),
+            vec![card.hash.clone()]
+        );
+        assert!(store.get(&card.hash).is_err());
+        assert!(store.get(&photo.hash).is_ok());
+
+        contact.photo = None;
+        assert_eq!(store.collect_garbage([&contact]).unwrap(), vec![photo.hash]);
+        assert!(store.collect_garbage([&contact]).unwrap().is_empty());
+    }
+}
@@ -0,0 +1,825 @@
+//! How a CSV file maps onto contacts: which column holds which field, the
+//! delimiter, the separator for lists inside one cell and the date formats.
+//! Built-in presets cover our own layout and Google and Outlook exports;
+//! anything else can be described in a TOML or JSON file.
+
+use std::{collections::BTreeMap, fmt, fs, path::Path, str::FromStr};
+
+use chrono::{DateTime, Datelike, NaiveDate, SecondsFormat, Utc};
+use csv::StringRecord;
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::{
+    address::{Address, AddressLabel},
+    dates::{LabelledDate, PartialDate},
+    domain::Contact,
+    email::{self, Email, EmailLabel},
+    error::AppError,
+    phone::{Phone, PhoneLabel},
+    relation::Relation,
+    tags,
+};
+
+/// A contact field a column can hold.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CsvField {
+    Id,
+    Name,
+    Prefix,
+    GivenName,
+    MiddleName,
+    FamilyName,
+    Suffix,
+    Nickname,
+    Phone,
+    Email,
+    Organization,
+    Title,
+    Department,
+    /// Whole addresses, `street|city|region|postal_code|country` each.
+    Addresses,
+    Street,
+    City,
+    Region,
+    PostalCode,
+    Country,
+    Notes,
+    /// `key=value` pairs.
+    CustomFields,
+    Birthday,
+    Anniversary,
+    /// `label=date` pairs.
+    Dates,
+    /// `kind:id` pairs.
+    Relations,
+    Tags,
+    /// Names of the contact's groups; only written with groups, never read.
+    Groups,
+    CreatedAt,
+    UpdatedAt,
+    /// Written empty and skipped on import.
+    Ignore,
+}
+
+impl CsvField {
+    /// Fields whose cell may hold several values.
+    fn is_list(self) -> bool {
+        matches!(
+            self,
+            CsvField::Phone
+                | CsvField::Email
+                | CsvField::Addresses
+                | CsvField::CustomFields
+                | CsvField::Dates
+                | CsvField::Relations
+                | CsvField::Tags
+                | CsvField::Groups
+        )
+    }
+}
+
+/// One column of the file.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CsvColumn {
+    /// Header as it appears in the file; matched case-insensitively.
+    pub column: String,
+    pub field: CsvField,
+    /// Label of the phones, emails or address parts in this column, e.g.
+    /// `work` for Outlook's "Business Phone". Without one, list cells carry
+    /// their labels as `label:value`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub label: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CsvProfile {
+    #[serde(default = "default_delimiter")]
+    pub delimiter: char,
+    /// Separates the values of a list field inside one cell.
+    #[serde(default = "default_list_separator")]
+    pub list_separator: String,
+    /// `chrono` formats tried when a date is not ISO (`1990-05-17`,
+    /// `--05-17`); the first one is used on export.
+    #[serde(default)]
+    pub date_formats: Vec<String>,
+    /// Tag values left out on import, such as Google's `* myContacts`.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub skip_tags: Vec<String>,
+    pub columns: Vec<CsvColumn>,
+}
+
+fn default_delimiter() -> char {
+    ','
+}
+
+fn default_list_separator() -> String {
+    ";".to_string()
+}
+
+fn column(name: &str, field: CsvField) -> CsvColumn {
+    CsvColumn {
+        column: name.to_string(),
+        field,
+        label: None,
+    }
+}
+
+fn labelled(name: &str, field: CsvField, label: &str) -> CsvColumn {
+    CsvColumn {
+        label: Some(label.to_string()),
+        ..column(name, field)
+    }
+}
+
+/// Our own layout, as written by `export-csv`; every column but `name` may
+/// be missing on import.
+impl Default for CsvProfile {
+    fn default() -> Self {
+        use CsvField::*;
+        let columns = [
+            ("id", Id),
+            ("name", Name),
+            ("prefix", Prefix),
+            ("given_name", GivenName),
+            ("middle_name", MiddleName),
+            ("family_name", FamilyName),
+            ("suffix", Suffix),
+            ("nickname", Nickname),
+            ("phone", Phone),
+            ("email", Email),
+            ("organization", Organization),
+            ("title", Title),
+            ("department", Department),
+            ("addresses", Addresses),
+            ("notes", Notes),
+            ("custom_fields", CustomFields),
+            ("birthday", Birthday),
+            ("anniversary", Anniversary),
+            ("dates", Dates),
+            ("relations", Relations),
+            ("tags", Tags),
+            ("groups", Groups),
+            ("created_at", CreatedAt),
+            ("updated_at", UpdatedAt),
+        ];
+        Self {
+            delimiter: default_delimiter(),
+            list_separator: default_list_separator(),
+            date_formats: vec![],
+            skip_tags: vec![],
+            columns: columns
+                .into_iter()
+                .map(|(name, field)| column(name, field))
+                .collect(),
+        }
+    }
+}
+
+impl CsvProfile {
+    /// `rolodex` (the default), `google` or `outlook`.
+    pub fn preset(name: &str) -> Option<Self> {
+        use CsvField::*;
+        match name.trim().to_lowercase().as_str() {
+            "rolodex" | "default" => Some(Self::default()),
+            // Google Contacts "Google CSV" export
+            "google" => Some(Self {
+                delimiter: ',',
+                list_separator: " ::: ".to_string(),
+                date_formats: vec!["%Y-%m-%d".to_string()],
+                skip_tags: vec!["* myContacts".to_string(), "* starred".to_string()],
+                columns: vec![
+                    column("First Name", GivenName),
+                    column("Middle Name", MiddleName),
+                    column("Last Name", FamilyName),
+                    column("Name Prefix", Prefix),
+                    column("Name Suffix", Suffix),
+                    column("Nickname", Nickname),
+                    column("Organization Name", Organization),
+                    column("Organization Title", Title),
+                    column("Organization Department", Department),
+                    column("Birthday", Birthday),
+                    column("Notes", Notes),
+                    column("Labels", Tags),
+                    column("E-mail 1 - Value", Email),
+                    column("E-mail 2 - Value", Email),
+                    column("Phone 1 - Value", Phone),
+                    column("Phone 2 - Value", Phone),
+                    column("Address 1 - Street", Street),
+                    column("Address 1 - City", City),
+                    column("Address 1 - Region", Region),
+                    column("Address 1 - Postal Code", PostalCode),
+                    column("Address 1 - Country", Country),
+                ],
+            }),
+            // Outlook "Comma Separated Values" export
+            "outlook" => Some(Self {
+                delimiter: ',',
+                list_separator: ";".to_string(),
+                date_formats: vec!["%m/%d/%Y".to_string()],
+                skip_tags: vec![],
+                columns: vec![
+                    column("Title", Prefix),
+                    column("First Name", GivenName),
+                    column("Middle Name", MiddleName),
+                    column("Last Name", FamilyName),
+                    column("Suffix", Suffix),
+                    column("Nickname", Nickname),
+                    column("Company", Organization),
+                    column("Department", Department),
+                    column("Job Title", Title),
+                    labelled("Business Street", Street, "work"),
+                    labelled("Business City", City, "work"),
+                    labelled("Business State", Region, "work"),
+                    labelled("Business Postal Code", PostalCode, "work"),
+                    labelled("Business Country/Region", Country, "work"),
+                    labelled("Home Street", Street, "home"),
+                    labelled("Home City", City, "home"),
+                    labelled("Home State", Region, "home"),
+                    labelled("Home Postal Code", PostalCode, "home"),
+                    labelled("Home Country/Region", Country, "home"),
+                    labelled("Business Fax", Phone, "fax"),
+                    labelled("Business Phone", Phone, "work"),
+                    labelled("Home Phone", Phone, "home"),
+                    labelled("Mobile Phone", Phone, "mobile"),
+                    labelled("Other Phone", Phone, "other"),
+                    column("E-mail Address", Email),
+                    column("E-mail 2 Address", Email),
+                    column("E-mail 3 Address", Email),
+                    column("Birthday", Birthday),
+                    column("Anniversary", Anniversary),
+                    column("Notes", Notes),
+                    column("Categories", Tags),
+                ],
+            }),
+            _ => None,
+        }
+    }
+
+    /// A preset name, or the path of a `.toml` or `.json` profile.
+    pub fn load(name_or_path: &str) -> Result<Self, AppError> {
+        if let Some(profile) = Self::preset(name_or_path) {
+            return Ok(profile);
+        }
+        let path = Path::new(name_or_path);
+        if !path.exists() {
+            return Err(AppError::Parse(format!(
+                "No CSV profile '{}'. Use: rolodex | google | outlook | a .toml or .json file",
+                name_or_path
+            )));
+        }
+        let data = fs::read_to_string(path)?;
+        let profile: Self = match path.extension().and_then(|e| e.to_str()) {
+            Some("toml") => toml::from_str(&data).map_err(|e| AppError::Parse(e.to_string()))?,
+            _ => serde_json::from_str(&data).map_err(|e| AppError::Parse(e.to_string()))?,
+        };
+        profile.validate()?;
+        Ok(profile)
+    }
+
+    fn validate(&self) -> Result<(), AppError> {
+        if !self.delimiter.is_ascii() {
+            return Err(AppError::Validation(format!(
+                "CSV delimiter '{}' must be a single ASCII character",
+                self.delimiter
+            )));
+        }
+        if self.list_separator.is_empty() {
+            return Err(AppError::Validation("List separator is empty".to_string()));
+        }
+        if self.columns.is_empty() {
+            return Err(AppError::Validation(
+                "CSV profile has no columns".to_string(),
+            ));
+        }
+        for column in &self.columns {
+            let Some(label) = &column.label else {
+                continue;
+            };
+            let valid = match column.field {
+                CsvField::Phone => label.parse::<PhoneLabel>().map(|_| ()),
+                CsvField::Email => label.parse::<EmailLabel>().map(|_| ()),
+                CsvField::Addresses
+                | CsvField::Street
+                | CsvField::City
+                | CsvField::Region
+                | CsvField::PostalCode
+                | CsvField::Country => label.parse::<AddressLabel>().map(|_| ()),
+                _ => Err(format!("{:?} columns take no label", column.field)),
+            };
+            valid
+                .map_err(|e| AppError::Validation(format!("Column '{}': {}", column.column, e)))?;
+        }
+        Ok(())
+    }
+
+    pub fn delimiter(&self) -> u8 {
+        self.delimiter as u8
+    }
+
+    pub fn header(&self) -> Vec<&str> {
+        self.columns.iter().map(|c| c.column.as_str()).collect()
+    }
+
+    /// Pairs each mapped column with its position in `headers`; columns of
+    /// the file the profile doesn't know are skipped.
+    pub fn bind(&self, headers: &StringRecord) -> Result<Vec<(usize, &CsvColumn)>, AppError> {
+        let bound: Vec<(usize, &CsvColumn)> = headers
+            .iter()
+            .enumerate()
+            .filter_map(|(i, header)| {
+                self.columns
+                    .iter()
+                    .find(|c| c.column.eq_ignore_ascii_case(header.trim()))
+                    .map(|c| (i, c))
+            })
+            .collect();
+        if bound.is_empty() {
+            return Err(AppError::Parse(
+                "None of the CSV columns match the profile".to_string(),
+            ));
+        }
+        Ok(bound)
+    }
+
+    /// Builds a contact from one row, using the columns from `bind`.
+    pub fn read_contact(
+        &self,
+        bound: &[(usize, &CsvColumn)],
+        record: &StringRecord,
+    ) -> Result<Contact, AppError> {
+        let mut contact = Contact {
+            id: Uuid::new_v4(),
+            created_at: Utc::now(),
+            updated_at: Utc::now(),
+            ..Default::default()
+        };
+        // Address parts are collected per label
+        let mut addresses: BTreeMap<String, Address> = BTreeMap::new();
+
+        for (i, column) in bound {
+            let cell = record.get(*i).unwrap_or_default().trim();
+            if cell.is_empty() {
+                continue;
+            }
+            let text = || Some(cell.to_string());
+            let bad = |e: &dyn fmt::Display| {
+                AppError::Parse(format!("Column '{}': {}", column.column, e))
+            };
+            match column.field {
+                CsvField::Id => contact.id = cell.parse().map_err(|e| bad(&e))?,
+                CsvField::Name => contact.name = cell.to_string(),
+                CsvField::Prefix => contact.prefix = text(),
+                CsvField::GivenName => contact.given_name = text(),
+                CsvField::MiddleName => contact.middle_name = text(),
+                CsvField::FamilyName => contact.family_name = text(),
+                CsvField::Suffix => contact.suffix = text(),
+                CsvField::Nickname => contact.nickname = text(),
+                CsvField::Organization => contact.organization = text(),
+                CsvField::Title => contact.title = text(),
+                CsvField::Department => contact.department = text(),
+                CsvField::Notes => {
+                    contact.notes = match contact.notes.take() {
+                        Some(notes) => Some(format!("{}\n{}", notes, cell)),
+                        None => text(),
+                    }
+                }
+                CsvField::Phone => {
+                    for item in self.split(cell) {
+                        let (label, raw) = cell_label(column, item);
+                        contact.phone.push(Phone::with_label(raw, label));
+                    }
+                }
+                CsvField::Email => {
+                    for item in self.split(cell) {
+                        let (label, address) = cell_label(column, item);
+                        contact.email.push(Email::with_label(address, label));
+                    }
+                }
+                CsvField::Addresses => {
+                    for item in self.split(cell) {
+                        let (label, parts) = cell_label::<AddressLabel>(column, item);
+                        let address = Address::from_cell(parts, label);
+                        if !address.is_empty() {
+                            contact.addresses.push(address);
+                        }
+                    }
+                }
+                CsvField::Street
+                | CsvField::City
+                | CsvField::Region
+                | CsvField::PostalCode
+                | CsvField::Country => {
+                    let label = column.label.clone().unwrap_or_default();
+                    let address = addresses.entry(label).or_insert_with(|| Address {
+                        label: column
+                            .label
+                            .as_deref()
+                            .and_then(|l| l.parse().ok())
+                            .unwrap_or_default(),
+                        ..Default::default()
+                    });
+                    let part = match column.field {
+                        CsvField::Street => &mut address.street,
+                        CsvField::City => &mut address.city,
+                        CsvField::Region => &mut address.region,
+                        CsvField::PostalCode => &mut address.postal_code,
+                        _ => &mut address.country,
+                    };
+                    *part = text();
+                }
+                CsvField::CustomFields => {
+                    let items = self.split_escaped(cell);
+                    for (key, value) in items.iter().filter_map(|item| item.split_once('=')) {
+                        contact
+                            .custom_fields
+                            .insert(key.trim().to_string(), value.trim().to_string());
+                    }
+                }
+                CsvField::Birthday => {
+                    contact.birthday = self.parse_date(cell).map_err(|e| bad(&e))?
+                }
+                CsvField::Anniversary => {
+                    contact.anniversary = self.parse_date(cell).map_err(|e| bad(&e))?
+                }
+                CsvField::Dates => contact
+                    .dates
+                    .extend(self.split(cell).filter_map(|item| item.parse().ok())),
+                CsvField::Relations => contact.relations.extend(
+                    self.split(cell)
+                        .filter_map(|item| Relation::from_cell(item).ok()),
+                ),
+                CsvField::Tags => contact.tags.extend(
+                    self.split(cell)
+                        .filter(|tag| !self.skip_tags.iter().any(|s| s == tag))
+                        .map(String::from),
+                ),
+                CsvField::CreatedAt => {
+                    contact.created_at = self.parse_timestamp(cell).map_err(|e| bad(&e))?
+                }
+                CsvField::UpdatedAt => {
+                    contact.updated_at = self.parse_timestamp(cell).map_err(|e| bad(&e))?
+                }
+                CsvField::Groups | CsvField::Ignore => {}
+            }
+        }
+
+        contact
+            .addresses
+            .extend(addresses.into_values().filter(|a| !a.is_empty()));
+        contact.tags = tags::normalize_all(&contact.tags);
+        contact.normalize_name();
+        if contact.name.trim().is_empty() {
+            return Err(AppError::Validation("Row has no name".to_string()));
+        }
+        email::ensure_primary(&mut contact.email);
+        Ok(contact)
+    }
+
+    /// One row for `contact`, in `header` order. `groups` fills group columns.
+    pub fn write_contact(&self, contact: &Contact, groups: &[&str]) -> Vec<String> {
+        self.columns
+            .iter()
+            .map(|column| {
+                let text = |value: &Option<String>| value.clone().unwrap_or_default();
+                match column.field {
+                    CsvField::Id => contact.id.to_string(),
+                    CsvField::Name => contact.name.clone(),
+                    CsvField::Prefix => text(&contact.prefix),
+                    CsvField::GivenName => text(&contact.given_name),
+                    CsvField::MiddleName => text(&contact.middle_name),
+                    CsvField::FamilyName => text(&contact.family_name),
+                    CsvField::Suffix => text(&contact.suffix),
+                    CsvField::Nickname => text(&contact.nickname),
+                    CsvField::Organization => text(&contact.organization),
+                    CsvField::Title => text(&contact.title),
+                    CsvField::Department => text(&contact.department),
+                    CsvField::Notes => text(&contact.notes),
+                    CsvField::Phone => {
+                        let items = contact
+                            .phone
+                            .iter()
+                            .filter(|p| label_matches(column, p.label))
+                            .map(|p| labelled_cell(column, p.label, &p.raw));
+                        self.list_cell(column, items)
+                    }
+                    // Primary address first; that's how it's recognised on import
+                    CsvField::Email => {
+                        let items = contact
+                            .email
+                            .iter()
+                            .filter(|e| e.primary)
+                            .chain(contact.email.iter().filter(|e| !e.primary))
+                            .filter(|e| label_matches(column, e.label))
+                            .map(|e| labelled_cell(column, e.label, &e.address));
+                        self.list_cell(column, items)
+                    }
+                    CsvField::Addresses => {
+                        let items = contact
+                            .addresses
+                            .iter()
+                            .filter(|a| label_matches(column, a.label))
+                            .map(|a| labelled_cell(column, a.label, &a.to_cell()));
+                        self.list_cell(column, items)
+                    }
+                    CsvField::Street
+                    | CsvField::City
+                    | CsvField::Region
+                    | CsvField::PostalCode
+                    | CsvField::Country => {
+                        let address = contact
+                            .addresses
+                            .iter()
+                            .find(|a| label_matches(column, a.label));
+                        let part = address.and_then(|a| match column.field {
+                            CsvField::Street => a.street.clone(),
+                            CsvField::City => a.city.clone(),
+                            CsvField::Region => a.region.clone(),
+                            CsvField::PostalCode => a.postal_code.clone(),
+                            _ => a.country.clone(),
+                        });
+                        part.unwrap_or_default()
+                    }
+                    CsvField::CustomFields => self.list_cell(
+                        column,
+                        contact
+                            .custom_fields
+                            .iter()
+                            .map(|(k, v)| self.escape(&format!("{}={}", k, v))),
+                    ),
+                    CsvField::Birthday => self.format_date(contact.birthday),
+                    CsvField::Anniversary => self.format_date(contact.anniversary),
+                    CsvField::Dates => {
+                        self.list_cell(column, contact.dates.iter().map(LabelledDate::to_string))
+                    }
+                    CsvField::Relations => {
+                        self.list_cell(column, contact.relations.iter().map(Relation::to_cell))
+                    }
+                    CsvField::Tags => self.list_cell(column, contact.tags.iter().cloned()),
+                    CsvField::Groups => {
+                        self.list_cell(column, groups.iter().map(|g| g.to_string()))
+                    }
+                    CsvField::CreatedAt => contact
+                        .created_at
+                        .to_rfc3339_opts(SecondsFormat::AutoSi, true),
+                    CsvField::UpdatedAt => contact
+                        .updated_at
+                        .to_rfc3339_opts(SecondsFormat::AutoSi, true),
+                    CsvField::Ignore => String::new(),
+                }
+            })
+            .collect()
+    }
+
+    fn split<'a>(&'a self, cell: &'a str) -> impl Iterator<Item = &'a str> {
+        cell.split(self.list_separator.as_str())
+            .map(str::trim)
+            .filter(|v| !v.is_empty())
+    }
+
+    /// Free text such as custom field values may hold the separator itself;
+    /// it is written as `\` plus the separator, and `\` as `\\`.
+    fn escape(&self, item: &str) -> String {
+        item.replace('\\', "\\\\")
+            .replace(&self.list_separator, &format!("\\{}", self.list_separator))
+    }
+
+    /// `split` for cells written with `escape`.
+    fn split_escaped(&self, cell: &str) -> Vec<String> {
+        let mut items = Vec::new();
+        let mut item = String::new();
+        let mut rest = cell;
+        while let Some(c) = rest.chars().next() {
+            if c == '\\' && rest.len() > 1 {
+                let next = rest[1..].chars().next().unwrap_or_default();
+                item.push(next);
+                rest = &rest[1 + next.len_utf8()..];
+            } else if let Some(after) = rest.strip_prefix(self.list_separator.as_str()) {
+                items.push(std::mem::take(&mut item));
+                rest = after;
+            } else {
+                item.push(c);
+                rest = &rest[c.len_utf8()..];
+            }
+        }
+        items.push(item);
+        items
+            .into_iter()
+            .map(|v| v.trim().to_string())
+            .filter(|v| !v.is_empty())
+            .collect()
+    }
+
+    /// Several columns may share a list field (Outlook's three e-mail
+    /// columns): each takes one value and the last one takes the rest.
+    fn list_cell(&self, column: &CsvColumn, items: impl Iterator<Item = String>) -> String {
+        let siblings: Vec<&CsvColumn> = self
+            .columns
+            .iter()
+            .filter(|c| c.field == column.field && c.label == column.label)
+            .collect();
+        let position = siblings
+            .iter()
+            .position(|c| std::ptr::eq(*c, column))
+            .unwrap_or(0);
+        debug_assert!(column.field.is_list());
+
+        let items: Vec<String> = items.collect();
+        if position + 1 < siblings.len() {
+            items.get(position).cloned().unwrap_or_default()
+        } else {
+            items
+                .get(position..)
+                .unwrap_or_default()
+                .join(&self.list_separator)
+        }
+    }
+
+    /// ISO dates first, then `date_formats`. All-zero dates such as
+    /// Outlook's `0/0/00` mean no date.
+    fn parse_date(&self, cell: &str) -> Result<Option<PartialDate>, AppError> {
+        if cell.chars().all(|c| c == '0' || !c.is_ascii_alphanumeric()) {
+            return Ok(None);
+        }
+        if let Ok(date) = cell.parse() {
+            return Ok(Some(date));
+        }
+        self.date_formats
+            .iter()
+            .find_map(|format| NaiveDate::parse_from_str(cell, format).ok())
+            .map(|date| {
+                Some(PartialDate {
+                    year: Some(date.year()),
+                    month: date.month(),
+                    day: date.day(),
+                })
+            })
+            .ok_or(AppError::Parse(format!("Unrecognised date '{}'", cell)))
+    }
+
+    fn format_date(&self, date: Option<PartialDate>) -> String {
+        let Some(date) = date else {
+            return String::new();
+        };
+        let full = date
+            .year
+            .and_then(|year| NaiveDate::from_ymd_opt(year, date.month, date.day));
+        match (full, self.date_formats.first()) {
+            (Some(full), Some(format)) => full.format(format).to_string(),
+            _ => date.to_string(),
+        }
+    }
+
+    fn parse_timestamp(&self, cell: &str) -> Result<DateTime<Utc>, AppError> {
+        DateTime::parse_from_rfc3339(cell)
+            .map(|t| t.with_timezone(&Utc))
+            .ok()
+            .or_else(|| {
+                self.date_formats.iter().find_map(|format| {
+                    NaiveDate::parse_from_str(cell, format)
+                        .ok()
+                        .and_then(|d| d.and_hms_opt(0, 0, 0))
+                        .map(|t| t.and_utc())
+                })
+            })
+            .ok_or(AppError::Parse(format!(
+                "Unrecognised timestamp '{}'",
+                cell
+            )))
+    }
+}
+
+/// The column's own label, or one written as `label:value` in the cell; a
+/// prefix that isn't a known label is kept as part of the value.
+fn cell_label<'a, L: FromStr + Default>(column: &CsvColumn, cell: &'a str) -> (L, &'a str) {
+    if let Some(label) = column.label.as_deref().and_then(|l| l.parse().ok()) {
+        return (label, cell);
+    }
+    cell.split_once(':')
+        .and_then(|(label, value)| Some((label.parse().ok()?, value)))
+        .unwrap_or((L::default(), cell))
+}
+
+/// Values carry a non-default label as `label:value`, unless the column
+/// already names it.
+fn labelled_cell<L: fmt::Display + Default + PartialEq>(
+    column: &CsvColumn,
+    label: L,
+    value: &str,
+) -> String {
+    if column.label.is_some() || label == L::default() {
+        value.to_string()
+    } else {
+        format!("{}:{}", label, value)
+    }
+}
+
+fn label_matches<L: FromStr + PartialEq>(column: &CsvColumn, label: L) -> bool {
+    column
+        .label
+        .as_deref()
+        .is_none_or(|l| l.parse::<L>().is_ok_and(|l| l == label))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_outlook_columns_round_trip() {
+        let profile = CsvProfile::preset("outlook").unwrap();
+        let headers = StringRecord::from(profile.header());
+        let bound = profile.bind(&headers).unwrap();
+
+        let header = profile.header();
+        let row = header.iter().map(|h| match *h {
+            "First Name" => "Grace",
+            "Last Name" => "Hopper",
+            "Mobile Phone" => "+1-202-555-0147",
+            "Business Phone" => "+1-202-555-0100",
+            "E-mail Address" => "grace@navy.mil",
+            "E-mail 2 Address" => "grace@home.org",
+            "Business City" => "Arlington",
+            "Birthday" => "12/9/1906",
+            "Anniversary" => "0/0/00",
+            "Categories" => "work;navy",
+            _ => "",
+        });
+        let contact = profile
+            .read_contact(&bound, &StringRecord::from_iter(row))
+            .unwrap();
+
+        assert_eq!(contact.name, "Grace Hopper");
+        assert_eq!(contact.phone[0].label, PhoneLabel::Work);
+        assert_eq!(contact.phone[1].label, PhoneLabel::Mobile);
+        assert_eq!(contact.primary_email(), "grace@navy.mil");
+        assert_eq!(contact.addresses[0].label, AddressLabel::Work);
+        assert_eq!(contact.birthday, Some("1906-12-09".parse().unwrap()));
+        assert_eq!(contact.anniversary, None);
+        assert_eq!(contact.tags, vec!["work", "navy"]);
+
+        let written = profile.write_contact(&contact, &[]);
+        let cell = |name: &str| {
+            let i = profile.header().iter().position(|h| *h == name).unwrap();
+            written[i].clone()
+        };
+        assert_eq!(cell("Mobile Phone"), "+1-202-555-0147");
+        assert_eq!(cell("E-mail 2 Address"), "grace@home.org");
+        assert_eq!(cell("E-mail 3 Address"), "");
+        assert_eq!(cell("Business City"), "Arlington");
+        assert_eq!(cell("Home City"), "");
+        assert_eq!(cell("Birthday"), "12/09/1906");
+    }
+
+    #[test]
+    fn test_custom_fields_keep_the_separator() {
+        let profile = CsvProfile::preset("rolodex").unwrap();
+        let headers = StringRecord::from(profile.header());
+        let bound = profile.bind(&headers).unwrap();
+        let mut contact = Contact::new(
+            "Ada Lovelace",
+            "08123456789",
+            "ada@work.com",
+            vec![],
+            Utc::now(),
+            Utc::now(),
+        );
+        let value = format!("a{}b \\ c", profile.list_separator);
+        contact.custom_fields.insert("Gate code".to_string(), value);
+        contact
+            .custom_fields
+            .insert("Shoe size".to_string(), "38".to_string());
+
+        let written = profile.write_contact(&contact, &[]);
+        let back = profile
+            .read_contact(&bound, &StringRecord::from(written))
+            .unwrap();
+        assert_eq!(back.custom_fields, contact.custom_fields);
+    }
+
+    #[test]
+    fn test_profile_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("crm.toml");
+        fs::write(
+            &path,
+            "delimiter = \"|\"\nlist_separator = \",\"\n\n\
+             [[columns]]\ncolumn = \"Full Name\"\nfield = \"name\"\n\n\
+             [[columns]]\ncolumn = \"Cell\"\nfield = \"phone\"\nlabel = \"mobile\"\n",
+        )
+        .unwrap();
+        let profile = CsvProfile::load(path.to_str().unwrap()).unwrap();
+        assert_eq!(profile.delimiter(), b'|');
+        assert_eq!(profile.columns[1].field, CsvField::Phone);
+
+        let path = dir.path().join("bad.json");
+        fs::write(
+            &path,
+            r#"{"columns": [{"column": "Cell", "field": "phone", "label": "pager"}]}"#,
+        )
+        .unwrap();
+        assert!(CsvProfile::load(path.to_str().unwrap()).is_err());
+        assert!(CsvProfile::load("thunderbird").is_err());
+    }
+}
@@ -0,0 +1,171 @@
+use std::{fmt, str::FromStr};
+
+use chrono::{Datelike, NaiveDate};
+use serde::{Deserialize, Serialize};
+
+use crate::error::AppError;
+
+/// A calendar date whose year may be unknown, written `1990-05-17` or, without
+/// a year, `--05-17` (the vCard form).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct PartialDate {
+    pub year: Option<i32>,
+    pub month: u32,
+    pub day: u32,
+}
+
+impl PartialDate {
+    /// The next time this date comes round on or after `today`. Feb 29 falls
+    /// on Feb 28 in other years.
+    pub fn next_occurrence(&self, today: NaiveDate) -> NaiveDate {
+        let this_year = self.in_year(today.year());
+        if this_year >= today {
+            this_year
+        } else {
+            self.in_year(today.year() + 1)
+        }
+    }
+
+    fn in_year(&self, year: i32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(year, self.month, self.day)
+            .or_else(|| NaiveDate::from_ymd_opt(year, self.month, self.day - 1))
+            .unwrap_or_default()
+    }
+
+    /// Years since the date on `on`, when the year is known.
+    pub fn years_on(&self, on: NaiveDate) -> Option<i32> {
+        self.year.map(|y| on.year() - y)
+    }
+}
+
+impl FromStr for PartialDate {
+    type Err = AppError;
+
+    /// Accepts `YYYY-MM-DD`, `--MM-DD` and `MM-DD`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        let invalid = || {
+            AppError::Validation(format!(
+                "Invalid date '{}', expected YYYY-MM-DD or --MM-DD",
+                s
+            ))
+        };
+
+        if let Ok(date) = NaiveDate::parse_from_str(s, "%Y-%m-%d") {
+            return Ok(Self {
+                year: Some(date.year()),
+                month: date.month(),
+                day: date.day(),
+            });
+        }
+
+        let (month, day) = s
+            .trim_start_matches("--")
+            .split_once('-')
+            .ok_or_else(invalid)?;
+        let month: u32 = month.parse().map_err(|_| invalid())?;
+        let day: u32 = day.parse().map_err(|_| invalid())?;
+        // 2000 is a leap year, so Feb 29 is accepted
+        NaiveDate::from_ymd_opt(2000, month, day).ok_or_else(invalid)?;
+
+        Ok(Self {
+            year: None,
+            month,
+            day,
+        })
+    }
+}
+
+impl fmt::Display for PartialDate {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.year {
+            Some(year) => write!(f, "{:04}-{:02}-{:02}", year, self.month, self.day),
+            None => write!(f, "--{:02}-{:02}", self.month, self.day),
+        }
+    }
+}
+
+impl TryFrom<String> for PartialDate {
+    type Error = AppError;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        value.parse()
+    }
+}
+
+impl From<PartialDate> for String {
+    fn from(date: PartialDate) -> Self {
+        date.to_string()
+    }
+}
+
+/// Any other recurring date worth remembering, e.g. a work anniversary.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LabelledDate {
+    pub label: String,
+    pub date: PartialDate,
+}
+
+impl FromStr for LabelledDate {
+    type Err = AppError;
+
+    /// Parses `label=date`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (label, date) = s.split_once('=').ok_or(AppError::Validation(format!(
+            "Expected LABEL=DATE, got '{}'",
+            s
+        )))?;
+        let label = label.trim();
+        if label.is_empty() {
+            return Err(AppError::Validation("Date label is empty".to_string()));
+        }
+        Ok(Self {
+            label: label.to_string(),
+            date: date.parse()?,
+        })
+    }
+}
+
+impl fmt::Display for LabelledDate {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}={}", self.label, self.date)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn day(s: &str) -> NaiveDate {
+        NaiveDate::parse_from_str(s, "%Y-%m-%d").unwrap()
+    }
+
+    #[test]
+    fn test_partial_dates_parse_and_recur() {
+        let birthday: PartialDate = "1990-05-17".parse().unwrap();
+        assert_eq!(
+            birthday.next_occurrence(day("2026-05-17")),
+            day("2026-05-17")
+        );
+        assert_eq!(
+            birthday.next_occurrence(day("2026-05-18")),
+            day("2027-05-17")
+        );
+        assert_eq!(birthday.years_on(day("2026-05-17")), Some(36));
+
+        let no_year: PartialDate = "--12-31".parse().unwrap();
+        assert_eq!(no_year.year, None);
+        assert_eq!(no_year.to_string(), "--12-31");
+        assert_eq!("12-31".parse::<PartialDate>().unwrap(), no_year);
+
+        let leap: PartialDate = "--02-29".parse().unwrap();
+        assert_eq!(leap.next_occurrence(day("2026-01-01")), day("2026-02-28"));
+
+        assert!("--02-30".parse::<PartialDate>().is_err());
+        assert!("someday".parse::<PartialDate>().is_err());
+
+        let json = serde_json::to_string(&no_year).unwrap();
+        assert_eq!(serde_json::from_str::<PartialDate>(&json).unwrap(), no_year);
+    }
+}
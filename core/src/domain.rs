@@ -5,23 +5,36 @@ use uuid::Uuid;
 // use crate::{domain::Contact, prelude::AppError};
 
 use std::{
-    collections::{HashMap, HashSet},
+    collections::{BTreeMap, HashMap, HashSet},
+    fmt,
     fs::File,
     sync::{Arc, Mutex},
     thread,
 };
 
-use chrono::{DateTime, Utc};
-use csv::{ReaderBuilder, Writer};
+use chrono::{DateTime, NaiveDate, Utc};
+use csv::{ReaderBuilder, StringRecord, WriterBuilder};
 use dotenv::dotenv;
 use fuzzy_search::distance::levenshtein;
 use reqwest::{blocking::Client, header::CONTENT_TYPE};
 
 use crate::{
+    address::Address,
+    attachment::Attachment,
+    csv_profile::{CsvColumn, CsvProfile},
+    dates::{LabelledDate, PartialDate},
+    email::{self, Email, format_emails},
     error::AppError,
+    group::{self, Group},
     helpers::{get_key, merge_contact_data, resolve_conflict},
+    history::{ChangeRecord, ChangeSource},
+    migrations,
+    phone::{Phone, format_phones},
+    relation::{self, Relation, RelationKind},
     store::MergePolicy,
-    validation::ValidationResponse,
+    stream::{self, ContactWriter, Format, OnContact},
+    tags,
+    validation::{ValidationResponse, contact_problems},
 };
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -57,32 +70,71 @@ impl AppState {
     }
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct ContactRaw {
-    pub id: String,
-    pub name: String,
-    pub phone: Vec<String>,
-    pub email: String,
-    #[serde(default)]
-    pub tags: Vec<String>,
-    #[serde(default)]
-    pub created_at: DateTime<Utc>,
-    #[serde(default)]
-    pub updated_at: DateTime<Utc>,
-}
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct Contact {
     #[serde(default = "Uuid::new_v4")]
     pub id: Uuid,
+    /// Display name; kept in step with the name parts below.
     pub name: String,
-    pub phone: Vec<String>,
-    pub email: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub prefix: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub given_name: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub middle_name: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub family_name: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub suffix: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub nickname: Option<String>,
+    pub phone: Vec<Phone>,
+    /// One entry is `primary`; a bare address string is read as that entry.
+    #[serde(default, deserialize_with = "email::deserialize_emails")]
+    pub email: Vec<Email>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub addresses: Vec<Address>,
+    /// Company or other organization the contact works for.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub organization: Option<String>,
+    /// Job title.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub title: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub department: Option<String>,
+    /// Free-form text; `note add` appends a line.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub notes: Option<String>,
+    /// Open key/value pairs such as account numbers or a timezone.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub custom_fields: BTreeMap<String, String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub birthday: Option<PartialDate>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub anniversary: Option<PartialDate>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub dates: Vec<LabelledDate>,
+    /// Links to other contacts; see `Contacts::link`.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub relations: Vec<Relation>,
+    /// Avatar image; the bytes live in the `AttachmentStore`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub photo: Option<Attachment>,
+    /// Business card scans, PDFs and other small files.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub attachments: Vec<Attachment>,
     #[serde(default)]
     pub tags: Vec<String>,
     #[serde(default)]
     pub created_at: DateTime<Utc>,
     #[serde(default)]
     pub updated_at: DateTime<Utc>,
+    /// Bumped on every change; used for optimistic concurrency (`If-Match`).
+    #[serde(default)]
+    pub revision: u64,
+    /// Tombstone: set when the contact is moved to the trash, cleared on restore.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub deleted_at: Option<DateTime<Utc>>,
 }
 
 impl Contact {
@@ -94,18 +146,229 @@ impl Contact {
         created_at: DateTime<Utc>,
         updated_at: DateTime<Utc>,
     ) -> Self {
-        Self {
+        let mut contact = Self {
             id: Uuid::new_v4(),
-            name: name.to_string(),
-            phone: vec![phone.to_string()],
-            email: email.to_string(),
-            tags,
+            phone: vec![Phone::new(phone)],
+            email: vec![],
+            tags: tags::normalize_all(&tags),
             created_at,
             updated_at,
+            ..Default::default()
+        };
+        contact.set_name(name);
+        contact.set_primary_email(Email::new(email));
+        contact
+    }
+
+    /// Address of the primary email, or `""` when the contact has none.
+    pub fn primary_email(&self) -> &str {
+        self.email
+            .iter()
+            .find(|e| e.primary)
+            .or(self.email.first())
+            .map_or("", |e| e.address.as_str())
+    }
+
+    /// Makes `email` the primary address, replacing the current primary one.
+    /// An empty address just drops the current primary.
+    pub fn set_primary_email(&mut self, email: Email) {
+        if let Some(i) = self.email.iter().position(|e| e.primary) {
+            self.email.remove(i);
+        }
+        self.email.retain(|e| *e != email);
+        if !email.address.is_empty() {
+            self.email.insert(0, email);
+        }
+        email::ensure_primary(&mut self.email);
+    }
+
+    /// Makes `email`, already one of the contact's addresses, the primary one;
+    /// the old primary stays as a secondary address.
+    pub fn promote_email(&mut self, email: &Email) {
+        if let Some(i) = self.email.iter().position(|e| e == email) {
+            let mut promoted = self.email.remove(i);
+            promoted.primary = true;
+            for other in &mut self.email {
+                other.primary = false;
+            }
+            self.email.insert(0, promoted);
+        }
+    }
+
+    /// `Title, Department, Organization`, leaving out the missing parts.
+    pub fn job_line(&self) -> Option<String> {
+        let parts: Vec<&str> = [&self.title, &self.department, &self.organization]
+            .into_iter()
+            .filter_map(|p| p.as_deref())
+            .collect();
+        (!parts.is_empty()).then(|| parts.join(", "))
+    }
+
+    pub fn works_at(&self, company: &str) -> bool {
+        let company = company.trim().to_lowercase();
+        self.organization
+            .as_ref()
+            .is_some_and(|o| o.to_lowercase().contains(&company))
+    }
+
+    pub fn in_city(&self, city: &str) -> bool {
+        self.addresses.iter().any(|a| a.in_city(city))
+    }
+
+    pub fn add_note(&mut self, text: &str) {
+        let text = text.trim();
+        self.notes = match self.notes.take().filter(|n| !n.is_empty()) {
+            Some(notes) => Some(format!("{}\n{}", notes, text)),
+            None => Some(text.to_string()),
+        };
+    }
+
+    pub fn notes_contain(&self, text: &str) -> bool {
+        let text = text.trim().to_lowercase();
+        self.notes
+            .as_ref()
+            .is_some_and(|n| n.to_lowercase().contains(&text))
+    }
+
+    /// Sets each field; an empty value removes the key.
+    pub fn set_custom_fields(&mut self, fields: &BTreeMap<String, String>) {
+        for (key, value) in fields {
+            if value.is_empty() {
+                self.custom_fields.remove(key);
+            } else {
+                self.custom_fields.insert(key.clone(), value.clone());
+            }
         }
     }
 
-    // for migrating from txt -> JSON
+    /// Adds a labelled date, replacing one with the same label.
+    pub fn set_date(&mut self, date: LabelledDate) {
+        match self
+            .dates
+            .iter_mut()
+            .find(|d| d.label.eq_ignore_ascii_case(&date.label))
+        {
+            Some(existing) => *existing = date,
+            None => self.dates.push(date),
+        }
+    }
+
+    /// One `kind: name` line per relation. `name_of` resolves a live contact's
+    /// name; links to missing or trashed contacts are flagged.
+    pub fn relation_lines(&self, name_of: impl Fn(Uuid) -> Option<String>) -> Vec<String> {
+        self.relations
+            .iter()
+            .map(|r| match name_of(r.contact_id) {
+                Some(name) => format!("{}: {}", r.kind, name),
+                None => format!("{}: {} ⚠️ missing or deleted", r.kind, r.contact_id),
+            })
+            .collect()
+    }
+
+    /// Birthday, anniversary and labelled dates, each with its label.
+    pub fn all_dates(&self) -> Vec<(&str, PartialDate)> {
+        let mut dates = Vec::new();
+        if let Some(birthday) = self.birthday {
+            dates.push(("birthday", birthday));
+        }
+        if let Some(anniversary) = self.anniversary {
+            dates.push(("anniversary", anniversary));
+        }
+        dates.extend(self.dates.iter().map(|d| (d.label.as_str(), d.date)));
+        dates
+    }
+
+    /// Lowercased domains of every address.
+    pub fn email_domains(&self) -> Vec<String> {
+        self.email.iter().filter_map(Email::domain).collect()
+    }
+
+    /// Sets the display name and splits it into name parts.
+    pub fn set_name(&mut self, name: &str) {
+        let mut words: Vec<&str> = name.split_whitespace().collect();
+
+        self.prefix = None;
+        self.suffix = None;
+        if words.len() > 1 && NAME_PREFIXES.contains(&name_word_key(words[0]).as_str()) {
+            self.prefix = Some(words.remove(0).to_string());
+        }
+        if words.len() > 1
+            && NAME_SUFFIXES.contains(&name_word_key(words[words.len() - 1]).as_str())
+        {
+            self.suffix = words.pop().map(String::from);
+            if let Some(last) = words.last_mut() {
+                *last = last.trim_end_matches(',');
+            }
+        }
+
+        self.given_name = words.first().map(|w| w.to_string());
+        self.family_name = (words.len() > 1).then(|| words[words.len() - 1].to_string());
+        self.middle_name = (words.len() > 2).then(|| words[1..words.len() - 1].join(" "));
+        self.name = name.trim().to_string();
+    }
+
+    /// Name built from the parts, falling back to `name` when there are none.
+    pub fn display_name(&self) -> String {
+        let parts: Vec<&str> = [
+            &self.prefix,
+            &self.given_name,
+            &self.middle_name,
+            &self.family_name,
+            &self.suffix,
+        ]
+        .into_iter()
+        .flatten()
+        .map(|p| p.trim())
+        .filter(|p| !p.is_empty())
+        .collect();
+
+        if self.given_name.is_none() && self.family_name.is_none() {
+            self.name.clone()
+        } else {
+            parts.join(" ")
+        }
+    }
+
+    /// Recomputes `name` after the parts were edited directly (API payloads,
+    /// CSV rows); splits `name` instead when no parts are set.
+    pub fn normalize_name(&mut self) {
+        if self.given_name.is_some() || self.family_name.is_some() {
+            self.name = self.display_name();
+        } else {
+            let name = self.name.clone();
+            self.set_name(&name);
+        }
+    }
+
+    /// Sort key putting the family name first ("Smith John").
+    pub fn family_sort_key(&self) -> String {
+        let given = self.given_name.as_deref().unwrap_or(&self.name);
+        match &self.family_name {
+            Some(family) => format!("{} {}", family, given).to_lowercase(),
+            None => given.to_lowercase(),
+        }
+    }
+
+    /// Lowercased given name, family name and nickname, for `ContactsIndex`.
+    pub fn name_part_keys(&self) -> Vec<String> {
+        [&self.given_name, &self.family_name, &self.nickname]
+            .into_iter()
+            .flatten()
+            .map(|p| p.trim().to_lowercase())
+            .filter(|p| !p.is_empty())
+            .collect()
+    }
+
+    pub fn initials(&self) -> String {
+        [&self.given_name, &self.family_name]
+            .into_iter()
+            .flatten()
+            .filter_map(|p| p.chars().next())
+            .flat_map(char::to_uppercase)
+            .collect()
+    }
+
+    /// Parses a `name,phone,email` line of the old `contacts.txt` format.
     pub fn from_line(line: &str) -> Result<Self, AppError> {
         let parts: Vec<&str> = line.split(",").collect();
         if parts.len() != 3 {
@@ -121,25 +384,190 @@ impl Contact {
         ))
     }
 
+    /// True for the tag itself and for any of its children (`work` matches
+    /// `work/project-x`).
     pub fn has_tag(&self, tag: &str) -> bool {
-        self.tags.iter().any(|t| t == tag)
+        self.tags.iter().any(|t| tags::matches(t, tag))
+    }
+
+    /// Hashes of the photo and every attachment.
+    pub fn attachment_hashes(&self) -> impl Iterator<Item = &str> {
+        self.photo
+            .iter()
+            .chain(&self.attachments)
+            .map(|a| a.hash.as_str())
+    }
+
+    /// Every tag together with its parents, as indexed by `ContactsIndex`.
+    pub fn tag_keys(&self) -> HashSet<&str> {
+        self.tags.iter().flat_map(|t| tags::ancestors(t)).collect()
+    }
+
+    /// Points tags under `old` at `new`; returns whether any changed.
+    pub fn rename_tag(&mut self, old: &str, new: &str) -> bool {
+        let mut renamed = false;
+        let mut result: Vec<String> = Vec::with_capacity(self.tags.len());
+        for tag in &self.tags {
+            let tag = match tags::rename(tag, old, new) {
+                Some(new_tag) => {
+                    renamed = true;
+                    new_tag
+                }
+                None => tag.clone(),
+            };
+            if !result.contains(&tag) {
+                result.push(tag);
+            }
+        }
+        self.tags = result;
+        renamed
     }
 
     pub fn has_domain(&self, domain: &str) -> bool {
-        self.email.ends_with(&format!("@{}", domain))
+        let domain = domain.to_lowercase();
+        self.email_domains().contains(&domain)
+    }
+
+    pub fn is_deleted(&self) -> bool {
+        self.deleted_at.is_some()
     }
-    pub fn from(raw: ContactRaw) -> Self {
-        let id = Uuid::parse_str(&raw.id).unwrap_or_else(|_| Uuid::new_v4());
 
-        Contact {
-            id,
-            name: raw.name,
-            phone: raw.phone,
-            email: raw.email,
-            tags: raw.tags,
-            created_at: raw.created_at,
-            updated_at: raw.updated_at,
+    /// Moves the contact to the trash.
+    pub fn trash(&mut self, at: DateTime<Utc>) {
+        self.deleted_at = Some(at);
+        self.updated_at = Utc::now();
+        self.revision += 1;
+    }
+
+    pub fn restore(&mut self) {
+        self.deleted_at = None;
+        self.updated_at = Utc::now();
+        self.revision += 1;
+    }
+}
+
+const NAME_PREFIXES: &[&str] = &["mr", "mrs", "ms", "miss", "mx", "dr", "prof", "sir", "rev"];
+const NAME_SUFFIXES: &[&str] = &["jr", "sr", "ii", "iii", "iv", "phd", "md", "esq"];
+
+fn name_word_key(word: &str) -> String {
+    word.trim_matches(|c: char| c == '.' || c == ',')
+        .to_lowercase()
+}
+
+/// Partial update for a stored contact; `None` leaves the field untouched.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ContactPatch {
+    pub name: Option<String>,
+    pub phone: Option<Vec<Phone>>,
+    pub email: Option<Vec<Email>>,
+    pub addresses: Option<Vec<Address>>,
+    pub organization: Option<String>,
+    pub title: Option<String>,
+    pub department: Option<String>,
+    pub notes: Option<String>,
+    /// Merged into the stored fields; an empty value removes the key.
+    pub custom_fields: Option<BTreeMap<String, String>>,
+    pub birthday: Option<PartialDate>,
+    pub anniversary: Option<PartialDate>,
+    /// Added to the stored dates, replacing any with the same label.
+    pub dates: Option<Vec<LabelledDate>>,
+    pub tags: Option<Vec<String>>,
+    /// Reject the update unless the stored contact is still at this revision.
+    #[serde(skip)]
+    pub expected_revision: Option<u64>,
+}
+
+impl ContactPatch {
+    pub fn check_revision(&self, contact: &Contact) -> Result<(), AppError> {
+        match self.expected_revision {
+            Some(expected) if expected != contact.revision => Err(AppError::Conflict(format!(
+                "Contact {} is at revision {}, not {}",
+                contact.id, contact.revision, expected
+            ))),
+            _ => Ok(()),
+        }
+    }
+
+    pub fn apply(&self, contact: &mut Contact) {
+        if let Some(name) = &self.name {
+            contact.set_name(name);
+        }
+        if let Some(phone) = &self.phone {
+            contact.phone = phone.clone();
+        }
+        if let Some(emails) = &self.email {
+            contact.email = emails.clone();
+            email::ensure_primary(&mut contact.email);
+        }
+        if let Some(addresses) = &self.addresses {
+            contact.addresses = addresses.clone();
+        }
+        if let Some(organization) = &self.organization {
+            contact.organization = Some(organization.clone());
+        }
+        if let Some(title) = &self.title {
+            contact.title = Some(title.clone());
         }
+        if let Some(department) = &self.department {
+            contact.department = Some(department.clone());
+        }
+        if let Some(notes) = &self.notes {
+            contact.notes = Some(notes.clone());
+        }
+        if let Some(fields) = &self.custom_fields {
+            contact.set_custom_fields(fields);
+        }
+        if let Some(birthday) = self.birthday {
+            contact.birthday = Some(birthday);
+        }
+        if let Some(anniversary) = self.anniversary {
+            contact.anniversary = Some(anniversary);
+        }
+        for date in self.dates.iter().flatten() {
+            contact.set_date(date.clone());
+        }
+        if let Some(tags) = &self.tags {
+            contact.tags = tags::normalize_all(tags);
+        }
+        contact.updated_at = Utc::now();
+        contact.revision += 1;
+    }
+}
+
+/// Criteria for `ContactStore::list`. Empty fields match every contact.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ContactFilter {
+    pub name: Option<String>,
+    pub tag: Option<String>,
+    pub domain: Option<String>,
+    /// Any format; compared by normalized number.
+    pub phone: Option<String>,
+    /// Case-insensitive substring of the organization.
+    pub company: Option<String>,
+    pub city: Option<String>,
+    /// Case-insensitive substring of the notes.
+    pub note: Option<String>,
+    /// List the trash instead of live contacts.
+    #[serde(default)]
+    pub trashed: bool,
+}
+
+impl ContactFilter {
+    pub fn matches(&self, contact: &Contact) -> bool {
+        contact.is_deleted() == self.trashed
+            && self.name.as_ref().is_none_or(|n| {
+                let n = n.trim().to_lowercase();
+                contact.name.to_lowercase() == n || contact.name_part_keys().contains(&n)
+            })
+            && self.tag.as_ref().is_none_or(|t| contact.has_tag(t))
+            && self.domain.as_ref().is_none_or(|d| contact.has_domain(d))
+            && self.phone.as_ref().is_none_or(|p| {
+                let wanted = Phone::new(p);
+                contact.phone.contains(&wanted)
+            })
+            && self.company.as_ref().is_none_or(|c| contact.works_at(c))
+            && self.city.as_ref().is_none_or(|c| contact.in_city(c))
+            && self.note.as_ref().is_none_or(|n| contact.notes_contain(n))
     }
 }
 
@@ -167,6 +595,11 @@ pub struct SpecialContact {
 pub struct Contacts {
     pub items: HashMap<Uuid, Contact>,
     pub index: ContactsIndex,
+    /// Changes not yet written to the history log; see `take_changes`.
+    changes: Vec<ChangeRecord>,
+    source: ChangeSource,
+    /// Set between `begin_batch` and `end_batch` so every change shares one operation.
+    batch: Option<Uuid>,
 }
 
 #[derive(serde::Deserialize, Debug)]
@@ -196,9 +629,37 @@ impl Contacts {
         Self {
             items,
             index: build_index,
+            changes: Vec::new(),
+            source: ChangeSource::Cli,
+            batch: None,
         }
     }
 
+    pub fn with_source(mut self, source: ChangeSource) -> Self {
+        self.source = source;
+        self
+    }
+
+    /// Groups the following changes into one undoable operation.
+    pub fn begin_batch(&mut self) {
+        self.batch = Some(Uuid::new_v4());
+    }
+
+    pub fn end_batch(&mut self) {
+        self.batch = None;
+    }
+
+    /// Drains the changes recorded since the last call.
+    pub fn take_changes(&mut self) -> Vec<ChangeRecord> {
+        std::mem::take(&mut self.changes)
+    }
+
+    fn record(&mut self, before: Option<Contact>, after: Option<Contact>) {
+        let operation = self.batch.unwrap_or_else(Uuid::new_v4);
+        self.changes
+            .push(ChangeRecord::new(operation, self.source, before, after));
+    }
+
     pub fn iter(&'_ self) -> ContactsIter<'_> {
         ContactsIter {
             inner: self.items.values(),
@@ -213,10 +674,26 @@ impl Contacts {
             .or_default()
             .insert(contact.id);
 
-        if let Some(domain) = contact.email.split("@").nth(1) {
+        for key in contact.name_part_keys() {
+            self.index
+                .name_part_map
+                .entry(key)
+                .or_default()
+                .insert(contact.id);
+        }
+
+        for domain in contact.email_domains() {
             self.index
                 .domain_map
-                .entry(domain.to_lowercase())
+                .entry(domain)
+                .or_default()
+                .insert(contact.id);
+        }
+
+        for tag in contact.tag_keys() {
+            self.index
+                .tag_map
+                .entry(tag.to_string())
                 .or_default()
                 .insert(contact.id);
         }
@@ -229,15 +706,31 @@ impl Contacts {
             set.remove(&contact.id);
         }
 
-        if let Some(domain) = contact.email.split("@").nth(1)
-            && let Some(set) = self.index.domain_map.get_mut(&domain.to_lowercase())
-        {
-            set.remove(&contact.id);
+        for key in contact.name_part_keys() {
+            if let Some(set) = self.index.name_part_map.get_mut(&key) {
+                set.remove(&contact.id);
+            }
+        }
+
+        for domain in contact.email_domains() {
+            if let Some(set) = self.index.domain_map.get_mut(&domain) {
+                set.remove(&contact.id);
+            }
+        }
+
+        for tag in contact.tag_keys() {
+            if let Some(set) = self.index.tag_map.get_mut(tag) {
+                set.remove(&contact.id);
+            }
         }
 
         let _ = &self.index.name_map.retain(|_key, set| !set.is_empty());
 
+        let _ = &self.index.name_part_map.retain(|_key, set| !set.is_empty());
+
         let _ = &self.index.domain_map.retain(|_key, set| !set.is_empty());
+
+        let _ = &self.index.tag_map.retain(|_key, set| !set.is_empty());
     }
 
     pub fn update_index(&mut self, id: Uuid) {
@@ -249,11 +742,25 @@ impl Contacts {
             .or_default()
             .insert(id);
 
-        let domain = contact.email.split("@").nth(1).unwrap().to_string();
-        self.index.domain_map.entry(domain).or_default().insert(id);
+        for key in contact.name_part_keys() {
+            self.index.name_part_map.entry(key).or_default().insert(id);
+        }
+
+        for domain in contact.email_domains() {
+            self.index.domain_map.entry(domain).or_default().insert(id);
+        }
+
+        for tag in contact.tag_keys() {
+            self.index
+                .tag_map
+                .entry(tag.to_string())
+                .or_default()
+                .insert(id);
+        }
     }
 
-    pub fn find_with_name_phone(&self, name: &str, phone: &[String]) -> Option<Uuid> {
+    /// Matches phones by their normalized number, so formatting differences don't matter.
+    pub fn find_with_name_phone(&self, name: &str, phone: &[Phone]) -> Option<Uuid> {
         let imported_phone_set: HashSet<_> = phone.iter().collect();
 
         let name_key = name.trim().to_lowercase();
@@ -271,7 +778,7 @@ impl Contacts {
         })
     }
 
-    pub fn add(&mut self, mut contact: Contact) -> Result<(), AppError> {
+    pub fn add(&mut self, mut contact: Contact) -> Result<Uuid, AppError> {
         if self.check_contact_exist(&contact) {
             return Err(AppError::Validation(
                 "Contact with info already exists".to_string(),
@@ -282,29 +789,320 @@ impl Contacts {
 
         self.items.insert(contact.id, contact.clone());
         self.add_index(&contact);
+        self.record(None, Some(contact.clone()));
 
         println!("Name index after: {:?}", self.index.name_map);
         println!("Domain index after: {:?}", self.index.domain_map);
-        Ok(())
+        Ok(contact.id)
     }
 
+    /// Stores `contact` as-is, without the duplicate check done by `add`.
+    pub fn insert(&mut self, contact: Contact) {
+        self.items.insert(contact.id, contact.clone());
+        self.add_index(&contact);
+        self.record(None, Some(contact));
+    }
+
+    /// Moves a contact to the trash; it stays in `items` as a tombstone so the
+    /// deletion can be synced, restored or purged later.
     pub fn delete(&mut self, id: Uuid) -> Result<(), AppError> {
-        let contact = self
+        let before = self
             .items
-            .remove(&id)
-            .ok_or(AppError::Parse("No contact found".to_string()))?;
-        self.remove_index(&contact);
+            .get(&id)
+            .filter(|c| !c.is_deleted())
+            .cloned()
+            .ok_or(AppError::NotFound("No contact found".to_string()))?;
+        self.remove_index(&before);
+
+        // The trashing and the dropped links are undone together
+        let own_batch = self.batch.is_none();
+        if own_batch {
+            self.begin_batch();
+        }
+
+        let mut contact = before.clone();
+        contact.trash(Utc::now());
+        self.items.insert(id, contact.clone());
+        self.index.add_tombstone(&contact);
+        self.record(Some(before), Some(contact));
+        self.drop_links_to(id);
+
+        if own_batch {
+            self.end_batch();
+        }
 
         println!("Name index after: {:?}", self.index.name_map);
         println!("Domain index after: {:?}", self.index.domain_map);
 
         Ok(())
     }
+
+    /// Removes every live contact's links to `target`.
+    fn drop_links_to(&mut self, target: Uuid) {
+        let linked: Vec<Contact> = self
+            .items
+            .values()
+            .filter(|c| !c.is_deleted() && c.relations.iter().any(|r| r.contact_id == target))
+            .cloned()
+            .collect();
+
+        for before in linked {
+            let mut contact = before.clone();
+            contact.relations.retain(|r| r.contact_id != target);
+            contact.updated_at = Utc::now();
+            contact.revision += 1;
+            println!("Unlinked {} from the deleted contact", contact.name);
+            self.items.insert(contact.id, contact.clone());
+            self.record(Some(before), Some(contact));
+        }
+    }
+
+    /// Runs `change` on a copy of live contact `id`; if it succeeds, the copy
+    /// becomes the next revision and the change is recorded. For edits that
+    /// leave the indexed fields (names, emails, tags) alone.
+    pub fn edit<T>(
+        &mut self,
+        id: Uuid,
+        change: impl FnOnce(&mut Contact) -> Result<T, AppError>,
+    ) -> Result<T, AppError> {
+        let before = self
+            .items
+            .get(&id)
+            .filter(|c| !c.is_deleted())
+            .cloned()
+            .ok_or(AppError::NotFound("Contact not found".to_string()))?;
+
+        let mut contact = before.clone();
+        let result = change(&mut contact)?;
+        contact.updated_at = Utc::now();
+        contact.revision += 1;
+        self.items.insert(id, contact.clone());
+        self.record(Some(before), Some(contact));
+        Ok(result)
+    }
+
+    /// Records that `to` is `id`'s `kind`, e.g. their manager.
+    pub fn link(&mut self, id: Uuid, kind: RelationKind, to: Uuid) -> Result<(), AppError> {
+        if id == to {
+            return Err(AppError::Validation(
+                "A contact cannot be linked to itself".to_string(),
+            ));
+        }
+        if self.items.get(&to).is_none_or(|c| c.is_deleted()) {
+            return Err(AppError::NotFound(format!(
+                "No contact found with id {}",
+                to
+            )));
+        }
+        let relation = Relation {
+            kind,
+            contact_id: to,
+        };
+        self.edit(id, |contact| {
+            if contact.relations.contains(&relation) {
+                return Err(AppError::Validation(format!(
+                    "{} is already linked as {}",
+                    to, kind
+                )));
+            }
+            contact.relations.push(relation);
+            Ok(())
+        })
+    }
+
+    /// Removes every link from `id` to `to`.
+    pub fn unlink(&mut self, id: Uuid, to: Uuid) -> Result<(), AppError> {
+        self.edit(id, |contact| {
+            if !contact.relations.iter().any(|r| r.contact_id == to) {
+                return Err(AppError::Validation(format!(
+                    "{} is not linked to {}",
+                    contact.name, to
+                )));
+            }
+            contact.relations.retain(|r| r.contact_id != to);
+            Ok(())
+        })
+    }
+
+    /// Sets or clears `id`'s photo; returns the one it replaced.
+    pub fn set_photo(
+        &mut self,
+        id: Uuid,
+        photo: Option<Attachment>,
+    ) -> Result<Option<Attachment>, AppError> {
+        self.edit(id, |contact| {
+            if contact.photo == photo {
+                return Err(AppError::Validation(format!(
+                    "{} already has this photo",
+                    contact.name
+                )));
+            }
+            Ok(std::mem::replace(&mut contact.photo, photo))
+        })
+    }
+
+    /// Adds a file to `id`, unless the same content is already attached.
+    pub fn attach(&mut self, id: Uuid, attachment: Attachment) -> Result<(), AppError> {
+        self.edit(id, |contact| {
+            if let Some(existing) = contact
+                .attachments
+                .iter()
+                .find(|a| a.hash == attachment.hash)
+            {
+                return Err(AppError::Validation(format!(
+                    "{} already has this file as {}",
+                    contact.name, existing.name
+                )));
+            }
+            contact.attachments.push(attachment);
+            Ok(())
+        })
+    }
+
+    /// Removes the attachment whose hash or file name is `key`.
+    pub fn detach(&mut self, id: Uuid, key: &str) -> Result<Attachment, AppError> {
+        self.edit(id, |contact| {
+            let position = contact
+                .attachments
+                .iter()
+                .position(|a| a.hash.eq_ignore_ascii_case(key) || a.name == key)
+                .ok_or(AppError::Parse(format!(
+                    "{} has no attachment {}",
+                    contact.name, key
+                )))?;
+            Ok(contact.attachments.remove(position))
+        })
+    }
+
+    /// Renames `old` and its children to `new` on every live contact, e.g.
+    /// `work` -> `job` turns `work/project-x` into `job/project-x`. Trashed
+    /// contacts keep their tags. Returns how many contacts changed.
+    pub fn rename_tag(&mut self, old: &str, new: &str) -> Result<usize, AppError> {
+        let (old, new) = (tags::normalize(old), tags::normalize(new));
+        if !self.index.lookup_tag(&new).is_empty() {
+            return Err(AppError::Validation(format!(
+                "Tag '{}' is already in use; merge the tags instead",
+                new
+            )));
+        }
+        self.retag(&old, &new)
+    }
+
+    /// Moves every live contact tagged `from` (or a child of it) onto `into`,
+    /// which must already be in use. Returns how many contacts changed.
+    pub fn merge_tags(&mut self, from: &str, into: &str) -> Result<usize, AppError> {
+        let (from, into) = (tags::normalize(from), tags::normalize(into));
+        if self.index.lookup_tag(&into).is_empty() {
+            return Err(AppError::NotFound(format!(
+                "No contact is tagged '{}'",
+                into
+            )));
+        }
+        self.retag(&from, &into)
+    }
+
+    fn retag(&mut self, old: &str, new: &str) -> Result<usize, AppError> {
+        if new.is_empty() {
+            return Err(AppError::Validation("Tag is empty".to_string()));
+        }
+        if old == new || tags::matches(new, old) {
+            return Err(AppError::Validation(format!(
+                "Cannot move tag '{}' onto '{}'",
+                old, new
+            )));
+        }
+        let ids = self.index.lookup_tag(old);
+        if ids.is_empty() {
+            return Err(AppError::NotFound(format!(
+                "No contact is tagged '{}'",
+                old
+            )));
+        }
+
+        // Every rewritten contact is undone together
+        let own_batch = self.batch.is_none();
+        if own_batch {
+            self.begin_batch();
+        }
+
+        let mut changed = 0;
+        for id in &ids {
+            let Some(before) = self.items.get(id).cloned() else {
+                continue;
+            };
+            let mut contact = before.clone();
+            if !contact.rename_tag(old, new) {
+                continue;
+            }
+            contact.updated_at = Utc::now();
+            contact.revision += 1;
+            self.remove_index(&before);
+            self.items.insert(*id, contact.clone());
+            self.add_index(&contact);
+            self.record(Some(before), Some(contact));
+            changed += 1;
+        }
+
+        if own_batch {
+            self.end_batch();
+        }
+
+        Ok(changed)
+    }
+
+    pub fn restore(&mut self, id: Uuid) -> Result<(), AppError> {
+        let before = self
+            .items
+            .get(&id)
+            .filter(|c| c.is_deleted())
+            .cloned()
+            .ok_or(AppError::NotFound(
+                "No such contact in the trash".to_string(),
+            ))?;
+
+        let mut contact = before.clone();
+        contact.restore();
+        self.index.remove_tombstone(&before);
+        self.items.insert(id, contact.clone());
+        self.add_index(&contact);
+        self.record(Some(before), Some(contact));
+        Ok(())
+    }
+
+    pub fn add_note(&mut self, id: Uuid, text: &str) -> Result<(), AppError> {
+        if text.trim().is_empty() {
+            return Err(AppError::Validation("Note text is empty".to_string()));
+        }
+        self.edit(id, |contact| {
+            contact.add_note(text);
+            Ok(())
+        })
+    }
+
+    /// Permanently removes contacts trashed before `cutoff`; returns their ids.
+    pub fn purge(&mut self, cutoff: DateTime<Utc>) -> Vec<Uuid> {
+        let expired: Vec<Uuid> = self
+            .items
+            .values()
+            .filter(|c| c.deleted_at.is_some_and(|at| at < cutoff))
+            .map(|c| c.id)
+            .collect();
+
+        for id in &expired {
+            if let Some(contact) = self.items.remove(id) {
+                self.index.remove_tombstone(&contact);
+                self.record(Some(contact), None);
+            }
+        }
+        expired
+    }
+
     fn check_contact_before_updating(
         &self,
         contact: &Contact,
         new_name: &Option<String>,
-        new_email: &Option<String>,
+        new_email: Option<&Email>,
+        extra_emails: &[Email],
     ) -> Result<(), AppError> {
         if let Some(n) = new_name.clone()
             && n == contact.name
@@ -314,50 +1112,69 @@ impl Contacts {
             ));
         }
 
-        if let Some(e) = new_email.clone()
-            && e == contact.email
-        {
-            return Err(AppError::Validation(
-                "Contact already has same email address".to_string(),
-            ));
+        // A secondary address can become the primary one, but not be added again
+        let primary = contact.email.iter().find(|e| e.primary);
+        if let Some(e) = new_email.filter(|e| primary == Some(*e)) {
+            return Err(AppError::Validation(format!(
+                "{} is already the primary address",
+                e.address
+            )));
+        }
+        if let Some(e) = extra_emails.iter().find(|e| contact.email.contains(e)) {
+            return Err(AppError::Validation(format!(
+                "Contact already has email address {}",
+                e.address
+            )));
         }
         Ok(())
     }
+    /// `new_email` replaces the primary address; `extra_emails` are added
+    /// alongside it. Everything else to change goes in `patch`.
     pub fn update(
         &mut self,
         id: Uuid,
         new_name: Option<String>,
         new_phone: Option<String>,
-        new_email: Option<String>,
+        new_email: Option<Email>,
+        extra_emails: Vec<Email>,
+        patch: &ContactPatch,
     ) -> Result<(), AppError> {
         if id.is_nil() {
             return Err(AppError::Validation(ValidationResponse::check_uuid()));
         }
 
-        let mut contact = self
+        let before = self
             .items
             .get(&id)
+            .filter(|c| !c.is_deleted())
             .cloned()
-            .ok_or(AppError::Parse("Contact not found".to_string()))?;
+            .ok_or(AppError::NotFound("Contact not found".to_string()))?;
 
-        self.check_contact_before_updating(&contact, &new_name, &new_email)?;
+        self.check_contact_before_updating(&before, &new_name, new_email.as_ref(), &extra_emails)?;
+        patch.check_revision(&before)?;
+        let mut contact = before.clone();
 
         self.remove_index(&contact);
 
         if let Some(name) = new_name {
-            contact.name = name
+            contact.set_name(&name)
         }
         if let Some(phone) = new_phone {
-            contact.phone = vec![phone]
+            contact.phone = vec![Phone::new(&phone)]
         }
-        if let Some(email) = new_email {
-            contact.email = email
+        match new_email {
+            Some(email) if contact.email.contains(&email) => contact.promote_email(&email),
+            Some(email) => contact.set_primary_email(email),
+            None => {}
         }
-        contact.updated_at = Utc::now();
+        contact.email.extend(extra_emails);
+        email::ensure_primary(&mut contact.email);
+        patch.apply(&mut contact);
 
         self.items.insert(contact.id, contact.clone());
 
         self.add_index(&contact);
+        self.record(Some(before), Some(contact));
 
         println!("✅ Contact updated");
 
@@ -412,23 +1229,12 @@ impl Contacts {
 
         println!("Matches {:?}", matches);
 
-        if matches.is_empty() {
-            println!("No contacts matched your search.")
-            // return Ok(());
-        }
-
-        println!("Found {} result(s)", matches.len());
-        for i in matches.clone() {
-            // if let Some(c) = contacts.get(i) {
-            println!(
-                "- {} - [{}] - {} - [{}]",
-                i.name,
-                i.phone.join(", "),
-                i.email,
-                i.tags.join(", ")
-            )
-            // }
-        }
+        print_search_results(&matches, |id| {
+            self.items
+                .get(&id)
+                .filter(|c| !c.is_deleted())
+                .map(|c| c.name.clone())
+        });
         Ok(matches)
     }
 
@@ -562,37 +1368,98 @@ impl Contacts {
     //     Ok(())
     // }
 
+    /// Merges a JSON or NDJSON file, reading it one contact at a time.
     pub fn merge_from_file(
         &mut self,
         other_path: &str,
         policy: MergePolicy,
     ) -> Result<usize, AppError> {
-        let data = fs::read_to_string(other_path)?;
+        let counts = self.merge_stream(policy, |merge| {
+            stream::read_contacts(other_path, merge).map(|_| ())
+        })?;
+        Ok(counts.merged)
+    }
+
+    /// Merges each contact through `merge_single_contact` as one batch,
+    /// rolling everything back if one fails. Returns how many were written.
+    pub fn merge_contacts(
+        &mut self,
+        imported_contacts: Vec<Contact>,
+        policy: MergePolicy,
+    ) -> Result<usize, AppError> {
+        let counts = self.merge_stream(policy, |merge| {
+            imported_contacts.into_iter().try_for_each(merge)
+        })?;
+        Ok(counts.merged)
+    }
 
-        let imported_contacts: Vec<Contact> = serde_json::from_str(&data)
-            .map_err(|e| AppError::Parse(format!("Error, JSON... : {}", e)))?;
+    /// Like `merge_contacts`, for contacts that `feed` hands over one at a
+    /// time as it reads them, so the imported file is never held whole.
+    pub fn merge_stream(
+        &mut self,
+        policy: MergePolicy,
+        feed: impl FnOnce(&mut OnContact) -> Result<(), AppError>,
+    ) -> Result<MergeCounts, AppError> {
+        // A failure undoes what was recorded from here on
+        let mark = self.changes.len();
+        let mut counts = MergeCounts::default();
+        self.begin_batch();
+
+        // Imported id -> id the contact ended up under locally
+        let mut ids = HashMap::new();
+        let result = feed(&mut |contact: Contact| {
+            let (imported_id, name, phone) =
+                (contact.id, contact.name.clone(), contact.phone.clone());
+            let recorded = self.changes.len();
+            counts.read += 1;
+            if !contact.is_deleted() && self.find_with_name_phone(&name, &phone).is_some() {
+                counts.duplicates += 1;
+            }
 
-        // Create snapshot for rollback
-        let snapshot = self.create_snapshot();
-        let mut merged_count = 0;
+            let merged = self.merge_single_contact(contact, &policy)?;
+            println!("Merge counter:{}", merged);
+            if merged {
+                counts.merged += 1;
+            }
 
-        let result = (|| -> Result<usize, AppError> {
-            for contact in imported_contacts {
-                let merged = self.merge_single_contact(contact, &policy)?;
-                println!("Merge counter:{}", merged);
-                if merged {
-                    merged_count += 1;
-                }
+            let local_id = if self.changes.len() > recorded {
+                self.changes.last().map(|r| r.contact_id)
+            } else {
+                self.find_with_name_phone(&name, &phone)
+            };
+            if let Some(local_id) = local_id {
+                ids.insert(imported_id, local_id);
             }
-            Ok(merged_count)
-        })();
+            Ok(())
+        });
+        if result.is_ok() {
+            self.remap_relations(&ids);
+        }
+        self.end_batch();
 
         // Rollback on error
-        if result.is_err() {
-            self.restore_snapshot(snapshot);
+        match result {
+            Ok(()) => Ok(counts),
+            Err(e) => {
+                self.roll_back_to(mark);
+                Err(e)
+            }
         }
+    }
 
-        result
+    /// Points the links of contacts written by the current batch at their
+    /// local ids, since imported contacts may have been given fresh ones.
+    fn remap_relations(&mut self, ids: &HashMap<Uuid, Uuid>) {
+        let Some(batch) = self.batch else {
+            return;
+        };
+        for record in self.changes.iter_mut().filter(|r| r.operation == batch) {
+            if let Some(after) = record.after.as_mut()
+                && relation::remap(&mut after.relations, ids)
+            {
+                self.items.insert(after.id, after.clone());
+            }
+        }
     }
 
     pub fn merge_single_contact(
@@ -602,6 +1469,20 @@ impl Contacts {
     ) -> Result<bool, AppError> {
         // let key = (contact.name.clone(), contact.phone.clone());
 
+        if contact.is_deleted() {
+            return Ok(self.apply_tombstone(&contact));
+        }
+
+        // Don't resurrect a contact that was deleted after this copy was last changed
+        if let Some(tombstone) = self.find_tombstone(&contact)
+            && tombstone
+                .deleted_at
+                .is_some_and(|at| at >= contact.updated_at)
+        {
+            println!("Skipping deleted contact: {}", contact.name);
+            return Ok(false);
+        }
+
         let test = self.find_with_name_phone(&contact.name, &contact.phone);
         println!("Testing: {:?}", test);
 
@@ -629,16 +1510,20 @@ impl Contacts {
                             self.remove_index(&existing);
                             contact.id = existing_id;
                             contact.updated_at = Utc::now();
+                            contact.revision = existing.revision + 1;
                             self.items.insert(existing_id, contact.clone());
                             self.add_index(&contact);
+                            self.record(Some(existing), Some(contact.clone()));
                             println!("Overwrote with imported version: {}", contact.name);
                             Ok(true)
                         }
                         ConflictResolution::Merge => {
-                            let merged = merge_contact_data(&existing, &contact);
+                            let mut merged = merge_contact_data(&existing, &contact);
+                            merged.revision = existing.revision + 1;
                             self.remove_index(&existing);
                             self.items.insert(existing_id, merged.clone());
                             self.add_index(&merged);
+                            self.record(Some(existing), Some(merged));
                             println!("Merged data for: {}", contact.name);
                             Ok(true)
                         }
@@ -651,7 +1536,7 @@ impl Contacts {
                     new_contact.id = Uuid::new_v4();
 
                     // Merge phone numbers from both
-                    let mut all_phones: HashSet<String> = existing.phone.iter().cloned().collect();
+                    let mut all_phones: HashSet<Phone> = existing.phone.iter().cloned().collect();
                     println!("Existing phone number: {:?}", all_phones);
                     all_phones.extend(contact.phone.iter().cloned());
                     new_contact.phone = all_phones.into_iter().collect();
@@ -662,6 +1547,7 @@ impl Contacts {
 
                     self.items.insert(new_contact.id, new_contact.clone());
                     self.add_index(&new_contact);
+                    self.record(None, Some(new_contact));
                     println!("Created duplicate entry for: {}", contact.name);
                     Ok(true)
                 }
@@ -673,11 +1559,49 @@ impl Contacts {
             contact.updated_at = Utc::now();
             self.items.insert(contact.id, contact.clone());
             self.add_index(&contact);
+            self.record(None, Some(contact.clone()));
             println!("Added new contact: {}", contact.name);
             Ok(true)
         }
     }
 
+    /// Trashes the local copy of a contact deleted elsewhere, unless the local
+    /// copy was edited after the deletion.
+    fn apply_tombstone(&mut self, tombstone: &Contact) -> bool {
+        let local_id = self
+            .items
+            .get(&tombstone.id)
+            .map(|c| c.id)
+            .or_else(|| self.find_with_name_phone(&tombstone.name, &tombstone.phone));
+
+        let Some(local) = local_id.and_then(|id| self.items.get(&id)).cloned() else {
+            return false;
+        };
+        let deleted_at = tombstone.deleted_at.unwrap_or_else(Utc::now);
+        if local.is_deleted() || local.updated_at > deleted_at {
+            return false;
+        }
+
+        let mut contact = local.clone();
+        contact.trash(deleted_at);
+        self.remove_index(&local);
+        self.items.insert(contact.id, contact.clone());
+        self.index.add_tombstone(&contact);
+        self.record(Some(local), Some(contact));
+        println!("Deleted (synced tombstone): {}", tombstone.name);
+        true
+    }
+
+    fn find_tombstone(&self, contact: &Contact) -> Option<&Contact> {
+        if let Some(tombstone) = self.items.get(&contact.id).filter(|c| c.is_deleted()) {
+            return Some(tombstone);
+        }
+        self.index
+            .lookup_tombstones(&contact.name, &contact.phone)
+            .into_iter()
+            .find_map(|id| self.items.get(&id))
+    }
+
     pub fn sync_from_file(
         &mut self,
         other_path: &str,
@@ -685,8 +1609,7 @@ impl Contacts {
     ) -> Result<(), AppError> {
         let data = fs::read_to_string(other_path)?;
 
-        let mut imported_contacts: Vec<Contact> = serde_json::from_str(&data)
-            .map_err(|e| AppError::Parse(format!("Error, JSON... : {}", e)))?;
+        let (mut imported_contacts, _) = migrations::load(&data)?;
         println!("Passed Serde: {:?}", imported_contacts);
 
         for contact in imported_contacts.iter_mut() {
@@ -717,7 +1640,7 @@ impl Contacts {
                     }
                     MergePolicy::Duplicate => {
                         if local_contact == contact {
-                            local_contact.phone.push(contact.phone.join(", "));
+                            local_contact.phone.extend(contact.phone.iter().cloned());
                         }
 
                         contact.id = Uuid::new_v4();
@@ -731,40 +1654,24 @@ impl Contacts {
         Ok(())
     }
 
-    fn create_snapshot(&self) -> ContactsSnapshot {
-        ContactsSnapshot {
-            items: self.items.clone(),
-            index: ContactsIndex::build(&self.items),
+    /// Puts back what the changes recorded after the first `mark` replaced,
+    /// newest first, and drops them. Cheaper than copying the store up front,
+    /// since only the contacts that changed are held twice.
+    fn roll_back_to(&mut self, mark: usize) {
+        let undone: Vec<ChangeRecord> = self.changes.drain(mark..).collect();
+        for record in undone.into_iter().rev() {
+            match record.before {
+                Some(before) => self.items.insert(record.contact_id, before),
+                None => self.items.remove(&record.contact_id),
+            };
         }
-    }
-
-    fn restore_snapshot(&mut self, snapshot: ContactsSnapshot) {
-        self.items = snapshot.items;
-        self.index = snapshot.index;
+        self.index = ContactsIndex::build(&self.items);
         println!("⚠️  Rollback performed due to error");
     }
 
     pub fn check_contact_exist(&self, new_contact: &Contact) -> bool {
-        let imported_phone_set: HashSet<_> = new_contact.phone.iter().collect();
-
-        let get_uuids = self.index.name_map.get(&new_contact.name).and_then(|ids| {
-            ids.iter()
-                .find(|&id| {
-                    if let Some(c) = self.items.get(id) {
-                        c.phone.iter().any(|p| imported_phone_set.contains(p))
-                    } else {
-                        false
-                    }
-                })
-                .cloned()
-        });
-
-        // if let Some(_status) = get_uuids {
-        //     true
-        // } else {
-        //     false
-        // }
-        matches!(get_uuids, Some(_status))
+        self.find_with_name_phone(&new_contact.name, &new_contact.phone)
+            .is_some()
     }
 
     // pub fn check_contact_duplicates(&self, name: String) -> bool {
@@ -777,6 +1684,17 @@ impl Contacts {
     // }
 }
 
+/// What a `merge_stream` run did.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct MergeCounts {
+    /// Contacts handed over by the feed.
+    pub read: usize,
+    /// Contacts added or changed.
+    pub merged: usize,
+    /// Contacts that matched one already there by name and phone.
+    pub duplicates: usize,
+}
+
 //Return type
 
 pub struct ContactsIter<'a> {
@@ -792,68 +1710,416 @@ impl<'a> Iterator for ContactsIter<'a> {
     }
 }
 
+/// Edit distance from `query` to the closest of the contact's addresses.
+fn email_distance(query: &str, contact: &Contact) -> usize {
+    contact
+        .email
+        .iter()
+        .map(|e| levenshtein(query, &e.key()))
+        .min()
+        .unwrap_or(usize::MAX)
+}
+
+/// A contact's date falling within the window asked of `upcoming_dates`.
+#[derive(Debug, Clone)]
+pub struct UpcomingDate<'a> {
+    pub contact: &'a Contact,
+    pub label: String,
+    pub date: PartialDate,
+    /// When it next falls.
+    pub on: NaiveDate,
+    pub days_until: i64,
+}
+
+impl UpcomingDate<'_> {
+    /// Age reached or years married on the day, when the year is known.
+    pub fn years(&self) -> Option<i32> {
+        self.date.years_on(self.on)
+    }
+}
+
+/// Dates of live contacts that fall within `days` days of `today` (inclusive),
+/// soonest first.
+pub fn upcoming_dates<'a>(
+    contacts: impl IntoIterator<Item = &'a Contact>,
+    today: NaiveDate,
+    days: i64,
+) -> Vec<UpcomingDate<'a>> {
+    let mut upcoming: Vec<UpcomingDate> = contacts
+        .into_iter()
+        .filter(|c| !c.is_deleted())
+        .flat_map(|contact| {
+            contact.all_dates().into_iter().map(move |(label, date)| {
+                let on = date.next_occurrence(today);
+                UpcomingDate {
+                    contact,
+                    label: label.to_string(),
+                    date,
+                    on,
+                    days_until: (on - today).num_days(),
+                }
+            })
+        })
+        .filter(|u| u.days_until <= days)
+        .collect();
+
+    upcoming.sort_by(|a, b| {
+        a.days_until
+            .cmp(&b.days_until)
+            .then_with(|| a.contact.name.cmp(&b.contact.name))
+    });
+    upcoming
+}
+
+/// `name_of` resolves the names of linked contacts; see `Contact::relation_lines`.
+pub fn print_search_results(matches: &[Contact], name_of: impl Fn(Uuid) -> Option<String>) {
+    if matches.is_empty() {
+        println!("No contacts matched your search.")
+    }
+
+    println!("Found {} result(s)", matches.len());
+    for i in matches {
+        println!(
+            "- {} - [{}] - {} - [{}]",
+            i.name,
+            format_phones(&i.phone),
+            format_emails(&i.email),
+            i.tags.join(", ")
+        );
+        if let Some(job) = i.job_line() {
+            println!("    {}", job);
+        }
+        for address in &i.addresses {
+            println!("    {}", address);
+        }
+        for (label, date) in i.all_dates() {
+            println!("    {}: {}", label, date);
+        }
+        for line in i.relation_lines(&name_of) {
+            println!("    🔗 {}", line);
+        }
+        for (key, value) in &i.custom_fields {
+            println!("    {}: {}", key, value);
+        }
+        for line in i.notes.iter().flat_map(|n| n.lines()) {
+            println!("    📝 {}", line);
+        }
+    }
+}
+
 pub fn export_csv(path: &str, contacts: &[Contact]) -> Result<(), AppError> {
+    export_csv_with_groups(path, contacts, &HashMap::new())
+}
+
+/// Like `export_csv`, filling the `groups` column from `groups`.
+pub fn export_csv_with_groups(
+    path: &str,
+    contacts: &[Contact],
+    groups: &HashMap<Uuid, Group>,
+) -> Result<(), AppError> {
+    export_csv_with_profile(path, contacts, groups, &CsvProfile::default())
+}
+
+/// Writes `contacts` in the columns, delimiter and date format of `profile`.
+pub fn export_csv_with_profile(
+    path: &str,
+    contacts: &[Contact],
+    groups: &HashMap<Uuid, Group>,
+    profile: &CsvProfile,
+) -> Result<(), AppError> {
     println!("Export Path: {}", path);
-    let file = File::create(path)?;
-    let mut wtr = Writer::from_writer(file);
-    // println!("Export files: {:?}", wtr.serialize(contacts.first()));
     println!("Export files: {:?}", (contacts.len()));
-
+    let mut writer = ContactWriter::create(path, Format::Csv, profile, contacts.len())?;
     for c in contacts {
-        wtr.serialize(Some(c))
-            .map_err(|e| AppError::Parse(e.to_string()))
-            .unwrap();
+        writer.write_with_groups(c, &group::names_of(groups.values(), c.id))?;
     }
-    wtr.flush()?;
+    writer.finish()?;
     Ok(())
 }
 
 pub fn import_csv(path: &str) -> Result<Vec<Contact>, AppError> {
-    let file = File::open(path)?;
-    let mut rdr = ReaderBuilder::new().from_reader(file);
+    import_csv_with_profile(path, &CsvProfile::default())
+}
+
+/// Reads contacts from a file laid out as `profile` describes; columns the
+/// profile doesn't name are ignored. Fails on the first bad rows; see
+/// `read_csv_rows` to import the rest.
+pub fn import_csv_with_profile(path: &str, profile: &CsvProfile) -> Result<Vec<Contact>, AppError> {
+    let rows = read_csv_rows(path, profile)?;
+    if !rows.rejected.is_empty() {
+        let reasons: Vec<String> = rows.rejected.iter().map(RejectedRow::to_string).collect();
+        return Err(AppError::Validation(reasons.join("\n")));
+    }
+    Ok(rows.contacts)
+}
+
+/// A CSV row that can't be imported.
+#[derive(Debug, Clone)]
+pub struct RejectedRow {
+    /// Line in the file, counting the header as line 1.
+    pub line: u64,
+    pub reason: String,
+    /// The row as read, empty if it couldn't be read at all.
+    pub record: StringRecord,
+}
+
+impl fmt::Display for RejectedRow {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.reason)
+    }
+}
+
+/// A CSV file split into the contacts that passed validation and the rows
+/// that didn't.
+#[derive(Debug, Clone)]
+pub struct CsvRows {
+    pub headers: StringRecord,
+    pub contacts: Vec<Contact>,
+    pub rejected: Vec<RejectedRow>,
+}
+
+impl CsvRows {
+    /// Writes the rejected rows with the file's own header plus an `error`
+    /// column, so they can be fixed and imported again.
+    pub fn write_rejects(&self, path: &str, profile: &CsvProfile) -> Result<(), AppError> {
+        let mut rejects = RejectWriter::create(path, profile, &self.headers)?;
+        for row in &self.rejected {
+            rejects.write(row)?;
+        }
+        rejects.finish()
+    }
+}
+
+/// Writes rejected rows as they turn up; see `CsvRows::write_rejects`.
+pub struct RejectWriter {
+    wtr: csv::Writer<File>,
+    columns: usize,
+}
+
+impl RejectWriter {
+    pub fn create(
+        path: &str,
+        profile: &CsvProfile,
+        headers: &StringRecord,
+    ) -> Result<Self, AppError> {
+        let mut wtr = WriterBuilder::new()
+            .delimiter(profile.delimiter())
+            .flexible(true)
+            .from_path(path)
+            .map_err(|e| AppError::Parse(e.to_string()))?;
+        let mut header = headers.clone();
+        header.push_field("error");
+        wtr.write_record(&header)
+            .map_err(|e| AppError::Parse(e.to_string()))?;
+        Ok(Self {
+            wtr,
+            columns: headers.len(),
+        })
+    }
+
+    pub fn write(&mut self, row: &RejectedRow) -> Result<(), AppError> {
+        let mut record: Vec<&str> = (0..self.columns)
+            .map(|i| row.record.get(i).unwrap_or_default())
+            .collect();
+        record.push(&row.reason);
+        self.wtr
+            .write_record(record)
+            .map_err(|e| AppError::Parse(e.to_string()))
+    }
+
+    pub fn finish(mut self) -> Result<(), AppError> {
+        Ok(self.wtr.flush()?)
+    }
+}
+
+/// Reads every row of the file, checking each contact with the validation
+/// module; a bad row is set aside with its line number instead of failing
+/// the whole file.
+pub fn read_csv_rows(path: &str, profile: &CsvProfile) -> Result<CsvRows, AppError> {
     let mut contacts = Vec::new();
+    let mut rejected = Vec::new();
+    let headers = for_each_csv_row(path, profile, &mut |_, row| {
+        match row {
+            Ok(contact) => contacts.push(contact),
+            Err(row) => rejected.push(row),
+        }
+        Ok(())
+    })?;
+    Ok(CsvRows {
+        headers,
+        contacts,
+        rejected,
+    })
+}
 
-    for result in rdr.deserialize() {
-        let contact: Contact = result.map_err(|e| AppError::Parse(e.to_string()))?;
-        contacts.push(contact);
+/// Called with the file's header and each row, valid or not.
+pub type OnCsvRow<'a> =
+    dyn FnMut(&StringRecord, Result<Contact, RejectedRow>) -> Result<(), AppError> + 'a;
+
+/// Like `read_csv_rows`, handing each row to `on_row` as it is read instead
+/// of collecting them; returns the header.
+pub fn for_each_csv_row(
+    path: &str,
+    profile: &CsvProfile,
+    on_row: &mut OnCsvRow,
+) -> Result<StringRecord, AppError> {
+    let (reader, mut progress) = stream::open(path, format!("Reading {}", path))?;
+    let mut rdr = ReaderBuilder::new()
+        .delimiter(profile.delimiter())
+        .flexible(true)
+        .from_reader(reader);
+    let headers = rdr
+        .headers()
+        .map_err(|e| AppError::Parse(e.to_string()))?
+        .clone();
+    let bound = profile.bind(&headers)?;
+
+    let mut record = StringRecord::new();
+    loop {
+        let row = match rdr.read_record(&mut record) {
+            Ok(false) => break,
+            Ok(true) => read_csv_row(profile, &bound, &record)?,
+            Err(e) => Err(RejectedRow {
+                line: e.position().map_or(0, |p| p.line()),
+                reason: e.to_string(),
+                record: StringRecord::new(),
+            }),
+        };
+        progress.tick();
+        on_row(&headers, row)?;
     }
 
-    if contacts.is_empty() {
+    if progress.finish() == 0 {
         return Err(AppError::Parse("Error: No CSV data".to_string()));
     }
-    Ok(contacts)
+    Ok(headers)
+}
+
+/// The row's contact if it reads and validates, else why not.
+fn read_csv_row(
+    profile: &CsvProfile,
+    bound: &[(usize, &CsvColumn)],
+    record: &StringRecord,
+) -> Result<Result<Contact, RejectedRow>, AppError> {
+    let reason = match profile.read_contact(bound, record) {
+        Ok(contact) => {
+            let problems = contact_problems(&contact);
+            if problems.is_empty() {
+                return Ok(Ok(contact));
+            }
+            problems.join(", ")
+        }
+        Err(AppError::Parse(reason) | AppError::Validation(reason)) => reason,
+        Err(e) => return Err(e),
+    };
+    Ok(Err(RejectedRow {
+        line: record.position().map_or(0, |p| p.line()),
+        reason,
+        record: record.clone(),
+    }))
+}
+
+fn tombstone_keys<'a>(
+    name: &str,
+    phones: &'a [Phone],
+) -> impl Iterator<Item = (String, String)> + 'a {
+    let name = name.trim().to_lowercase();
+    phones.iter().map(move |p| (name.clone(), p.key()))
 }
 
 #[derive(Debug, Clone)]
 pub struct ContactsIndex {
     name_map: HashMap<String, HashSet<Uuid>>,
+    /// Given names, family names and nicknames, so a search for "O'Brien" finds "Conan O'Brien".
+    name_part_map: HashMap<String, HashSet<Uuid>>,
     domain_map: HashMap<String, HashSet<Uuid>>,
+    /// Keyed by every tag and each of its parents, so `work` finds contacts
+    /// tagged `work/project-x`.
+    tag_map: HashMap<String, HashSet<Uuid>>,
+    /// Trashed contacts by lowercased name and phone key, so a merge can tell
+    /// it would bring one back without scanning every contact.
+    tombstone_map: HashMap<(String, String), HashSet<Uuid>>,
 }
 
 impl ContactsIndex {
     pub fn build(contacts: &HashMap<Uuid, Contact>) -> Self {
         let mut name_map: HashMap<String, HashSet<Uuid>> = HashMap::new();
+        let mut name_part_map: HashMap<String, HashSet<Uuid>> = HashMap::new();
         let mut domain_map: HashMap<String, HashSet<Uuid>> = HashMap::new();
+        let mut tag_map: HashMap<String, HashSet<Uuid>> = HashMap::new();
+        let mut tombstone_map: HashMap<(String, String), HashSet<Uuid>> = HashMap::new();
+
+        for contact in contacts.values().filter(|c| c.is_deleted()) {
+            for key in tombstone_keys(&contact.name, &contact.phone) {
+                tombstone_map.entry(key).or_default().insert(contact.id);
+            }
+        }
 
-        for (_key, contact) in contacts.iter() {
+        for contact in contacts.values().filter(|c| !c.is_deleted()) {
             let name_key = contact.name.to_lowercase();
             name_map.entry(name_key).or_default().insert(contact.id);
 
-            if let Some(domain) = contact.email.split('@').nth(1) {
-                let domain_key = domain.to_lowercase();
-                domain_map.entry(domain_key).or_default().insert(contact.id);
+            for key in contact.name_part_keys() {
+                name_part_map.entry(key).or_default().insert(contact.id);
+            }
+
+            for domain in contact.email_domains() {
+                domain_map.entry(domain).or_default().insert(contact.id);
+            }
+
+            for tag in contact.tag_keys() {
+                tag_map
+                    .entry(tag.to_string())
+                    .or_default()
+                    .insert(contact.id);
             }
         }
 
         ContactsIndex {
             name_map,
+            name_part_map,
             domain_map,
+            tag_map,
+            tombstone_map,
+        }
+    }
+
+    fn add_tombstone(&mut self, contact: &Contact) {
+        for key in tombstone_keys(&contact.name, &contact.phone) {
+            self.tombstone_map
+                .entry(key)
+                .or_default()
+                .insert(contact.id);
         }
     }
 
+    fn remove_tombstone(&mut self, contact: &Contact) {
+        for key in tombstone_keys(&contact.name, &contact.phone) {
+            if let Some(set) = self.tombstone_map.get_mut(&key) {
+                set.remove(&contact.id);
+                if set.is_empty() {
+                    self.tombstone_map.remove(&key);
+                }
+            }
+        }
+    }
+
+    /// Trashed contacts with this name and one of these phones.
+    fn lookup_tombstones(&self, name: &str, phones: &[Phone]) -> HashSet<Uuid> {
+        tombstone_keys(name, phones)
+            .filter_map(|key| self.tombstone_map.get(&key))
+            .flatten()
+            .copied()
+            .collect()
+    }
+
     pub fn lookup_name(&self, name: &str) -> HashSet<Uuid> {
-        let key = name.to_lowercase();
-        self.name_map.get(&key).cloned().unwrap_or_default()
+        let key = name.trim().to_lowercase();
+        let mut ids = self.name_map.get(&key).cloned().unwrap_or_default();
+        if let Some(part_ids) = self.name_part_map.get(&key) {
+            ids.extend(part_ids);
+        }
+        ids
     }
 
     pub fn lookup_domain(&self, domain: &str) -> HashSet<Uuid> {
@@ -861,6 +2127,25 @@ impl ContactsIndex {
         self.domain_map.get(&key).cloned().unwrap_or_default()
     }
 
+    /// Contacts tagged `tag` or one of its children.
+    pub fn lookup_tag(&self, tag: &str) -> HashSet<Uuid> {
+        self.tag_map
+            .get(&tags::normalize(tag))
+            .cloned()
+            .unwrap_or_default()
+    }
+
+    /// Every tag and parent tag in use with its number of contacts, sorted.
+    pub fn tag_counts(&self) -> Vec<(&str, usize)> {
+        let mut counts: Vec<(&str, usize)> = self
+            .tag_map
+            .iter()
+            .map(|(tag, ids)| (tag.as_str(), ids.len()))
+            .collect();
+        counts.sort_unstable();
+        counts
+    }
+
     pub fn fuzzy_search<'a>(
         &self,
         query: &str,
@@ -871,11 +2156,11 @@ impl ContactsIndex {
         let q = query.to_lowercase();
         let mut results: Vec<&Contact> = Vec::new();
 
-        let contacts_x = contacts.values();
+        let contacts_x = contacts.values().filter(|c| !c.is_deleted());
 
         for c in contacts_x {
             let name_distance = levenshtein(&q, &c.name.to_lowercase());
-            let email_distance = levenshtein(&q, &c.email.to_lowercase());
+            let email_distance = email_distance(&q, c);
 
             if name_distance < max_edits || email_distance < max_edits {
                 results.push(c);
@@ -895,7 +2180,11 @@ impl ContactsIndex {
         let num_threads = 4;
         let query = query.to_lowercase();
 
-        let contacts_vec: Vec<Contact> = contacts.values().cloned().collect();
+        let contacts_vec: Vec<Contact> = contacts
+            .values()
+            .filter(|c| !c.is_deleted())
+            .cloned()
+            .collect();
         let total = contacts_vec.len();
 
         if total == 0 {
@@ -926,7 +2215,7 @@ impl ContactsIndex {
                 //Each threads going to work!
                 for c in &contacts_clone[start_of_chunk..end_of_chunk] {
                     let name_distance = levenshtein(&query_clone, &c.name.to_lowercase());
-                    let email_distance = levenshtein(&query_clone, &c.email.to_lowercase());
+                    let email_distance = email_distance(&query_clone, c);
 
                     if name_distance <= max_edits || email_distance <= max_edits {
                         local_results.push(c.clone());
@@ -958,7 +2247,7 @@ impl ContactsIndex {
         max_edits: usize,
     ) -> Vec<&'a Contact> {
         let query = query.to_lowercase();
-        let values: Vec<&Contact> = contacts.values().collect();
+        let values: Vec<&Contact> = contacts.values().filter(|c| !c.is_deleted()).collect();
 
         // let threads = std::thread::available_parallelism()
         //     .map(|n| n.get())
@@ -979,7 +2268,7 @@ impl ContactsIndex {
 
                     for c in chunk {
                         let name_distance = levenshtein(query, &c.name.to_lowercase());
-                        let email_distance = levenshtein(query, &c.email.to_lowercase());
+                        let email_distance = email_distance(query, c);
 
                         if name_distance <= max_edits || email_distance <= max_edits {
                             local.push(*c);
@@ -998,10 +2287,3 @@ impl ContactsIndex {
         results
     }
 }
-
-/// Snapshot structure for rollback capability
-#[derive(Debug, Clone)]
-struct ContactsSnapshot {
-    items: HashMap<Uuid, Contact>,
-    index: ContactsIndex,
-}
@@ -0,0 +1,205 @@
+use std::{
+    fmt,
+    hash::{Hash, Hasher},
+    str::FromStr,
+};
+
+use serde::{Deserialize, Deserializer, Serialize};
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum EmailLabel {
+    #[default]
+    Personal,
+    Work,
+    Other,
+}
+
+impl fmt::Display for EmailLabel {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let label = match self {
+            EmailLabel::Personal => "personal",
+            EmailLabel::Work => "work",
+            EmailLabel::Other => "other",
+        };
+        write!(f, "{}", label)
+    }
+}
+
+impl FromStr for EmailLabel {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_lowercase().as_str() {
+            "personal" | "home" => Ok(EmailLabel::Personal),
+            "work" => Ok(EmailLabel::Work),
+            "other" => Ok(EmailLabel::Other),
+            _ => Err(format!(
+                "Unknown email label '{}'. Use: personal | work | other",
+                s
+            )),
+        }
+    }
+}
+
+/// One address of a contact. Addresses compare case-insensitively.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(from = "EmailRepr")]
+pub struct Email {
+    pub label: EmailLabel,
+    pub address: String,
+    /// Exactly one address per contact is primary; see [`ensure_primary`].
+    #[serde(default)]
+    pub primary: bool,
+}
+
+/// Older data stores the address as a bare string.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum EmailRepr {
+    Address(String),
+    Full {
+        #[serde(default)]
+        label: EmailLabel,
+        address: String,
+        #[serde(default)]
+        primary: bool,
+    },
+}
+
+impl From<EmailRepr> for Email {
+    fn from(repr: EmailRepr) -> Self {
+        match repr {
+            EmailRepr::Address(address) => Email::new(&address),
+            EmailRepr::Full {
+                label,
+                address,
+                primary,
+            } => Email {
+                label,
+                address,
+                primary,
+            },
+        }
+    }
+}
+
+impl Email {
+    pub fn new(address: &str) -> Self {
+        Self::with_label(address, EmailLabel::default())
+    }
+
+    pub fn with_label(address: &str, label: EmailLabel) -> Self {
+        Self {
+            label,
+            address: address.trim().to_string(),
+            primary: false,
+        }
+    }
+
+    /// Lowercased part after the `@`, if any.
+    pub fn domain(&self) -> Option<String> {
+        self.address
+            .split_once('@')
+            .map(|(_, domain)| domain.to_lowercase())
+    }
+
+    pub fn key(&self) -> String {
+        self.address.to_lowercase()
+    }
+}
+
+impl From<&str> for Email {
+    fn from(address: &str) -> Self {
+        Email::new(address)
+    }
+}
+
+impl PartialEq for Email {
+    fn eq(&self, other: &Self) -> bool {
+        self.key() == other.key()
+    }
+}
+
+impl Eq for Email {}
+
+impl Hash for Email {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.key().hash(state);
+    }
+}
+
+impl fmt::Display for Email {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} ({})", self.address, self.label)
+    }
+}
+
+/// Keeps exactly one primary address: the first one marked primary, or the
+/// first address when none is.
+pub fn ensure_primary(emails: &mut [Email]) {
+    let primary = emails.iter().position(|e| e.primary).unwrap_or(0);
+    for (i, email) in emails.iter_mut().enumerate() {
+        email.primary = i == primary;
+    }
+}
+
+/// A single address string as sent by older clients, or a list of addresses.
+pub fn deserialize_emails<'de, D>(deserializer: D) -> Result<Vec<Email>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum OneOrMany {
+        One(String),
+        Many(Vec<Email>),
+    }
+
+    let mut emails = match OneOrMany::deserialize(deserializer)? {
+        OneOrMany::One(address) if address.trim().is_empty() => vec![],
+        OneOrMany::One(address) => vec![Email::new(&address)],
+        OneOrMany::Many(emails) => emails,
+    };
+    ensure_primary(&mut emails);
+    Ok(emails)
+}
+
+/// Joins addresses for one-line output, primary first.
+pub fn format_emails(emails: &[Email]) -> String {
+    let mut sorted: Vec<&Email> = emails.iter().collect();
+    sorted.sort_by_key(|e| !e.primary);
+    sorted
+        .iter()
+        .map(|e| e.to_string())
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_emails_deserialize_from_string_or_list() {
+        #[derive(Deserialize)]
+        struct Row {
+            #[serde(deserialize_with = "deserialize_emails")]
+            email: Vec<Email>,
+        }
+
+        let old: Row = serde_json::from_str(r#"{"email": "Alice@Work.com"}"#).unwrap();
+        assert_eq!(old.email, vec![Email::new("alice@work.com")]);
+        assert!(old.email[0].primary);
+        assert_eq!(old.email[0].domain().as_deref(), Some("work.com"));
+
+        let new: Row = serde_json::from_str(
+            r#"{"email": ["a@home.com", {"label": "work", "address": "a@work.com", "primary": true}]}"#,
+        )
+        .unwrap();
+        assert!(!new.email[0].primary);
+        assert!(new.email[1].primary);
+        assert_eq!(new.email[1].label, EmailLabel::Work);
+        assert!(format_emails(&new.email).starts_with("a@work.com"));
+    }
+}
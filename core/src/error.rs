@@ -3,7 +3,12 @@ pub enum AppError {
     Io(std::io::Error),
     Parse(String),
     Validation(String),
+    /// A contact, group or tag that isn't there.
+    NotFound(String),
     Network(String),
+    Locked(String),
+    Conflict(String),
+    Database(String),
 }
 
 impl std::fmt::Display for AppError {
@@ -12,7 +17,11 @@ impl std::fmt::Display for AppError {
             AppError::Io(err) => write!(f, "I/O error: {}", err),
             AppError::Parse(msg) => write!(f, "Parse error: {}", msg),
             AppError::Validation(msg) => write!(f, "Validation failed: {}", msg),
+            AppError::NotFound(msg) => write!(f, "{}", msg),
             AppError::Network(msg) => write!(f, "Network error: {}", msg),
+            AppError::Locked(msg) => write!(f, "Store locked: {}", msg),
+            AppError::Conflict(msg) => write!(f, "Conflict: {}", msg),
+            AppError::Database(msg) => write!(f, "Database error: {}", msg),
         }
     }
 }
@@ -28,3 +37,9 @@ impl From<reqwest::Error> for AppError {
         AppError::Network(format!("HTTP error: {}", e))
     }
 }
+
+impl From<rusqlite::Error> for AppError {
+    fn from(e: rusqlite::Error) -> Self {
+        AppError::Database(e.to_string())
+    }
+}
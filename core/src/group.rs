@@ -0,0 +1,202 @@
+use std::collections::{BTreeSet, HashMap};
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::{domain::Contact, error::AppError};
+
+/// A named set of contacts. Unlike tags, a group has its own id and
+/// description, and membership is kept on the group rather than the contact.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Group {
+    #[serde(default = "Uuid::new_v4")]
+    pub id: Uuid,
+    pub name: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    /// Contact ids. Members may be trashed or purged later; callers skip
+    /// those when resolving them.
+    #[serde(default)]
+    pub members: BTreeSet<Uuid>,
+    #[serde(default)]
+    pub created_at: DateTime<Utc>,
+    #[serde(default)]
+    pub updated_at: DateTime<Utc>,
+}
+
+impl Group {
+    pub fn new(name: &str, description: Option<String>) -> Self {
+        Self {
+            id: Uuid::new_v4(),
+            name: name.trim().to_string(),
+            description: description.filter(|d| !d.trim().is_empty()),
+            members: BTreeSet::new(),
+            created_at: Utc::now(),
+            updated_at: Utc::now(),
+        }
+    }
+
+    /// Returns false if the contact was already a member.
+    pub fn add_member(&mut self, contact_id: Uuid) -> bool {
+        let added = self.members.insert(contact_id);
+        if added {
+            self.updated_at = Utc::now();
+        }
+        added
+    }
+
+    /// The members that are neither trashed nor purged.
+    pub fn live_members<'a>(&self, contacts: &'a HashMap<Uuid, Contact>) -> Vec<&'a Contact> {
+        self.members
+            .iter()
+            .filter_map(|id| contacts.get(id))
+            .filter(|c| !c.is_deleted())
+            .collect()
+    }
+
+    /// Returns false if the contact was not a member.
+    pub fn remove_member(&mut self, contact_id: Uuid) -> bool {
+        let removed = self.members.remove(&contact_id);
+        if removed {
+            self.updated_at = Utc::now();
+        }
+        removed
+    }
+}
+
+/// Adds a new group, rejecting empty or already used names (case-insensitive).
+pub fn create(
+    groups: &mut HashMap<Uuid, Group>,
+    name: &str,
+    description: Option<String>,
+) -> Result<Group, AppError> {
+    if name.trim().is_empty() {
+        return Err(AppError::Validation("Group name is empty".to_string()));
+    }
+    if groups
+        .values()
+        .any(|g| g.name.eq_ignore_ascii_case(name.trim()))
+    {
+        return Err(AppError::Validation(format!(
+            "A group named '{}' already exists",
+            name.trim()
+        )));
+    }
+
+    let group = Group::new(name, description);
+    groups.insert(group.id, group.clone());
+    Ok(group)
+}
+
+/// Looks a group up by id or by name (case-insensitive).
+pub fn find_mut<'a>(
+    groups: &'a mut HashMap<Uuid, Group>,
+    key: &str,
+) -> Result<&'a mut Group, AppError> {
+    let id = find(groups, key)?.id;
+    Ok(groups.get_mut(&id).expect("group was just found"))
+}
+
+/// Looks a group up by id or by name (case-insensitive).
+pub fn find<'a>(groups: &'a HashMap<Uuid, Group>, key: &str) -> Result<&'a Group, AppError> {
+    let key = key.trim();
+    key.parse::<Uuid>()
+        .ok()
+        .and_then(|id| groups.get(&id))
+        .or_else(|| groups.values().find(|g| g.name.eq_ignore_ascii_case(key)))
+        .ok_or(AppError::NotFound(format!("No group found: {}", key)))
+}
+
+/// Drops purged contacts from every group; returns how many memberships went.
+pub fn forget(groups: &mut HashMap<Uuid, Group>, purged: &[Uuid]) -> usize {
+    let mut removed = 0;
+    for group in groups.values_mut() {
+        for id in purged {
+            if group.remove_member(*id) {
+                removed += 1;
+            }
+        }
+    }
+    removed
+}
+
+/// Names of the groups `contact_id` belongs to, sorted.
+pub fn names_of<'a>(groups: impl IntoIterator<Item = &'a Group>, contact_id: Uuid) -> Vec<&'a str> {
+    let mut names: Vec<&str> = groups
+        .into_iter()
+        .filter(|g| g.members.contains(&contact_id))
+        .map(|g| g.name.as_str())
+        .collect();
+    names.sort_unstable();
+    names
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_create_find_and_membership() {
+        let mut groups = HashMap::new();
+        let family = create(&mut groups, "Family", Some("Close relatives".into())).unwrap();
+        create(&mut groups, "Book club", None).unwrap();
+        assert!(create(&mut groups, " family ", None).is_err());
+        assert!(create(&mut groups, "  ", None).is_err());
+
+        assert_eq!(find(&groups, "FAMILY").unwrap().id, family.id);
+        assert_eq!(
+            find(&groups, &family.id.to_string()).unwrap().name,
+            "Family"
+        );
+        assert!(find(&groups, "work").is_err());
+
+        let alice = Uuid::new_v4();
+        assert!(find_mut(&mut groups, "family").unwrap().add_member(alice));
+        assert!(!find_mut(&mut groups, "family").unwrap().add_member(alice));
+        find_mut(&mut groups, "book club")
+            .unwrap()
+            .add_member(alice);
+        assert_eq!(
+            names_of(groups.values(), alice),
+            vec!["Book club", "Family"]
+        );
+
+        assert!(
+            find_mut(&mut groups, "family")
+                .unwrap()
+                .remove_member(alice)
+        );
+        assert_eq!(names_of(groups.values(), alice), vec!["Book club"]);
+    }
+
+    #[test]
+    fn test_trashed_and_purged_members_are_not_counted() {
+        let mut groups = HashMap::new();
+        let family = create(&mut groups, "Family", None).unwrap();
+        let mut contacts = HashMap::new();
+        let [alice, bob, carol] = ["Alice", "Bob", "Carol"].map(|name| {
+            let contact = Contact::new(
+                name,
+                "08123456789",
+                "someone@work.com",
+                vec![],
+                Utc::now(),
+                Utc::now(),
+            );
+            contacts.insert(contact.id, contact.clone());
+            contact.id
+        });
+        for id in [alice, bob, carol] {
+            find_mut(&mut groups, "family").unwrap().add_member(id);
+        }
+
+        contacts.get_mut(&bob).unwrap().trash(Utc::now());
+        contacts.remove(&carol);
+        let live = groups[&family.id].live_members(&contacts);
+        assert_eq!(live.iter().map(|c| c.id).collect::<Vec<_>>(), vec![alice]);
+
+        assert_eq!(forget(&mut groups, &[carol]), 1);
+        assert_eq!(groups[&family.id].members.len(), 2);
+    }
+}
@@ -1,32 +1,112 @@
 use std::{collections::HashSet, env};
 
-use chrono::Utc;
+use chrono::{Duration, Utc};
 
 use crate::{
     domain::{ConflictResolution, Contact},
+    email::{self, Email},
     error::AppError,
+    phone::Phone,
 };
 
 pub fn merge_contact_data(local: &Contact, imported: &Contact) -> Contact {
     let mut merged = local.clone();
 
-    // Use the more complete name
+    // Use the more complete name, together with its parts
     if imported.name.len() > local.name.len() {
         merged.name = imported.name.clone();
+        merged.prefix = imported.prefix.clone();
+        merged.given_name = imported.given_name.clone();
+        merged.middle_name = imported.middle_name.clone();
+        merged.family_name = imported.family_name.clone();
+        merged.suffix = imported.suffix.clone();
+    }
+    if merged.nickname.is_none() {
+        merged.nickname = imported.nickname.clone();
+    }
+    if merged.organization.is_none() {
+        merged.organization = imported.organization.clone();
+    }
+    if merged.title.is_none() {
+        merged.title = imported.title.clone();
+    }
+    if merged.department.is_none() {
+        merged.department = imported.department.clone();
     }
 
-    // Use non-empty email, prefer the one from newer contact
-    if !imported.email.is_empty()
-        && (local.email.is_empty() || imported.updated_at > local.updated_at)
-    {
-        merged.email = imported.email.clone();
+    // Keep every address; the newer contact's primary one stays primary
+    let (newer, older) = if imported.updated_at > local.updated_at {
+        (imported, local)
+    } else {
+        (local, imported)
+    };
+    merged.email = newer.email.clone();
+    for address in &older.email {
+        if !merged.email.contains(address) {
+            merged.email.push(Email {
+                primary: false,
+                ..address.clone()
+            });
+        }
     }
+    email::ensure_primary(&mut merged.email);
 
     // Merge phone numbers (unique)
-    let mut all_phones: HashSet<String> = local.phone.iter().cloned().collect();
+    // Phones hash by their normalized number, so reformatted duplicates collapse
+    let mut all_phones: HashSet<Phone> = local.phone.iter().cloned().collect();
     all_phones.extend(imported.phone.iter().cloned());
     merged.phone = all_phones.into_iter().collect();
 
+    // Keep both notes, adding only the imported lines the local notes lack;
+    // "call back" must not swallow "call back on Monday"
+    merged.notes = match (&local.notes, &imported.notes) {
+        (Some(l), Some(i)) => {
+            let mut lines: Vec<&str> = l.lines().collect();
+            for line in i.lines() {
+                if !lines.contains(&line) {
+                    lines.push(line);
+                }
+            }
+            Some(lines.join("\n"))
+        }
+        (l, i) => l.clone().or(i.clone()),
+    };
+
+    // Union of custom fields; the newer contact wins on conflicting keys
+    merged.custom_fields = older.custom_fields.clone();
+    merged.custom_fields.extend(newer.custom_fields.clone());
+
+    // Known dates fill the gaps; other dates are merged (unique)
+    merged.birthday = newer.birthday.or(older.birthday);
+    merged.anniversary = newer.anniversary.or(older.anniversary);
+    for date in &imported.dates {
+        if !merged.dates.contains(date) {
+            merged.dates.push(date.clone());
+        }
+    }
+
+    // Merge links to other contacts (unique)
+    for relation in &imported.relations {
+        if !merged.relations.contains(relation) {
+            merged.relations.push(*relation);
+        }
+    }
+
+    // The newer photo wins; attachments are merged by content
+    merged.photo = newer.photo.clone().or(older.photo.clone());
+    for attachment in &imported.attachments {
+        if !merged.attachments.iter().any(|a| a.hash == attachment.hash) {
+            merged.attachments.push(attachment.clone());
+        }
+    }
+
+    // Merge addresses (unique)
+    for address in &imported.addresses {
+        if !merged.addresses.contains(address) {
+            merged.addresses.push(address.clone());
+        }
+    }
+
     // Merge tags (unique)
     let mut all_tags: HashSet<String> = local.tags.iter().cloned().collect();
     all_tags.extend(imported.tags.iter().cloned());
@@ -87,14 +167,27 @@ pub fn completeness_score(contact: &Contact) -> usize {
     //     score += 1; // Has full name
     // }
 
-    // Check email
-    if !contact.email.is_empty() {
-        score += 2;
-    }
+    // Email addresses
+    score += contact.email.len() * 2;
 
     // Phone numbers
     score += contact.phone.len() * 2;
 
+    // Work details and postal addresses
+    score += contact.job_line().is_some() as usize;
+    score += contact.addresses.len();
+
+    // Notes and custom fields
+    score += contact.notes.is_some() as usize;
+    score += contact.custom_fields.len();
+
+    // Birthday, anniversary and other dates
+    score += contact.all_dates().len();
+
+    // Links to other contacts
+    score += contact.relations.len();
+    score += contact.photo.is_some() as usize;
+
     // Tags
     score += contact.tags.len();
 
@@ -110,3 +203,20 @@ pub fn is_more_complete(a: &Contact, b: &Contact) -> bool {
 pub fn get_key(key: &str) -> Result<String, AppError> {
     env::var(key).map_err(|_e| AppError::Parse("env key not found".to_string()))
 }
+
+/// Parses an age such as `30d`, `12h` or `2w`.
+pub fn parse_age(value: &str) -> Result<Duration, AppError> {
+    let value = value.trim();
+    let invalid = || AppError::Validation(format!("Invalid age '{}', expected e.g. 30d", value));
+
+    let split = value.len().checked_sub(1).ok_or_else(invalid)?;
+    let (amount, unit) = value.split_at(split);
+    let amount: i64 = amount.parse().map_err(|_| invalid())?;
+
+    match unit {
+        "h" => Ok(Duration::hours(amount)),
+        "d" => Ok(Duration::days(amount)),
+        "w" => Ok(Duration::weeks(amount)),
+        _ => Err(invalid()),
+    }
+}
@@ -0,0 +1,347 @@
+use std::{
+    collections::{HashMap, HashSet},
+    fs::{self, OpenOptions},
+    io::Write,
+    path::PathBuf,
+};
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::{domain::Contact, error::AppError};
+
+/// Where a change came from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ChangeSource {
+    Cli,
+    Api,
+    Sync,
+    Import,
+    Undo,
+}
+
+impl std::fmt::Display for ChangeSource {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            ChangeSource::Cli => "cli",
+            ChangeSource::Api => "api",
+            ChangeSource::Sync => "sync",
+            ChangeSource::Import => "import",
+            ChangeSource::Undo => "undo",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// Before/after state of one contact for one change.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ChangeRecord {
+    /// Shared by every record of one operation, so a whole sync or import
+    /// is undone as a unit.
+    pub operation: Uuid,
+    pub contact_id: Uuid,
+    pub at: DateTime<Utc>,
+    pub source: ChangeSource,
+    pub before: Option<Contact>,
+    pub after: Option<Contact>,
+    /// Set on undo records: the operation they reverted.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reverts: Option<Uuid>,
+}
+
+impl ChangeRecord {
+    pub fn new(
+        operation: Uuid,
+        source: ChangeSource,
+        before: Option<Contact>,
+        after: Option<Contact>,
+    ) -> Self {
+        let contact_id = after
+            .as_ref()
+            .or(before.as_ref())
+            .map(|c| c.id)
+            .unwrap_or_default();
+
+        Self {
+            operation,
+            contact_id,
+            at: Utc::now(),
+            source,
+            before,
+            after,
+            reverts: None,
+        }
+    }
+
+    pub fn action(&self) -> &'static str {
+        match (&self.before, &self.after) {
+            (None, Some(_)) => "added",
+            (Some(_), None) => "removed",
+            (Some(b), Some(a)) if !b.is_deleted() && a.is_deleted() => "deleted",
+            (Some(b), Some(a)) if b.is_deleted() && !a.is_deleted() => "restored",
+            _ => "updated",
+        }
+    }
+}
+
+/// Append-only JSON-lines log of every `ChangeRecord`.
+pub struct HistoryLog {
+    path: PathBuf,
+}
+
+impl HistoryLog {
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self { path: path.into() }
+    }
+
+    pub fn append(&self, records: &[ChangeRecord]) -> Result<(), AppError> {
+        if records.is_empty() {
+            return Ok(());
+        }
+
+        let mut data = String::new();
+        for record in records {
+            let line = serde_json::to_string(record)
+                .map_err(|e| AppError::Parse(format!("History error: {}", e)))?;
+            data.push_str(&line);
+            data.push('\n');
+        }
+
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)?;
+        file.write_all(data.as_bytes())?;
+        Ok(())
+    }
+
+    pub fn records(&self) -> Result<Vec<ChangeRecord>, AppError> {
+        if !self.path.exists() {
+            return Ok(Vec::new());
+        }
+
+        fs::read_to_string(&self.path)?
+            .lines()
+            .filter(|l| !l.trim().is_empty())
+            .map(|l| {
+                serde_json::from_str(l)
+                    .map_err(|e| AppError::Parse(format!("History error: {}", e)))
+            })
+            .collect()
+    }
+
+    /// Every version of a contact the log holds, i.e. everything `undo`
+    /// could bring back.
+    pub fn versions(&self) -> Result<Vec<Contact>, AppError> {
+        Ok(self
+            .records()?
+            .into_iter()
+            .flat_map(|r| r.before.into_iter().chain(r.after))
+            .collect())
+    }
+
+    /// Timeline of one contact, oldest first.
+    pub fn for_contact(&self, id: Uuid) -> Result<Vec<ChangeRecord>, AppError> {
+        Ok(self
+            .records()?
+            .into_iter()
+            .filter(|r| r.contact_id == id)
+            .collect())
+    }
+
+    /// The latest `steps` operations that can still be undone, newest first.
+    /// Each operation keeps its records in the order they were made.
+    pub fn undoable(&self, steps: usize) -> Result<Vec<Vec<ChangeRecord>>, AppError> {
+        let records = self.records()?;
+
+        let reverted: HashSet<Uuid> = records.iter().filter_map(|r| r.reverts).collect();
+
+        let mut order: Vec<Uuid> = Vec::new();
+        let mut operations: HashMap<Uuid, Vec<ChangeRecord>> = HashMap::new();
+        for record in records {
+            if record.source == ChangeSource::Undo || reverted.contains(&record.operation) {
+                continue;
+            }
+            if !operations.contains_key(&record.operation) {
+                order.push(record.operation);
+            }
+            operations.entry(record.operation).or_default().push(record);
+        }
+
+        Ok(order
+            .iter()
+            .rev()
+            .take(steps)
+            .filter_map(|op| operations.remove(op))
+            .collect())
+    }
+}
+
+/// Puts every contact touched by `operations` (newest first, as returned by
+/// `undoable`) back to its `before` state and returns the records describing
+/// that, tagged with `undo_operation`.
+///
+/// A contact changed since the recorded change isn't the `after` the record
+/// describes; putting `before` back would throw that later change away, so
+/// that is a conflict and `contacts` must then be discarded.
+pub fn revert(
+    contacts: &mut HashMap<Uuid, Contact>,
+    operations: &[Vec<ChangeRecord>],
+    undo_operation: Uuid,
+) -> Result<Vec<ChangeRecord>, AppError> {
+    let mut records = Vec::new();
+
+    for record in operations.iter().flat_map(|op| op.iter().rev()) {
+        let current = contacts.get(&record.contact_id).cloned();
+
+        if !is_recorded_state(current.as_ref(), record.after.as_ref()) {
+            let name = record.after.as_ref().or(record.before.as_ref());
+            return Err(AppError::Conflict(format!(
+                "{} was changed after the change being undone (revision {}, expected {}); \
+                 nothing was undone",
+                name.map_or_else(|| record.contact_id.to_string(), |c| c.name.clone()),
+                revision_text(current.as_ref()),
+                revision_text(record.after.as_ref()),
+            )));
+        }
+
+        let restored = record.before.clone().map(|mut c| {
+            // Keep revisions moving forward so stale If-Match checks still fail
+            c.revision = current.as_ref().map_or(c.revision, |cur| cur.revision) + 1;
+            c.updated_at = Utc::now();
+            c
+        });
+
+        match &restored {
+            Some(contact) => {
+                contacts.insert(contact.id, contact.clone());
+            }
+            None => {
+                contacts.remove(&record.contact_id);
+            }
+        }
+
+        let mut undo = ChangeRecord::new(undo_operation, ChangeSource::Undo, current, restored);
+        undo.contact_id = record.contact_id;
+        undo.reverts = Some(record.operation);
+        records.push(undo);
+    }
+    Ok(records)
+}
+
+/// Whether `current` is still the `recorded` state: the same revision, or
+/// the same content at a later one, which is what an earlier undo leaves
+/// behind since it restores content but moves the revision forward.
+fn is_recorded_state(current: Option<&Contact>, recorded: Option<&Contact>) -> bool {
+    match (current, recorded) {
+        (None, None) => true,
+        (Some(current), Some(recorded)) if current.revision == recorded.revision => true,
+        (Some(current), Some(recorded)) => {
+            let mut current = current.clone();
+            current.revision = recorded.revision;
+            current.updated_at = recorded.updated_at;
+            serde_json::to_value(&current).ok() == serde_json::to_value(recorded).ok()
+        }
+        _ => false,
+    }
+}
+
+fn revision_text(contact: Option<&Contact>) -> String {
+    contact.map_or_else(|| "none".to_string(), |c| c.revision.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn contact(name: &str) -> Contact {
+        Contact::new(
+            name,
+            "08123456789",
+            "user@example.com",
+            vec![],
+            Utc::now(),
+            Utc::now(),
+        )
+    }
+
+    #[test]
+    fn test_undo_reverts_latest_operation_first() {
+        let dir = tempfile::tempdir().unwrap();
+        let log = HistoryLog::new(dir.path().join("history.jsonl"));
+
+        let alice = contact("Alice");
+        let mut renamed = alice.clone();
+        renamed.name = "Alicia".to_string();
+
+        let add = Uuid::new_v4();
+        let edit = Uuid::new_v4();
+        log.append(&[
+            ChangeRecord::new(add, ChangeSource::Cli, None, Some(alice.clone())),
+            ChangeRecord::new(
+                edit,
+                ChangeSource::Api,
+                Some(alice.clone()),
+                Some(renamed.clone()),
+            ),
+        ])
+        .unwrap();
+
+        let mut contacts = HashMap::from([(alice.id, renamed)]);
+
+        let ops = log.undoable(1).unwrap();
+        assert_eq!(ops.len(), 1);
+        assert_eq!(ops[0][0].operation, edit);
+
+        let undo = revert(&mut contacts, &ops, Uuid::new_v4()).unwrap();
+        log.append(&undo).unwrap();
+        assert_eq!(contacts[&alice.id].name, "Alice");
+
+        // The edit is gone from the undo stack; next in line is the add
+        let ops = log.undoable(5).unwrap();
+        assert_eq!(ops.len(), 1);
+        let undo = revert(&mut contacts, &ops, Uuid::new_v4()).unwrap();
+        log.append(&undo).unwrap();
+        assert!(contacts.is_empty());
+
+        assert_eq!(log.for_contact(alice.id).unwrap().len(), 4);
+        assert!(log.undoable(1).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_undo_refuses_to_drop_a_later_edit() {
+        let alice = contact("Alice");
+        let mut renamed = alice.clone();
+        renamed.name = "Alicia".to_string();
+        renamed.revision += 1;
+        let edit = vec![ChangeRecord::new(
+            Uuid::new_v4(),
+            ChangeSource::Cli,
+            Some(alice.clone()),
+            Some(renamed.clone()),
+        )];
+
+        // Edited again (say by the API) after the recorded rename
+        let mut retitled = renamed.clone();
+        retitled.title = Some("CTO".to_string());
+        retitled.revision += 1;
+        let mut contacts = HashMap::from([(alice.id, retitled.clone())]);
+
+        let result = revert(&mut contacts, std::slice::from_ref(&edit), Uuid::new_v4());
+        assert!(matches!(result, Err(AppError::Conflict(_))));
+
+        // Both edits undone together, newest first, is fine
+        let retitle = vec![ChangeRecord::new(
+            Uuid::new_v4(),
+            ChangeSource::Api,
+            Some(renamed),
+            Some(retitled),
+        )];
+        let undo = revert(&mut contacts, &[retitle, edit], Uuid::new_v4()).unwrap();
+        assert_eq!(undo.len(), 2);
+        assert_eq!(contacts[&alice.id].name, "Alice");
+        assert_eq!(contacts[&alice.id].title, None);
+    }
+}
@@ -1,5 +1,18 @@
+pub mod address;
+pub mod attachment;
+pub mod csv_profile;
+pub mod dates;
 pub mod domain;
+pub mod email;
 pub mod error;
+pub mod group;
 pub mod helpers;
+pub mod history;
+pub mod migrations;
+pub mod phone;
+pub mod relation;
 pub mod store;
+pub mod stream;
+pub mod tags;
 pub mod validation;
+pub mod vcard;
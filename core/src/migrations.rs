@@ -0,0 +1,388 @@
+//! Versioned layout of the JSON contacts file.
+//!
+//! Files are upgraded in memory on load by running every step in `MIGRATIONS`
+//! whose `from` is at or above the file's version; the next save writes the
+//! current layout. Versions so far:
+//!
+//! - 0: `contacts.txt`, one `name,phone,email` per line
+//! - 1: a bare JSON array of contacts (ids may be missing or not UUIDs)
+//! - 2: `{ "schema_version": 2, "contacts": [...] }`
+//! - 3: `given_name`, `family_name`, ... split out of `name`
+//! - 4: phones as `{ label, raw, e164 }` objects instead of strings
+//! - 5: `email` is a list of `{ label, address, primary }` objects
+//! - 6: a `groups` list is kept next to `contacts`
+
+use std::io::Write;
+
+use serde::Serialize;
+use serde_json::{Map, Value, json};
+use uuid::Uuid;
+
+use crate::{domain::Contact, email::Email, error::AppError, group::Group, phone::Phone};
+
+pub const CURRENT_SCHEMA_VERSION: u64 = 6;
+
+pub struct Migration {
+    /// Version this step upgrades from; it produces `from + 1`.
+    pub from: u64,
+    pub description: &'static str,
+    pub apply: fn(Value) -> Result<Value, AppError>,
+}
+
+/// Ordered registry of upgrade steps, one per schema version.
+pub const MIGRATIONS: &[Migration] = &[
+    Migration {
+        from: 0,
+        description: "convert name,phone,email text lines to a JSON array",
+        apply: text_to_array,
+    },
+    Migration {
+        from: 1,
+        description: "wrap contacts in a schema_version envelope and assign UUIDs",
+        apply: array_to_envelope,
+    },
+    Migration {
+        from: 2,
+        description: "split names into prefix/given/middle/family/suffix parts",
+        apply: split_names,
+    },
+    Migration {
+        from: 3,
+        description: "turn phone strings into labelled numbers with an E.164 form",
+        apply: label_phones,
+    },
+    Migration {
+        from: 4,
+        description: "turn the email address into the primary entry of an email list",
+        apply: list_emails,
+    },
+    Migration {
+        from: 5,
+        description: "add an empty group list next to the contacts",
+        apply: add_groups,
+    },
+];
+
+/// What loading a file did (or would do) to it.
+#[derive(Debug, Clone)]
+pub struct MigrationReport {
+    pub from_version: u64,
+    pub to_version: u64,
+    pub steps: Vec<&'static str>,
+    pub contacts: usize,
+}
+
+impl MigrationReport {
+    /// The steps loading a file at `from_version` runs.
+    pub fn new(from_version: u64, contacts: usize) -> Self {
+        Self {
+            from_version,
+            to_version: CURRENT_SCHEMA_VERSION,
+            steps: MIGRATIONS
+                .iter()
+                .filter(|m| m.from >= from_version)
+                .map(|m| m.description)
+                .collect(),
+            contacts,
+        }
+    }
+
+    pub fn is_current(&self) -> bool {
+        self.steps.is_empty()
+    }
+}
+
+/// Everything a contacts file holds.
+#[derive(Debug, Default)]
+pub struct Document {
+    pub contacts: Vec<Contact>,
+    pub groups: Vec<Group>,
+}
+
+/// Parses any supported version of the contacts file, upgrading it to the
+/// current layout.
+pub fn load(data: &str) -> Result<(Vec<Contact>, MigrationReport), AppError> {
+    let (document, report) = load_document(data)?;
+    Ok((document.contacts, report))
+}
+
+/// Like `load`, but also returns the groups. Only JSON is read: an empty
+/// file or anything else is an error rather than a text file with no
+/// contacts in it; see `load_legacy`.
+pub fn load_document(data: &str) -> Result<(Document, MigrationReport), AppError> {
+    if !data.trim_start().starts_with(['[', '{']) {
+        return Err(AppError::Parse(if data.trim().is_empty() {
+            "Contacts file is empty".to_string()
+        } else {
+            "Not a JSON contacts file".to_string()
+        }));
+    }
+    let document = serde_json::from_str(data)
+        .map_err(|e| AppError::Parse(format!("Error, JSON... : {}", e)))?;
+    upgrade(document)
+}
+
+/// Like `load_document`, also reading anything that isn't JSON as the old
+/// `contacts.txt` format. Only for files known to be in it: a `.txt` path,
+/// or one handed to `migrate`.
+pub fn load_legacy(data: &str) -> Result<(Document, MigrationReport), AppError> {
+    if data.trim_start().starts_with(['[', '{']) {
+        return load_document(data);
+    }
+    if data.trim().is_empty() {
+        return Err(AppError::Parse("Contacts file is empty".to_string()));
+    }
+    upgrade(Value::String(data.to_string()))
+}
+
+/// Runs the migrations a parsed file needs and reads the result.
+fn upgrade(mut document: Value) -> Result<(Document, MigrationReport), AppError> {
+    let from_version = schema_version(&document)?;
+    for migration in MIGRATIONS.iter().filter(|m| m.from >= from_version) {
+        document = (migration.apply)(document)?;
+    }
+
+    let contacts: Vec<Contact> = serde_json::from_value(document["contacts"].take())
+        .map_err(|e| AppError::Parse(format!("Error, JSON... : {}", e)))?;
+    let groups: Vec<Group> = match document.get_mut("groups") {
+        Some(groups) => serde_json::from_value(groups.take())
+            .map_err(|e| AppError::Parse(format!("Error, JSON... : {}", e)))?,
+        None => Vec::new(),
+    };
+
+    let report = MigrationReport::new(from_version, contacts.len());
+    Ok((Document { contacts, groups }, report))
+}
+
+#[derive(Serialize)]
+struct Envelope<'a, T> {
+    schema_version: u64,
+    contacts: &'a [T],
+    groups: &'a [&'a Group],
+}
+
+/// Serialises contacts in the current layout.
+pub fn to_document<T: Serialize>(contacts: &[T]) -> Result<String, AppError> {
+    to_document_with_groups(contacts, &[])
+}
+
+/// Serialises contacts and groups in the current layout.
+pub fn to_document_with_groups<T: Serialize>(
+    contacts: &[T],
+    groups: &[&Group],
+) -> Result<String, AppError> {
+    let envelope = Envelope {
+        schema_version: CURRENT_SCHEMA_VERSION,
+        contacts,
+        groups,
+    };
+    serde_json::to_string_pretty(&envelope)
+        .map_err(|e| AppError::Parse(format!("Saving error...: {}", e)))
+}
+
+/// Like `to_document_with_groups`, writing straight to `writer` instead of
+/// building the whole document in memory first.
+pub fn write_document_with_groups<T: Serialize>(
+    writer: impl Write,
+    contacts: &[T],
+    groups: &[&Group],
+) -> Result<(), AppError> {
+    let envelope = Envelope {
+        schema_version: CURRENT_SCHEMA_VERSION,
+        contacts,
+        groups,
+    };
+    serde_json::to_writer_pretty(writer, &envelope)
+        .map_err(|e| AppError::Parse(format!("Saving error...: {}", e)))
+}
+
+/// Upgrades a single contact object of a file at `from_version` (1 for a
+/// bare array) to the current layout, for reading files one contact at a
+/// time. Every contact-level step leaves current contacts unchanged.
+pub fn upgrade_contact(contact: Value, from_version: u64) -> Result<Contact, AppError> {
+    let mut document = if from_version <= 1 {
+        json!([contact])
+    } else {
+        json!({ "schema_version": from_version, "contacts": [contact] })
+    };
+    for migration in MIGRATIONS.iter().filter(|m| m.from >= from_version.max(1)) {
+        document = (migration.apply)(document)?;
+    }
+    serde_json::from_value(document["contacts"][0].take())
+        .map_err(|e| AppError::Parse(format!("Error, JSON... : {}", e)))
+}
+
+fn schema_version(document: &Value) -> Result<u64, AppError> {
+    let version = match document {
+        Value::String(_) => 0,
+        Value::Array(_) => 1,
+        Value::Object(map) => map
+            .get("schema_version")
+            .and_then(Value::as_u64)
+            .ok_or(AppError::Parse("Missing schema_version".to_string()))?,
+        _ => return Err(AppError::Parse("Unrecognised contacts file".to_string())),
+    };
+    supported_version(version)
+}
+
+/// Rejects files written by a newer build.
+pub(crate) fn supported_version(version: u64) -> Result<u64, AppError> {
+    if version > CURRENT_SCHEMA_VERSION {
+        return Err(AppError::Parse(format!(
+            "Contacts file has schema version {}, this build only understands up to {}",
+            version, CURRENT_SCHEMA_VERSION
+        )));
+    }
+    Ok(version)
+}
+
+fn text_to_array(document: Value) -> Result<Value, AppError> {
+    let text = document.as_str().unwrap_or_default();
+
+    let contacts = text
+        .lines()
+        .filter(|l| !l.trim().is_empty())
+        .map(Contact::from_line)
+        .collect::<Result<Vec<_>, _>>()?;
+
+    serde_json::to_value(contacts).map_err(|e| AppError::Parse(e.to_string()))
+}
+
+fn array_to_envelope(mut document: Value) -> Result<Value, AppError> {
+    if let Some(contacts) = document.as_array_mut() {
+        for contact in contacts.iter_mut().filter_map(Value::as_object_mut) {
+            let valid = contact
+                .get("id")
+                .and_then(Value::as_str)
+                .is_some_and(|id| Uuid::parse_str(id).is_ok());
+            if !valid {
+                contact.insert("id".into(), json!(Uuid::new_v4()));
+            }
+        }
+    }
+
+    Ok(json!({
+        "schema_version": 2,
+        "contacts": document,
+    }))
+}
+
+fn split_names(mut document: Value) -> Result<Value, AppError> {
+    for contact in contacts_mut(&mut document) {
+        if contact.contains_key("given_name") || contact.contains_key("family_name") {
+            continue;
+        }
+
+        let mut parsed = Contact::default();
+        parsed.set_name(
+            contact
+                .get("name")
+                .and_then(Value::as_str)
+                .unwrap_or_default(),
+        );
+        let parts = [
+            ("prefix", parsed.prefix),
+            ("given_name", parsed.given_name),
+            ("middle_name", parsed.middle_name),
+            ("family_name", parsed.family_name),
+            ("suffix", parsed.suffix),
+        ];
+        for (key, value) in parts {
+            if let Some(value) = value {
+                contact.insert(key.into(), json!(value));
+            }
+        }
+    }
+
+    document["schema_version"] = json!(3);
+    Ok(document)
+}
+
+fn label_phones(mut document: Value) -> Result<Value, AppError> {
+    for contact in contacts_mut(&mut document) {
+        let Some(phones) = contact.get_mut("phone").and_then(Value::as_array_mut) else {
+            continue;
+        };
+        for phone in phones.iter_mut() {
+            if let Some(raw) = phone.as_str() {
+                *phone = json!(Phone::new(raw));
+            }
+        }
+    }
+
+    document["schema_version"] = json!(4);
+    Ok(document)
+}
+
+fn list_emails(mut document: Value) -> Result<Value, AppError> {
+    for contact in contacts_mut(&mut document) {
+        if let Some(address) = contact.get("email").and_then(Value::as_str) {
+            let mut emails = Vec::new();
+            if !address.trim().is_empty() {
+                emails.push(Email {
+                    primary: true,
+                    ..Email::new(address)
+                });
+            }
+            contact.insert("email".into(), json!(emails));
+        }
+    }
+
+    document["schema_version"] = json!(5);
+    Ok(document)
+}
+
+fn add_groups(mut document: Value) -> Result<Value, AppError> {
+    if document.get("groups").is_none() {
+        document["groups"] = json!([]);
+    }
+
+    document["schema_version"] = json!(6);
+    Ok(document)
+}
+
+/// The contact objects of an enveloped document.
+fn contacts_mut(document: &mut Value) -> impl Iterator<Item = &mut Map<String, Value>> {
+    document["contacts"]
+        .as_array_mut()
+        .into_iter()
+        .flatten()
+        .filter_map(Value::as_object_mut)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_upgrades_every_old_layout() {
+        let text = "Dr. Alice May O'Brien,08123456789,a@example.com\n";
+        assert!(load(text).is_err());
+        let (document, report) = load_legacy(text).unwrap();
+        let contacts = document.contacts;
+        assert_eq!(contacts[0].phone, vec![Phone::new("08123456789")]);
+        assert_eq!(contacts[0].family_name.as_deref(), Some("O'Brien"));
+        assert_eq!(contacts[0].middle_name.as_deref(), Some("May"));
+        assert_eq!(contacts[0].prefix.as_deref(), Some("Dr."));
+        assert_eq!(report.from_version, 0);
+        assert_eq!(report.steps.len(), MIGRATIONS.len());
+
+        let bare = r#"[{"id": "7", "name": "Bob", "phone": ["1"], "email": "b@x.com"}]"#;
+        let (contacts, report) = load(bare).unwrap();
+        assert!(!contacts[0].id.is_nil());
+        assert_eq!(contacts[0].given_name.as_deref(), Some("Bob"));
+        assert_eq!(report.from_version, 1);
+
+        let saved = to_document(&contacts).unwrap();
+        let (reloaded, report) = load(&saved).unwrap();
+        assert!(report.is_current());
+        assert_eq!(reloaded[0].id, contacts[0].id);
+        assert!(saved.contains(r#""label": "mobile""#));
+        assert_eq!(reloaded[0].primary_email(), "b@x.com");
+        assert!(reloaded[0].email[0].primary);
+
+        assert!(load(r#"{"schema_version": 99, "contacts": []}"#).is_err());
+        assert!(load(" \n").is_err());
+        assert!(load_legacy("").is_err());
+    }
+}
@@ -0,0 +1,204 @@
+use std::{
+    env, fmt,
+    hash::{Hash, Hasher},
+    str::FromStr,
+    sync::OnceLock,
+};
+
+use dotenv::dotenv;
+use phonenumber::{Mode, country};
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PhoneLabel {
+    #[default]
+    Mobile,
+    Work,
+    Home,
+    Fax,
+    Other,
+}
+
+impl fmt::Display for PhoneLabel {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let label = match self {
+            PhoneLabel::Mobile => "mobile",
+            PhoneLabel::Work => "work",
+            PhoneLabel::Home => "home",
+            PhoneLabel::Fax => "fax",
+            PhoneLabel::Other => "other",
+        };
+        write!(f, "{}", label)
+    }
+}
+
+impl FromStr for PhoneLabel {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_lowercase().as_str() {
+            "mobile" | "cell" => Ok(PhoneLabel::Mobile),
+            "work" => Ok(PhoneLabel::Work),
+            "home" => Ok(PhoneLabel::Home),
+            "fax" => Ok(PhoneLabel::Fax),
+            "other" => Ok(PhoneLabel::Other),
+            _ => Err(format!(
+                "Unknown phone label '{}'. Use: mobile | work | home | fax | other",
+                s
+            )),
+        }
+    }
+}
+
+/// A phone number as typed plus its E.164 form. Two phones are equal when
+/// their normalized numbers are, so `0801…` and `+234801…` match once the
+/// default region is `NG`.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(from = "PhoneRepr")]
+pub struct Phone {
+    pub label: PhoneLabel,
+    pub raw: String,
+    /// `None` when the number can't be parsed (e.g. no country code and no default region).
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub e164: Option<String>,
+}
+
+/// Older data stores phones as bare strings.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum PhoneRepr {
+    Raw(String),
+    Full {
+        #[serde(default)]
+        label: PhoneLabel,
+        raw: String,
+        #[serde(default)]
+        e164: Option<String>,
+    },
+}
+
+impl From<PhoneRepr> for Phone {
+    fn from(repr: PhoneRepr) -> Self {
+        match repr {
+            PhoneRepr::Raw(raw) => Phone::new(&raw),
+            PhoneRepr::Full { label, raw, e164 } => {
+                let e164 = e164.or_else(|| normalize(&raw));
+                Phone { label, raw, e164 }
+            }
+        }
+    }
+}
+
+impl Phone {
+    pub fn new(raw: &str) -> Self {
+        Self::with_label(raw, PhoneLabel::default())
+    }
+
+    pub fn with_label(raw: &str, label: PhoneLabel) -> Self {
+        Self {
+            label,
+            raw: raw.trim().to_string(),
+            e164: normalize(raw),
+        }
+    }
+
+    /// What duplicate detection compares: the E.164 form, or the bare digits
+    /// when the number couldn't be normalized.
+    pub fn key(&self) -> String {
+        self.e164.clone().unwrap_or_else(|| digits(&self.raw))
+    }
+}
+
+impl From<&str> for Phone {
+    fn from(raw: &str) -> Self {
+        Phone::new(raw)
+    }
+}
+
+impl PartialEq for Phone {
+    fn eq(&self, other: &Self) -> bool {
+        self.key() == other.key()
+    }
+}
+
+impl Eq for Phone {}
+
+impl Hash for Phone {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.key().hash(state);
+    }
+}
+
+impl fmt::Display for Phone {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} ({})", self.raw, self.label)
+    }
+}
+
+/// Region for numbers written without a country code, from `ROLODEX_DEFAULT_REGION`
+/// (an ISO country code such as `NG` or `US`, also read from `.env`).
+pub fn default_region() -> Option<country::Id> {
+    static REGION: OnceLock<Option<country::Id>> = OnceLock::new();
+
+    *REGION.get_or_init(|| {
+        dotenv().ok();
+        env::var("ROLODEX_DEFAULT_REGION")
+            .ok()?
+            .trim()
+            .to_uppercase()
+            .parse()
+            .ok()
+    })
+}
+
+pub fn normalize(raw: &str) -> Option<String> {
+    normalize_in(raw, default_region())
+}
+
+pub fn normalize_in(raw: &str, region: Option<country::Id>) -> Option<String> {
+    let number = phonenumber::parse(region, raw.trim()).ok()?;
+    Some(number.format().mode(Mode::E164).to_string())
+}
+
+fn digits(raw: &str) -> String {
+    raw.chars().filter(char::is_ascii_digit).collect()
+}
+
+/// Joins phones for one-line output.
+pub fn format_phones(phones: &[Phone]) -> String {
+    phones
+        .iter()
+        .map(Phone::to_string)
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_local_and_international_numbers_match_in_region() {
+        let region = Some(country::Id::NG);
+        assert_eq!(
+            normalize_in("0801 234 5678", region),
+            normalize_in("+2348012345678", None)
+        );
+        assert_eq!(
+            normalize_in("+1-202-555-0147", None).as_deref(),
+            Some("+12025550147")
+        );
+    }
+
+    #[test]
+    fn test_phone_deserializes_from_string_or_object() {
+        let phones: Vec<Phone> = serde_json::from_str(
+            r#"["+2348012345678", {"label": "work", "raw": "+2348012345678"}]"#,
+        )
+        .unwrap();
+        assert_eq!(phones[0], phones[1]);
+        assert_eq!(phones[1].label, PhoneLabel::Work);
+        assert_eq!(phones[1].e164.as_deref(), Some("+2348012345678"));
+    }
+}
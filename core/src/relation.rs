@@ -0,0 +1,122 @@
+use std::{collections::HashMap, fmt, str::FromStr};
+
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum RelationKind {
+    Spouse,
+    Assistant,
+    Manager,
+    ReferredBy,
+    Colleague,
+}
+
+impl fmt::Display for RelationKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let kind = match self {
+            RelationKind::Spouse => "spouse",
+            RelationKind::Assistant => "assistant",
+            RelationKind::Manager => "manager",
+            RelationKind::ReferredBy => "referred-by",
+            RelationKind::Colleague => "colleague",
+        };
+        write!(f, "{}", kind)
+    }
+}
+
+impl FromStr for RelationKind {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_lowercase().replace('_', "-").as_str() {
+            "spouse" | "partner" => Ok(RelationKind::Spouse),
+            "assistant" => Ok(RelationKind::Assistant),
+            "manager" => Ok(RelationKind::Manager),
+            "referred-by" | "referrer" => Ok(RelationKind::ReferredBy),
+            "colleague" => Ok(RelationKind::Colleague),
+            _ => Err(format!(
+                "Unknown relationship '{}'. Use: spouse | assistant | manager | referred-by | colleague",
+                s
+            )),
+        }
+    }
+}
+
+/// A link from one contact to another: `contact_id` is this contact's `kind`
+/// (e.g. their manager).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct Relation {
+    pub kind: RelationKind,
+    pub contact_id: Uuid,
+}
+
+impl Relation {
+    /// `kind:uuid`, as used in a CSV cell.
+    pub fn to_cell(&self) -> String {
+        format!("{}:{}", self.kind, self.contact_id)
+    }
+
+    pub fn from_cell(cell: &str) -> Result<Self, String> {
+        let (kind, id) = cell
+            .split_once(':')
+            .ok_or(format!("Expected KIND:ID, got '{}'", cell))?;
+        Ok(Self {
+            kind: kind.parse()?,
+            contact_id: id.trim().parse().map_err(|e| format!("{}", e))?,
+        })
+    }
+}
+
+/// Points relations at the ids in `ids` (old id -> new id); returns whether
+/// anything changed. Duplicates created by the rewrite are dropped.
+pub fn remap(relations: &mut Vec<Relation>, ids: &HashMap<Uuid, Uuid>) -> bool {
+    let mut changed = false;
+    let mut remapped: Vec<Relation> = Vec::with_capacity(relations.len());
+    for relation in relations.iter() {
+        let contact_id = ids
+            .get(&relation.contact_id)
+            .copied()
+            .unwrap_or(relation.contact_id);
+        changed |= contact_id != relation.contact_id;
+        let relation = Relation {
+            contact_id,
+            ..*relation
+        };
+        if !remapped.contains(&relation) {
+            remapped.push(relation);
+        }
+    }
+    *relations = remapped;
+    changed
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_relation_cells_and_remap() {
+        let (old, new) = (Uuid::new_v4(), Uuid::new_v4());
+        let relation = Relation {
+            kind: RelationKind::ReferredBy,
+            contact_id: old,
+        };
+        assert_eq!(relation.to_cell(), format!("referred-by:{}", old));
+        assert_eq!(Relation::from_cell(&relation.to_cell()).unwrap(), relation);
+        assert!(Relation::from_cell("boss:x").is_err());
+
+        let mut relations = vec![
+            relation,
+            Relation {
+                contact_id: new,
+                ..relation
+            },
+        ];
+        assert!(remap(&mut relations, &HashMap::from([(old, new)])));
+        assert_eq!(relations.len(), 1);
+        assert_eq!(relations[0].contact_id, new);
+        assert!(!remap(&mut relations, &HashMap::from([(old, new)])));
+    }
+}
@@ -1,33 +1,191 @@
 use std::{
-    collections::HashMap,
-    fs,
-    path::PathBuf,
-    sync::{Arc, Mutex},
+    collections::{BTreeMap, HashMap},
+    fmt,
+    fs::{self, File, OpenOptions, TryLockError},
+    io::{BufWriter, Read, Seek, SeekFrom, Write},
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex, MutexGuard},
+    thread,
+    time::{Duration, Instant},
 };
 
-use chrono::Utc;
+use chrono::{DateTime, Utc};
 use reqwest::{blocking::Client, header::CONTENT_TYPE};
+use rusqlite::{Connection, ToSql, params};
+use serde::{Deserialize, Serialize};
 use uuid::Uuid;
 
 use crate::{
-    domain::{Contact, ContactRaw},
+    address::Address,
+    attachment::Attachment,
+    dates::LabelledDate,
+    domain::{Contact, ContactFilter, ContactPatch},
+    email::Email,
     error::AppError,
+    group::Group,
     helpers::get_key,
+    migrations::{self, MigrationReport},
+    phone::{self, Phone},
+    relation::Relation,
+    stream::{self, Format},
 };
 
-// pub trait ContactStore: Send + Sync {
-//     fn load(&self) -> Result<HashMap<Uuid, Contact>, AppError>;
-//     fn save(&self, contacts: HashMap<Uuid, Contact>) -> Result<(), AppError>;
-//     // fn search(&self, name: String, domain: String, fuzzy: String) -> Result<Vec<usize>, AppError>;
-//     // fn merge_from_file(&self, other_path: &str, policy: MergePolicy) -> Result<(), AppError>;
-// }
-pub trait ContactStore {
+/// Callback handed the full contact map by `ContactStore::modify`.
+pub type ModifyFn<'a> = dyn FnMut(&mut HashMap<Uuid, Contact>) -> Result<(), AppError> + 'a;
+
+/// Contacts and groups, as held together by the file-based stores.
+type StoredData = (HashMap<Uuid, Contact>, HashMap<Uuid, Group>);
+
+/// Callback handed every group by `ContactStore::modify_groups`.
+pub type ModifyGroupsFn<'a> = dyn FnMut(&mut HashMap<Uuid, Group>) -> Result<(), AppError> + 'a;
+
+pub trait ContactStore: Send + Sync {
     fn load(&self) -> Result<HashMap<Uuid, Contact>, AppError>;
     fn save(&self, contacts: HashMap<Uuid, Contact>) -> Result<(), AppError>;
+
+    // Per-record operations. The defaults go through a full load/save round trip;
+    // backends that can touch a single record override them.
+
+    fn get(&self, id: Uuid) -> Result<Option<Contact>, AppError> {
+        Ok(self.load()?.remove(&id))
+    }
+
+    /// Inserts the contact, replacing any existing record with the same id.
+    fn insert(&self, contact: Contact) -> Result<(), AppError> {
+        let mut contacts = self.load()?;
+        contacts.insert(contact.id, contact);
+        self.save(contacts)
+    }
+
+    /// Patches live contact `id`; returns it as it was before and after, so
+    /// the change can be recorded without a separate, possibly stale, read.
+    fn update(&self, id: Uuid, patch: &ContactPatch) -> Result<(Contact, Contact), AppError> {
+        let mut contacts = self.load()?;
+        let change = apply_patch(&mut contacts, id, patch)?;
+        self.save(contacts)?;
+        Ok(change)
+    }
+
+    /// Stores `contact` only if the stored copy is still at `expected_revision`,
+    /// so a concurrent edit is reported as `AppError::Conflict` instead of lost.
+    fn replace(&self, contact: Contact, expected_revision: u64) -> Result<(), AppError> {
+        self.modify(&mut |contacts| replace_checked(contacts, contact.clone(), expected_revision))
+    }
+
+    /// Deletes the record for good; see `trash` for the soft delete.
+    fn remove(&self, id: Uuid) -> Result<Option<Contact>, AppError> {
+        let mut contacts = self.load()?;
+        let removed = contacts.remove(&id);
+        if removed.is_some() {
+            self.save(contacts)?;
+        }
+        Ok(removed)
+    }
+
+    /// Moves a live contact to the trash and returns its tombstone.
+    fn trash(&self, id: Uuid) -> Result<Option<Contact>, AppError> {
+        let mut trashed = None;
+        self.modify(&mut |contacts| {
+            trashed = contacts.get_mut(&id).filter(|c| !c.is_deleted()).map(|c| {
+                c.trash(Utc::now());
+                c.clone()
+            });
+            Ok(())
+        })?;
+        Ok(trashed)
+    }
+
+    fn list(&self, filter: &ContactFilter) -> Result<Vec<Contact>, AppError> {
+        Ok(self
+            .load()?
+            .into_values()
+            .filter(|c| filter.matches(c))
+            .collect())
+    }
+
+    /// Runs a whole load -> modify -> save cycle for batch operations (import, sync).
+    /// Backends with locking hold their lock for the entire cycle.
+    fn modify(&self, f: &mut ModifyFn) -> Result<(), AppError> {
+        let mut contacts = self.load()?;
+        f(&mut contacts)?;
+        self.save(contacts)
+    }
+
+    /// Folds an append-only history into a single snapshot. Only meaningful
+    /// for backends that keep one (`JournalStore`).
+    fn compact(&self) -> Result<(), AppError> {
+        Err(AppError::Validation(
+            "This store does not support compaction".to_string(),
+        ))
+    }
+
+    // Groups are kept next to the contacts by the local backends.
+
+    fn load_groups(&self) -> Result<HashMap<Uuid, Group>, AppError> {
+        Err(AppError::Validation(
+            "This store does not support groups".to_string(),
+        ))
+    }
+
+    fn save_groups(&self, _groups: HashMap<Uuid, Group>) -> Result<(), AppError> {
+        Err(AppError::Validation(
+            "This store does not support groups".to_string(),
+        ))
+    }
+
+    /// Load -> modify -> save for groups, under the same lock as `modify`.
+    fn modify_groups(&self, f: &mut ModifyGroupsFn) -> Result<(), AppError> {
+        let mut groups = self.load_groups()?;
+        f(&mut groups)?;
+        self.save_groups(groups)
+    }
+}
+
+fn apply_patch(
+    contacts: &mut HashMap<Uuid, Contact>,
+    id: Uuid,
+    patch: &ContactPatch,
+) -> Result<(Contact, Contact), AppError> {
+    let contact = contacts
+        .get_mut(&id)
+        .filter(|c| !c.is_deleted())
+        .ok_or(AppError::NotFound("Contact not found".to_string()))?;
+    patch.check_revision(contact)?;
+    let before = contact.clone();
+    patch.apply(contact);
+    Ok((before, contact.clone()))
+}
+
+/// Whether two versions of a contact differ in any stored field; `Contact`'s
+/// own `==` only looks at the name and phones.
+fn differs(a: &Contact, b: &Contact) -> bool {
+    serde_json::to_value(a).ok() != serde_json::to_value(b).ok()
+}
+
+fn replace_checked(
+    contacts: &mut HashMap<Uuid, Contact>,
+    contact: Contact,
+    expected_revision: u64,
+) -> Result<(), AppError> {
+    check_stored_revision(contacts.get(&contact.id), expected_revision)?;
+    contacts.insert(contact.id, contact);
+    Ok(())
+}
+
+fn check_stored_revision(stored: Option<&Contact>, expected_revision: u64) -> Result<(), AppError> {
+    let stored = stored.ok_or(AppError::NotFound("Contact not found".to_string()))?;
+    if stored.revision != expected_revision {
+        return Err(AppError::Conflict(format!(
+            "Contact {} was changed by someone else (revision {}, expected {})",
+            stored.id, stored.revision, expected_revision
+        )));
+    }
+    Ok(())
 }
 
 pub struct MemStore {
-    contacts: std::cell::RefCell<HashMap<Uuid, Contact>>,
+    contacts: Mutex<HashMap<Uuid, Contact>>,
+    groups: Mutex<HashMap<Uuid, Group>>,
 }
 
 #[allow(clippy::new_without_default)]
@@ -44,76 +202,1688 @@ impl MemStore {
 
         let mut contacts_hashmap: HashMap<Uuid, Contact> = HashMap::new();
 
-        contacts_hashmap.insert(sample_contacts.id, sample_contacts);
+        contacts_hashmap.insert(sample_contacts.id, sample_contacts);
+
+        Self {
+            contacts: Mutex::new(contacts_hashmap),
+            groups: Mutex::new(HashMap::new()),
+        }
+    }
+}
+
+impl ContactStore for MemStore {
+    fn load(&self) -> Result<HashMap<Uuid, Contact>, AppError> {
+        Ok(self.contacts.lock().unwrap().clone())
+    }
+
+    fn save(&self, contacts: HashMap<Uuid, Contact>) -> Result<(), AppError> {
+        let mut contacts_hashmap = self.contacts.lock().unwrap();
+        contacts_hashmap.clear();
+
+        for (_key, contact) in contacts {
+            contacts_hashmap.insert(contact.id, contact);
+        }
+        Ok(())
+    }
+
+    fn get(&self, id: Uuid) -> Result<Option<Contact>, AppError> {
+        Ok(self.contacts.lock().unwrap().get(&id).cloned())
+    }
+
+    fn insert(&self, contact: Contact) -> Result<(), AppError> {
+        self.contacts.lock().unwrap().insert(contact.id, contact);
+        Ok(())
+    }
+
+    fn update(&self, id: Uuid, patch: &ContactPatch) -> Result<(Contact, Contact), AppError> {
+        apply_patch(&mut self.contacts.lock().unwrap(), id, patch)
+    }
+
+    fn replace(&self, contact: Contact, expected_revision: u64) -> Result<(), AppError> {
+        replace_checked(
+            &mut self.contacts.lock().unwrap(),
+            contact,
+            expected_revision,
+        )
+    }
+
+    fn remove(&self, id: Uuid) -> Result<Option<Contact>, AppError> {
+        Ok(self.contacts.lock().unwrap().remove(&id))
+    }
+
+    fn list(&self, filter: &ContactFilter) -> Result<Vec<Contact>, AppError> {
+        Ok(self
+            .contacts
+            .lock()
+            .unwrap()
+            .values()
+            .filter(|c| filter.matches(c))
+            .cloned()
+            .collect())
+    }
+
+    fn load_groups(&self) -> Result<HashMap<Uuid, Group>, AppError> {
+        Ok(self.groups.lock().unwrap().clone())
+    }
+
+    fn save_groups(&self, groups: HashMap<Uuid, Group>) -> Result<(), AppError> {
+        *self.groups.lock().unwrap() = groups;
+        Ok(())
+    }
+
+    fn modify_groups(&self, f: &mut ModifyGroupsFn) -> Result<(), AppError> {
+        f(&mut self.groups.lock().unwrap())
+    }
+}
+
+#[derive(Clone)]
+pub struct FileStore {
+    path: PathBuf,
+    lock: Arc<Mutex<()>>, // file write lock
+    lock_timeout: Duration,
+    legacy_text: bool,
+}
+
+/// Held across a load -> modify -> save cycle. The mutex serialises threads of
+/// this process; the OS lock on the lock file serialises other processes
+/// (e.g. the CLI while `rolodex_api` is running). Dropping it releases both,
+/// the file first: fields drop in order, and the next thread in must not find
+/// the OS lock still held.
+struct FileLock<'a> {
+    _file: File,
+    _guard: MutexGuard<'a, ()>,
+}
+
+impl FileStore {
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        let path = path.into();
+        let legacy_text = path.extension().is_some_and(|e| e == "txt");
+        Self {
+            path,
+            lock: Arc::new(Mutex::new(())),
+            lock_timeout: Duration::ZERO,
+            legacy_text,
+        }
+    }
+
+    /// Also reads the old `name,phone,email` text format, which `.txt` stores
+    /// always are. Any other store must be JSON, so an empty or garbled file is
+    /// reported (and recovered from the backup) rather than read as no contacts.
+    pub fn with_legacy_text(mut self) -> Self {
+        self.legacy_text = true;
+        self
+    }
+
+    /// How long to wait for another process to release the lock before giving up.
+    pub fn with_lock_timeout(mut self, timeout: Duration) -> Self {
+        self.lock_timeout = timeout;
+        self
+    }
+
+    /// Advisory lock file; the data file itself is replaced on every save.
+    pub fn lock_path(&self) -> PathBuf {
+        with_suffix(&self.path, ".lock")
+    }
+
+    fn acquire(&self) -> Result<FileLock<'_>, AppError> {
+        FileLock::acquire(&self.lock, &self.path, self.lock_timeout)
+    }
+
+    /// Previous copy of the file, kept around by every save.
+    pub fn backup_path(&self) -> PathBuf {
+        with_suffix(&self.path, ".bak")
+    }
+
+    fn write(
+        &self,
+        contacts: &HashMap<Uuid, Contact>,
+        groups: &HashMap<Uuid, Group>,
+        intact: bool,
+    ) -> Result<(), AppError> {
+        let contacts_vec: Vec<&Contact> = contacts.values().collect();
+        let mut groups_vec: Vec<&Group> = groups.values().collect();
+        groups_vec.sort_by_key(|g| g.created_at);
+
+        // An unreadable file must not replace the backup it was recovered from
+        if intact && self.path.exists() {
+            fs::copy(&self.path, self.backup_path())?;
+        }
+        if self.is_ndjson() {
+            // A stable order keeps diffs of the file down to the changed lines
+            let mut contacts_vec = contacts_vec;
+            contacts_vec.sort_by_key(|c| (c.created_at, c.id));
+            return write_atomic_with(&self.path, |out| {
+                stream::write_ndjson_document(out, &contacts_vec, &groups_vec)
+            });
+        }
+        write_atomic_with(&self.path, |out| {
+            migrations::write_document_with_groups(out, &contacts_vec, &groups_vec)
+        })
+    }
+
+    /// `.jsonl` and `.ndjson` stores keep one contact per line.
+    fn is_ndjson(&self) -> bool {
+        Format::from_path(&self.path) == Format::Ndjson
+    }
+
+    /// Streams the file in, so it is never held in memory next to the map.
+    fn read(&self, path: &Path) -> Result<StoredData, AppError> {
+        if self.legacy_text {
+            let (document, _) = migrations::load_legacy(&fs::read_to_string(path)?)?;
+            let contacts = document.contacts.into_iter().map(|c| (c.id, c)).collect();
+            let groups = document.groups.into_iter().map(|g| (g.id, g)).collect();
+            return Ok((contacts, groups));
+        }
+        let (reader, mut progress) = stream::open(path, format!("Loading {}", path.display()))?;
+
+        let mut contacts_hashmap: HashMap<Uuid, Contact> = HashMap::new();
+        let mut on_contact = |contact: Contact| {
+            progress.tick();
+            contacts_hashmap.insert(contact.id, contact);
+            Ok(())
+        };
+        let groups = if self.is_ndjson() {
+            stream::read_ndjson_store(reader, &mut on_contact)?
+        } else {
+            stream::read_json(reader, &mut on_contact)?
+        };
+        progress.finish();
+        let groups = groups.into_iter().map(|g| (g.id, g)).collect();
+        Ok((contacts_hashmap, groups))
+    }
+
+    /// Contacts and groups, falling back to the backup if the file is unreadable.
+    fn read_all(&self) -> Result<StoredData, AppError> {
+        Ok(self.read_latest()?.0)
+    }
+
+    /// Like `read_all`, also saying whether the file itself was readable (or
+    /// absent), i.e. whether the next write may back it up.
+    fn read_latest(&self) -> Result<(StoredData, bool), AppError> {
+        if !self.path.exists() {
+            return Ok(((HashMap::new(), HashMap::new()), true));
+        }
+
+        match self.read(&self.path) {
+            Ok(document) => Ok((document, true)),
+            Err(AppError::Parse(msg)) if self.backup_path().exists() => {
+                eprintln!(
+                    "⚠️ {} is unreadable ({}), recovering from {}",
+                    self.path.display(),
+                    msg,
+                    self.backup_path().display()
+                );
+                Ok((self.read(&self.backup_path())?, false))
+            }
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Reports which migrations loading the file runs, without writing anything.
+    pub fn check_schema(&self) -> Result<MigrationReport, AppError> {
+        if self.is_ndjson() {
+            let (reader, _) = stream::open(&self.path, "Checking")?;
+            let from_version = stream::ndjson_version(reader)?;
+            let (reader, _) = stream::open(&self.path, "Checking")?;
+            let mut contacts = 0;
+            stream::read_ndjson_store(reader, &mut |_| {
+                contacts += 1;
+                Ok(())
+            })?;
+            return Ok(MigrationReport::new(from_version, contacts));
+        }
+        let data = fs::read_to_string(&self.path)?;
+        if self.legacy_text {
+            return Ok(migrations::load_legacy(&data)?.1);
+        }
+        Ok(migrations::load(&data)?.1)
+    }
+}
+
+/// `contacts.json` + `.bak` -> `contacts.json.bak`
+pub(crate) fn with_suffix(path: &Path, suffix: &str) -> PathBuf {
+    let mut name = path.as_os_str().to_owned();
+    name.push(suffix);
+    PathBuf::from(name)
+}
+
+impl<'a> FileLock<'a> {
+    /// Locks `<path>.lock`, retrying until `timeout` runs out.
+    fn acquire(
+        mutex: &'a Mutex<()>,
+        path: &Path,
+        timeout: Duration,
+    ) -> Result<FileLock<'a>, AppError> {
+        let guard = mutex.lock().unwrap();
+
+        let file = OpenOptions::new()
+            .create(true)
+            .truncate(false)
+            .write(true)
+            .open(with_suffix(path, ".lock"))?;
+
+        let deadline = Instant::now() + timeout;
+        loop {
+            match file.try_lock() {
+                Ok(()) => {
+                    return Ok(FileLock {
+                        _file: file,
+                        _guard: guard,
+                    });
+                }
+                Err(TryLockError::WouldBlock) if Instant::now() < deadline => {
+                    thread::sleep(Duration::from_millis(100));
+                }
+                Err(TryLockError::WouldBlock) => {
+                    return Err(AppError::Locked(format!(
+                        "{} is in use by another process",
+                        path.display()
+                    )));
+                }
+                Err(TryLockError::Error(e)) => return Err(e.into()),
+            }
+        }
+    }
+}
+
+/// Writes to a temporary file next to `path`, fsyncs it and renames it over
+/// `path`, so readers see either the old or the new file and never half of one.
+pub(crate) fn write_atomic(path: &Path, data: &[u8]) -> Result<(), AppError> {
+    write_atomic_with(path, |out| Ok(out.write_all(data)?))
+}
+
+/// Like `write_atomic`, with `write` producing the content a piece at a time.
+pub(crate) fn write_atomic_with(
+    path: &Path,
+    write: impl FnOnce(&mut BufWriter<File>) -> Result<(), AppError>,
+) -> Result<(), AppError> {
+    let tmp_path = with_suffix(path, ".tmp");
+
+    let mut out = BufWriter::new(File::create(&tmp_path)?);
+    write(&mut out)?;
+    let file = out.into_inner().map_err(|e| e.into_error())?;
+    file.sync_all()?;
+    drop(file);
+
+    fs::rename(&tmp_path, path)?;
+
+    // Persist the rename itself; not every platform lets us open a directory.
+    if let Some(dir) = path.parent().filter(|d| !d.as_os_str().is_empty())
+        && let Ok(dir) = File::open(dir)
+    {
+        let _ = dir.sync_all();
+    }
+    Ok(())
+}
+
+impl ContactStore for FileStore {
+    fn load(&self) -> Result<HashMap<Uuid, Contact>, AppError> {
+        Ok(self.read_all()?.0)
+    }
+
+    fn save(&self, contacts: HashMap<Uuid, Contact>) -> Result<(), AppError> {
+        let _lock = self.acquire()?;
+        let ((_, groups), intact) = self.read_latest()?;
+        self.write(&contacts, &groups, intact)
+    }
+
+    // The whole file is rewritten either way, but holding the lock across the
+    // load -> modify -> write cycle keeps concurrent writers from losing updates.
+
+    fn insert(&self, contact: Contact) -> Result<(), AppError> {
+        let _lock = self.acquire()?;
+        let ((mut contacts, groups), intact) = self.read_latest()?;
+        contacts.insert(contact.id, contact);
+        self.write(&contacts, &groups, intact)
+    }
+
+    fn update(&self, id: Uuid, patch: &ContactPatch) -> Result<(Contact, Contact), AppError> {
+        let _lock = self.acquire()?;
+        let ((mut contacts, groups), intact) = self.read_latest()?;
+        let change = apply_patch(&mut contacts, id, patch)?;
+        self.write(&contacts, &groups, intact)?;
+        Ok(change)
+    }
+
+    fn remove(&self, id: Uuid) -> Result<Option<Contact>, AppError> {
+        let _lock = self.acquire()?;
+        let ((mut contacts, groups), intact) = self.read_latest()?;
+        let removed = contacts.remove(&id);
+        if removed.is_some() {
+            self.write(&contacts, &groups, intact)?;
+        }
+        Ok(removed)
+    }
+
+    fn modify(&self, f: &mut ModifyFn) -> Result<(), AppError> {
+        let _lock = self.acquire()?;
+        let ((mut contacts, groups), intact) = self.read_latest()?;
+        f(&mut contacts)?;
+        self.write(&contacts, &groups, intact)
+    }
+
+    fn load_groups(&self) -> Result<HashMap<Uuid, Group>, AppError> {
+        Ok(self.read_all()?.1)
+    }
+
+    fn save_groups(&self, groups: HashMap<Uuid, Group>) -> Result<(), AppError> {
+        let _lock = self.acquire()?;
+        let ((contacts, _), intact) = self.read_latest()?;
+        self.write(&contacts, &groups, intact)
+    }
+
+    fn modify_groups(&self, f: &mut ModifyGroupsFn) -> Result<(), AppError> {
+        let _lock = self.acquire()?;
+        let ((contacts, mut groups), intact) = self.read_latest()?;
+        f(&mut groups)?;
+        self.write(&contacts, &groups, intact)
+    }
+}
+
+/// Contacts in a SQLite database: one row per contact plus `phones`, `emails`,
+/// `addresses`, `custom_fields`, `dates`, `relations`, `attachments` and `tags` tables, so
+/// filters and single-record writes run in the database.
+pub struct SqliteStore {
+    conn: Mutex<Connection>,
+}
+
+type SqliteMigration = fn(&Connection) -> Result<(), AppError>;
+
+/// Applied in order on open; `PRAGMA user_version` counts how many already ran.
+const SQLITE_MIGRATIONS: &[SqliteMigration] = &[
+    |conn| Ok(conn.execute_batch(SQLITE_SCHEMA)?),
+    |conn| Ok(conn.execute_batch("ALTER TABLE contacts ADD COLUMN deleted_at TEXT;")?),
+    SqliteStore::add_name_parts,
+    SqliteStore::add_phone_labels,
+    |conn| Ok(conn.execute_batch(SQLITE_EMAILS)?),
+    |conn| Ok(conn.execute_batch(SQLITE_ORGANIZATIONS)?),
+    |conn| Ok(conn.execute_batch(SQLITE_NOTES)?),
+    |conn| Ok(conn.execute_batch(SQLITE_DATES)?),
+    |conn| Ok(conn.execute_batch(SQLITE_RELATIONS)?),
+    |conn| Ok(conn.execute_batch(SQLITE_GROUPS)?),
+    |conn| Ok(conn.execute_batch(SQLITE_ATTACHMENTS)?),
+    SqliteStore::add_phone_keys,
+    |conn| Ok(conn.execute_batch(SQLITE_NAME_INDEXES)?),
+];
+
+const NAME_PART_COLUMNS: [&str; 6] = [
+    "prefix",
+    "given_name",
+    "middle_name",
+    "family_name",
+    "suffix",
+    "nickname",
+];
+
+const SQLITE_SCHEMA: &str = "
+    CREATE TABLE IF NOT EXISTS contacts (
+        id           TEXT PRIMARY KEY,
+        name         TEXT NOT NULL,
+        email        TEXT NOT NULL,
+        email_domain TEXT NOT NULL,
+        created_at   TEXT NOT NULL,
+        updated_at   TEXT NOT NULL,
+        revision     INTEGER NOT NULL DEFAULT 0
+    );
+    CREATE INDEX IF NOT EXISTS idx_contacts_name ON contacts (lower(name));
+    CREATE INDEX IF NOT EXISTS idx_contacts_domain ON contacts (email_domain);
+
+    CREATE TABLE IF NOT EXISTS phones (
+        contact_id TEXT NOT NULL REFERENCES contacts (id) ON DELETE CASCADE,
+        position   INTEGER NOT NULL,
+        number     TEXT NOT NULL,
+        PRIMARY KEY (contact_id, position)
+    );
+
+    CREATE TABLE IF NOT EXISTS tags (
+        contact_id TEXT NOT NULL REFERENCES contacts (id) ON DELETE CASCADE,
+        position   INTEGER NOT NULL,
+        tag        TEXT NOT NULL,
+        PRIMARY KEY (contact_id, position)
+    );
+    CREATE INDEX IF NOT EXISTS idx_tags_tag ON tags (tag);
+";
+
+/// Every address of a contact; `contacts.email` keeps a copy of the primary one.
+const SQLITE_EMAILS: &str = "
+    CREATE TABLE IF NOT EXISTS emails (
+        contact_id TEXT NOT NULL REFERENCES contacts (id) ON DELETE CASCADE,
+        position   INTEGER NOT NULL,
+        address    TEXT NOT NULL,
+        label      TEXT NOT NULL DEFAULT 'personal',
+        is_primary INTEGER NOT NULL DEFAULT 0,
+        domain     TEXT NOT NULL,
+        PRIMARY KEY (contact_id, position)
+    );
+    CREATE INDEX IF NOT EXISTS idx_emails_domain ON emails (domain);
+
+    INSERT INTO emails (contact_id, position, address, is_primary, domain)
+        SELECT id, 0, email, 1, email_domain FROM contacts WHERE email <> '';
+";
+
+const SQLITE_ORGANIZATIONS: &str = "
+    ALTER TABLE contacts ADD COLUMN organization TEXT;
+    ALTER TABLE contacts ADD COLUMN title TEXT;
+    ALTER TABLE contacts ADD COLUMN department TEXT;
+    CREATE INDEX IF NOT EXISTS idx_contacts_organization ON contacts (lower(organization));
+
+    CREATE TABLE IF NOT EXISTS addresses (
+        contact_id  TEXT NOT NULL REFERENCES contacts (id) ON DELETE CASCADE,
+        position    INTEGER NOT NULL,
+        label       TEXT NOT NULL DEFAULT 'home',
+        street      TEXT,
+        city        TEXT,
+        region      TEXT,
+        postal_code TEXT,
+        country     TEXT,
+        PRIMARY KEY (contact_id, position)
+    );
+    CREATE INDEX IF NOT EXISTS idx_addresses_city ON addresses (lower(city));
+";
+
+const SQLITE_NOTES: &str = "
+    ALTER TABLE contacts ADD COLUMN notes TEXT;
+
+    CREATE TABLE IF NOT EXISTS custom_fields (
+        contact_id TEXT NOT NULL REFERENCES contacts (id) ON DELETE CASCADE,
+        key        TEXT NOT NULL,
+        value      TEXT NOT NULL,
+        PRIMARY KEY (contact_id, key)
+    );
+";
+
+/// Dates are stored as `PartialDate` text (`1990-05-17` or `--05-17`).
+const SQLITE_DATES: &str = "
+    ALTER TABLE contacts ADD COLUMN birthday TEXT;
+    ALTER TABLE contacts ADD COLUMN anniversary TEXT;
+
+    CREATE TABLE IF NOT EXISTS dates (
+        contact_id TEXT NOT NULL REFERENCES contacts (id) ON DELETE CASCADE,
+        position   INTEGER NOT NULL,
+        label      TEXT NOT NULL,
+        date       TEXT NOT NULL,
+        PRIMARY KEY (contact_id, position)
+    );
+";
+
+/// `related_id` is not a foreign key: links may outlive the contact they
+/// point at (see `Contact::relation_lines`).
+const SQLITE_RELATIONS: &str = "
+    CREATE TABLE IF NOT EXISTS relations (
+        contact_id TEXT NOT NULL REFERENCES contacts (id) ON DELETE CASCADE,
+        position   INTEGER NOT NULL,
+        kind       TEXT NOT NULL,
+        related_id TEXT NOT NULL,
+        PRIMARY KEY (contact_id, position)
+    );
+";
+
+/// Members are not foreign keys either: `write_all` rewrites every contact
+/// row, which would cascade away the memberships.
+const SQLITE_GROUPS: &str = "
+    CREATE TABLE IF NOT EXISTS groups (
+        id          TEXT PRIMARY KEY,
+        name        TEXT NOT NULL,
+        description TEXT,
+        created_at  TEXT NOT NULL,
+        updated_at  TEXT NOT NULL
+    );
+    CREATE UNIQUE INDEX IF NOT EXISTS idx_groups_name ON groups (lower(name));
+
+    CREATE TABLE IF NOT EXISTS group_members (
+        group_id   TEXT NOT NULL REFERENCES groups (id) ON DELETE CASCADE,
+        contact_id TEXT NOT NULL,
+        PRIMARY KEY (group_id, contact_id)
+    );
+";
+
+/// References only: the bytes live in the `AttachmentStore` next to the
+/// database. The photo is the row with `is_photo` set.
+const SQLITE_ATTACHMENTS: &str = "
+    CREATE TABLE IF NOT EXISTS attachments (
+        contact_id TEXT NOT NULL REFERENCES contacts (id) ON DELETE CASCADE,
+        position   INTEGER NOT NULL,
+        is_photo   INTEGER NOT NULL,
+        hash       TEXT NOT NULL,
+        name       TEXT NOT NULL,
+        media_type TEXT NOT NULL,
+        size       INTEGER NOT NULL,
+        PRIMARY KEY (contact_id, is_photo, position)
+    );
+";
+
+/// The name filter compares `lower(...)` of each of these columns (see
+/// `name_condition`); `idx_contacts_name` already covers `name`.
+const SQLITE_NAME_INDEXES: &str = "
+    CREATE INDEX IF NOT EXISTS idx_contacts_given_name ON contacts (lower(given_name));
+    CREATE INDEX IF NOT EXISTS idx_contacts_family_name ON contacts (lower(family_name));
+    CREATE INDEX IF NOT EXISTS idx_contacts_nickname ON contacts (lower(nickname));
+";
+
+/// Full name, given name, family name or nickname equal to parameter
+/// `param`, ignoring case. One `=` per column, so each can use its index.
+fn name_condition(param: usize) -> String {
+    ["name", "given_name", "family_name", "nickname"]
+        .iter()
+        .map(|column| format!("lower(c.{}) = lower(?{})", column, param))
+        .collect::<Vec<_>>()
+        .join(" OR ")
+}
+
+impl SqliteStore {
+    pub fn open(path: impl AsRef<Path>) -> Result<Self, AppError> {
+        Self::init(Connection::open(path)?)
+    }
+
+    pub fn open_in_memory() -> Result<Self, AppError> {
+        Self::init(Connection::open_in_memory()?)
+    }
+
+    fn init(mut conn: Connection) -> Result<Self, AppError> {
+        conn.execute_batch("PRAGMA foreign_keys = ON;")?;
+
+        let version: usize = conn.query_row("PRAGMA user_version", [], |row| row.get(0))?;
+        for (i, migration) in SQLITE_MIGRATIONS.iter().enumerate().skip(version) {
+            let tx = conn.transaction()?;
+            migration(&tx)?;
+            tx.pragma_update(None, "user_version", i + 1)?;
+            tx.commit()?;
+        }
+
+        Ok(Self {
+            conn: Mutex::new(conn),
+        })
+    }
+
+    /// Adds label and E.164 columns to `phones`, normalizing existing numbers.
+    fn add_phone_labels(conn: &Connection) -> Result<(), AppError> {
+        conn.execute_batch(
+            "ALTER TABLE phones ADD COLUMN label TEXT NOT NULL DEFAULT 'mobile';
+             ALTER TABLE phones ADD COLUMN e164 TEXT;
+             CREATE INDEX IF NOT EXISTS idx_phones_e164 ON phones (e164);",
+        )?;
+
+        let numbers: Vec<(String, usize, String)> = conn
+            .prepare("SELECT contact_id, position, number FROM phones")?
+            .query_map([], |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)))?
+            .collect::<Result<_, _>>()?;
+
+        for (id, position, number) in numbers {
+            conn.execute(
+                "UPDATE phones SET e164 = ?3 WHERE contact_id = ?1 AND position = ?2",
+                params![id, position, phone::normalize(&number)],
+            )?;
+        }
+        Ok(())
+    }
+
+    /// Adds the `Phone::key` column the phone filter compares, so numbers that
+    /// can't be normalized still match however they were typed.
+    fn add_phone_keys(conn: &Connection) -> Result<(), AppError> {
+        conn.execute_batch(
+            "ALTER TABLE phones ADD COLUMN key TEXT NOT NULL DEFAULT '';
+             CREATE INDEX IF NOT EXISTS idx_phones_key ON phones (key);",
+        )?;
+
+        let phones: Vec<(String, usize, Phone)> = conn
+            .prepare("SELECT contact_id, position, number, e164 FROM phones")?
+            .query_map([], |row| {
+                Ok((
+                    row.get(0)?,
+                    row.get(1)?,
+                    Phone {
+                        raw: row.get(2)?,
+                        e164: row.get(3)?,
+                        label: Default::default(),
+                    },
+                ))
+            })?
+            .collect::<Result<_, _>>()?;
+
+        for (id, position, phone) in phones {
+            conn.execute(
+                "UPDATE phones SET key = ?3 WHERE contact_id = ?1 AND position = ?2",
+                params![id, position, phone.key()],
+            )?;
+        }
+        Ok(())
+    }
+
+    /// Adds the name part columns and fills them by splitting existing names.
+    fn add_name_parts(conn: &Connection) -> Result<(), AppError> {
+        for column in NAME_PART_COLUMNS {
+            conn.execute_batch(&format!("ALTER TABLE contacts ADD COLUMN {} TEXT;", column))?;
+        }
+
+        let names: Vec<(String, String)> = conn
+            .prepare("SELECT id, name FROM contacts")?
+            .query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?
+            .collect::<Result<_, _>>()?;
+
+        for (id, name) in names {
+            let mut contact = Contact::default();
+            contact.set_name(&name);
+            conn.execute(
+                "UPDATE contacts SET prefix = ?2, given_name = ?3, middle_name = ?4,
+                    family_name = ?5, suffix = ?6
+                 WHERE id = ?1",
+                params![
+                    id,
+                    contact.prefix,
+                    contact.given_name,
+                    contact.middle_name,
+                    contact.family_name,
+                    contact.suffix,
+                ],
+            )?;
+        }
+        Ok(())
+    }
+
+    /// Loads every contact matching `condition` (a WHERE clause over `contacts c`)
+    /// together with its phones and tags.
+    fn query(
+        conn: &Connection,
+        condition: &str,
+        params: &[&dyn ToSql],
+    ) -> Result<Vec<Contact>, AppError> {
+        let mut stmt = conn.prepare(&format!(
+            "SELECT c.id, c.name, c.created_at, c.updated_at, c.revision, c.deleted_at,
+                    {}, c.organization, c.title, c.department, c.notes,
+                    c.birthday, c.anniversary
+             FROM contacts c WHERE {}",
+            NAME_PART_COLUMNS.map(|col| format!("c.{}", col)).join(", "),
+            condition
+        ))?;
+        let rows = stmt.query_map(params, |row| {
+            Ok((
+                row.get::<_, String>(0)?,
+                row.get::<_, String>(1)?,
+                row.get::<_, String>(2)?,
+                row.get::<_, String>(3)?,
+                row.get::<_, u64>(4)?,
+                row.get::<_, Option<String>>(5)?,
+                [
+                    row.get::<_, Option<String>>(6)?,
+                    row.get(7)?,
+                    row.get(8)?,
+                    row.get(9)?,
+                    row.get(10)?,
+                    row.get(11)?,
+                ],
+                [
+                    row.get::<_, Option<String>>(12)?,
+                    row.get(13)?,
+                    row.get(14)?,
+                    row.get(15)?,
+                ],
+                [row.get::<_, Option<String>>(16)?, row.get(17)?],
+            ))
+        })?;
+
+        let mut contacts = Vec::new();
+        let mut positions: HashMap<Uuid, usize> = HashMap::new();
+        for row in rows {
+            let (id, name, created_at, updated_at, revision, deleted_at, parts, details, dates) =
+                row?;
+            let [
+                prefix,
+                given_name,
+                middle_name,
+                family_name,
+                suffix,
+                nickname,
+            ] = parts;
+            let [organization, title, department, notes] = details;
+            let [birthday, anniversary] = dates.map(|d| d.as_deref().map(str::parse).transpose());
+            let contact = Contact {
+                id: parse_uuid(&id)?,
+                name,
+                phone: vec![],
+                email: vec![],
+                tags: vec![],
+                created_at: parse_timestamp(&created_at)?,
+                updated_at: parse_timestamp(&updated_at)?,
+                revision,
+                deleted_at: deleted_at.as_deref().map(parse_timestamp).transpose()?,
+                prefix,
+                given_name,
+                middle_name,
+                family_name,
+                suffix,
+                nickname,
+                addresses: vec![],
+                organization,
+                title,
+                department,
+                notes,
+                custom_fields: BTreeMap::new(),
+                birthday: birthday?,
+                anniversary: anniversary?,
+                dates: vec![],
+                relations: vec![],
+                photo: None,
+                attachments: vec![],
+            };
+            positions.insert(contact.id, contacts.len());
+            contacts.push(contact);
+        }
+
+        let mut stmt = conn.prepare(&format!(
+            "SELECT contact_id, number, label, e164 FROM phones
+             WHERE contact_id IN (SELECT c.id FROM contacts c WHERE {condition})
+             ORDER BY contact_id, position"
+        ))?;
+        let rows = stmt.query_map(params, |row| {
+            Ok((
+                row.get::<_, String>(0)?,
+                Phone {
+                    raw: row.get(1)?,
+                    label: row.get::<_, String>(2)?.parse().unwrap_or_default(),
+                    e164: row.get(3)?,
+                },
+            ))
+        })?;
+        for row in rows {
+            let (id, phone) = row?;
+            if let Some(&i) = positions.get(&parse_uuid(&id)?) {
+                contacts[i].phone.push(phone);
+            }
+        }
+
+        let mut stmt = conn.prepare(&format!(
+            "SELECT contact_id, address, label, is_primary FROM emails
+             WHERE contact_id IN (SELECT c.id FROM contacts c WHERE {condition})
+             ORDER BY contact_id, position"
+        ))?;
+        let rows = stmt.query_map(params, |row| {
+            Ok((
+                row.get::<_, String>(0)?,
+                Email {
+                    address: row.get(1)?,
+                    label: row.get::<_, String>(2)?.parse().unwrap_or_default(),
+                    primary: row.get(3)?,
+                },
+            ))
+        })?;
+        for row in rows {
+            let (id, email) = row?;
+            if let Some(&i) = positions.get(&parse_uuid(&id)?) {
+                contacts[i].email.push(email);
+            }
+        }
+
+        let mut stmt = conn.prepare(&format!(
+            "SELECT contact_id, label, street, city, region, postal_code, country FROM addresses
+             WHERE contact_id IN (SELECT c.id FROM contacts c WHERE {condition})
+             ORDER BY contact_id, position"
+        ))?;
+        let rows = stmt.query_map(params, |row| {
+            Ok((
+                row.get::<_, String>(0)?,
+                Address {
+                    label: row.get::<_, String>(1)?.parse().unwrap_or_default(),
+                    street: row.get(2)?,
+                    city: row.get(3)?,
+                    region: row.get(4)?,
+                    postal_code: row.get(5)?,
+                    country: row.get(6)?,
+                },
+            ))
+        })?;
+        for row in rows {
+            let (id, address) = row?;
+            if let Some(&i) = positions.get(&parse_uuid(&id)?) {
+                contacts[i].addresses.push(address);
+            }
+        }
+
+        let mut stmt = conn.prepare(&format!(
+            "SELECT contact_id, key, value FROM custom_fields
+             WHERE contact_id IN (SELECT c.id FROM contacts c WHERE {condition})"
+        ))?;
+        let rows = stmt.query_map(params, |row| {
+            Ok((
+                row.get::<_, String>(0)?,
+                row.get::<_, String>(1)?,
+                row.get::<_, String>(2)?,
+            ))
+        })?;
+        for row in rows {
+            let (id, key, value) = row?;
+            if let Some(&i) = positions.get(&parse_uuid(&id)?) {
+                contacts[i].custom_fields.insert(key, value);
+            }
+        }
+
+        let mut stmt = conn.prepare(&format!(
+            "SELECT contact_id, label, date FROM dates
+             WHERE contact_id IN (SELECT c.id FROM contacts c WHERE {condition})
+             ORDER BY contact_id, position"
+        ))?;
+        let rows = stmt.query_map(params, |row| {
+            Ok((
+                row.get::<_, String>(0)?,
+                row.get::<_, String>(1)?,
+                row.get::<_, String>(2)?,
+            ))
+        })?;
+        for row in rows {
+            let (id, label, date) = row?;
+            if let Some(&i) = positions.get(&parse_uuid(&id)?) {
+                contacts[i].dates.push(LabelledDate {
+                    label,
+                    date: date.parse()?,
+                });
+            }
+        }
+
+        let mut stmt = conn.prepare(&format!(
+            "SELECT contact_id, kind, related_id FROM relations
+             WHERE contact_id IN (SELECT c.id FROM contacts c WHERE {condition})
+             ORDER BY contact_id, position"
+        ))?;
+        let rows = stmt.query_map(params, |row| {
+            Ok((
+                row.get::<_, String>(0)?,
+                row.get::<_, String>(1)?,
+                row.get::<_, String>(2)?,
+            ))
+        })?;
+        for row in rows {
+            let (id, kind, related_id) = row?;
+            if let Some(&i) = positions.get(&parse_uuid(&id)?) {
+                contacts[i].relations.push(Relation {
+                    kind: kind.parse().map_err(AppError::Parse)?,
+                    contact_id: parse_uuid(&related_id)?,
+                });
+            }
+        }
+
+        let mut stmt = conn.prepare(&format!(
+            "SELECT contact_id, is_photo, hash, name, media_type, size FROM attachments
+             WHERE contact_id IN (SELECT c.id FROM contacts c WHERE {condition})
+             ORDER BY contact_id, position"
+        ))?;
+        let rows = stmt.query_map(params, |row| {
+            Ok((
+                row.get::<_, String>(0)?,
+                row.get::<_, bool>(1)?,
+                Attachment {
+                    hash: row.get(2)?,
+                    name: row.get(3)?,
+                    media_type: row.get(4)?,
+                    size: row.get(5)?,
+                },
+            ))
+        })?;
+        for row in rows {
+            let (id, is_photo, attachment) = row?;
+            if let Some(&i) = positions.get(&parse_uuid(&id)?) {
+                if is_photo {
+                    contacts[i].photo = Some(attachment);
+                } else {
+                    contacts[i].attachments.push(attachment);
+                }
+            }
+        }
+
+        let mut stmt = conn.prepare(&format!(
+            "SELECT contact_id, tag FROM tags
+             WHERE contact_id IN (SELECT c.id FROM contacts c WHERE {condition})
+             ORDER BY contact_id, position"
+        ))?;
+        let rows = stmt.query_map(params, |row| {
+            Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?))
+        })?;
+        for row in rows {
+            let (id, tag) = row?;
+            if let Some(&i) = positions.get(&parse_uuid(&id)?) {
+                contacts[i].tags.push(tag);
+            }
+        }
+
+        Ok(contacts)
+    }
+
+    fn fetch(conn: &Connection, id: Uuid) -> Result<Option<Contact>, AppError> {
+        Ok(Self::query(conn, "c.id = ?1", &[&id.to_string()])?.pop())
+    }
+
+    /// Inserts or replaces one contact and its child rows.
+    fn write(conn: &Connection, contact: &Contact) -> Result<(), AppError> {
+        let id = contact.id.to_string();
+        let primary = contact.primary_email();
+        let domain = primary.split('@').nth(1).unwrap_or_default().to_lowercase();
+
+        conn.execute(
+            "INSERT INTO contacts
+                (id, name, email, email_domain, created_at, updated_at, revision, deleted_at,
+                 prefix, given_name, middle_name, family_name, suffix, nickname,
+                 organization, title, department, notes, birthday, anniversary)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14, ?15, ?16, ?17,
+                     ?18, ?19, ?20)
+             ON CONFLICT (id) DO UPDATE SET
+                name = excluded.name,
+                email = excluded.email,
+                email_domain = excluded.email_domain,
+                created_at = excluded.created_at,
+                updated_at = excluded.updated_at,
+                revision = excluded.revision,
+                deleted_at = excluded.deleted_at,
+                prefix = excluded.prefix,
+                given_name = excluded.given_name,
+                middle_name = excluded.middle_name,
+                family_name = excluded.family_name,
+                suffix = excluded.suffix,
+                nickname = excluded.nickname,
+                organization = excluded.organization,
+                title = excluded.title,
+                department = excluded.department,
+                notes = excluded.notes,
+                birthday = excluded.birthday,
+                anniversary = excluded.anniversary",
+            params![
+                id,
+                contact.name,
+                primary,
+                domain,
+                contact.created_at.to_rfc3339(),
+                contact.updated_at.to_rfc3339(),
+                contact.revision,
+                contact.deleted_at.map(|t| t.to_rfc3339()),
+                contact.prefix,
+                contact.given_name,
+                contact.middle_name,
+                contact.family_name,
+                contact.suffix,
+                contact.nickname,
+                contact.organization,
+                contact.title,
+                contact.department,
+                contact.notes,
+                contact.birthday.map(String::from),
+                contact.anniversary.map(String::from),
+            ],
+        )?;
+
+        conn.execute("DELETE FROM phones WHERE contact_id = ?1", [&id])?;
+        for (position, phone) in contact.phone.iter().enumerate() {
+            conn.execute(
+                "INSERT INTO phones (contact_id, position, number, label, e164, key)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+                params![
+                    id,
+                    position,
+                    phone.raw,
+                    phone.label.to_string(),
+                    phone.e164,
+                    phone.key()
+                ],
+            )?;
+        }
+
+        conn.execute("DELETE FROM emails WHERE contact_id = ?1", [&id])?;
+        for (position, email) in contact.email.iter().enumerate() {
+            conn.execute(
+                "INSERT INTO emails (contact_id, position, address, label, is_primary, domain)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+                params![
+                    id,
+                    position,
+                    email.address,
+                    email.label.to_string(),
+                    email.primary,
+                    email.domain().unwrap_or_default(),
+                ],
+            )?;
+        }
+
+        conn.execute("DELETE FROM addresses WHERE contact_id = ?1", [&id])?;
+        for (position, address) in contact.addresses.iter().enumerate() {
+            conn.execute(
+                "INSERT INTO addresses
+                    (contact_id, position, label, street, city, region, postal_code, country)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)",
+                params![
+                    id,
+                    position,
+                    address.label.to_string(),
+                    address.street,
+                    address.city,
+                    address.region,
+                    address.postal_code,
+                    address.country,
+                ],
+            )?;
+        }
+
+        conn.execute("DELETE FROM custom_fields WHERE contact_id = ?1", [&id])?;
+        for (key, value) in &contact.custom_fields {
+            conn.execute(
+                "INSERT INTO custom_fields (contact_id, key, value) VALUES (?1, ?2, ?3)",
+                params![id, key, value],
+            )?;
+        }
+
+        conn.execute("DELETE FROM dates WHERE contact_id = ?1", [&id])?;
+        for (position, date) in contact.dates.iter().enumerate() {
+            conn.execute(
+                "INSERT INTO dates (contact_id, position, label, date) VALUES (?1, ?2, ?3, ?4)",
+                params![id, position, date.label, date.date.to_string()],
+            )?;
+        }
+
+        conn.execute("DELETE FROM relations WHERE contact_id = ?1", [&id])?;
+        for (position, relation) in contact.relations.iter().enumerate() {
+            conn.execute(
+                "INSERT INTO relations (contact_id, position, kind, related_id)
+                 VALUES (?1, ?2, ?3, ?4)",
+                params![
+                    id,
+                    position,
+                    relation.kind.to_string(),
+                    relation.contact_id.to_string()
+                ],
+            )?;
+        }
+
+        conn.execute("DELETE FROM attachments WHERE contact_id = ?1", [&id])?;
+        let photo = contact.photo.iter().map(|a| (true, a));
+        let files = contact.attachments.iter().map(|a| (false, a));
+        for (position, (is_photo, attachment)) in photo.chain(files).enumerate() {
+            conn.execute(
+                "INSERT INTO attachments
+                    (contact_id, position, is_photo, hash, name, media_type, size)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+                params![
+                    id,
+                    position,
+                    is_photo,
+                    attachment.hash,
+                    attachment.name,
+                    attachment.media_type,
+                    attachment.size,
+                ],
+            )?;
+        }
+
+        conn.execute("DELETE FROM tags WHERE contact_id = ?1", [&id])?;
+        for (position, tag) in contact.tags.iter().enumerate() {
+            conn.execute(
+                "INSERT INTO tags (contact_id, position, tag) VALUES (?1, ?2, ?3)",
+                params![id, position, tag],
+            )?;
+        }
+        Ok(())
+    }
+
+    fn query_groups(conn: &Connection) -> Result<HashMap<Uuid, Group>, AppError> {
+        let mut groups = HashMap::new();
+        let mut stmt =
+            conn.prepare("SELECT id, name, description, created_at, updated_at FROM groups")?;
+        let rows = stmt.query_map([], |row| {
+            Ok((
+                row.get::<_, String>(0)?,
+                row.get::<_, String>(1)?,
+                row.get::<_, Option<String>>(2)?,
+                row.get::<_, String>(3)?,
+                row.get::<_, String>(4)?,
+            ))
+        })?;
+        for row in rows {
+            let (id, name, description, created_at, updated_at) = row?;
+            let group = Group {
+                id: parse_uuid(&id)?,
+                name,
+                description,
+                members: Default::default(),
+                created_at: parse_timestamp(&created_at)?,
+                updated_at: parse_timestamp(&updated_at)?,
+            };
+            groups.insert(group.id, group);
+        }
+
+        let mut stmt = conn.prepare("SELECT group_id, contact_id FROM group_members")?;
+        let rows = stmt.query_map([], |row| {
+            Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?))
+        })?;
+        for row in rows {
+            let (group_id, contact_id) = row?;
+            if let Some(group) = groups.get_mut(&parse_uuid(&group_id)?) {
+                group.members.insert(parse_uuid(&contact_id)?);
+            }
+        }
+        Ok(groups)
+    }
+
+    fn write_groups(conn: &Connection, groups: &HashMap<Uuid, Group>) -> Result<(), AppError> {
+        conn.execute("DELETE FROM groups", [])?;
+        for group in groups.values() {
+            let id = group.id.to_string();
+            conn.execute(
+                "INSERT INTO groups (id, name, description, created_at, updated_at)
+                 VALUES (?1, ?2, ?3, ?4, ?5)",
+                params![
+                    id,
+                    group.name,
+                    group.description,
+                    group.created_at.to_rfc3339(),
+                    group.updated_at.to_rfc3339()
+                ],
+            )?;
+            for member in &group.members {
+                conn.execute(
+                    "INSERT INTO group_members (group_id, contact_id) VALUES (?1, ?2)",
+                    params![id, member.to_string()],
+                )?;
+            }
+        }
+        Ok(())
+    }
+
+    fn write_all(conn: &Connection, contacts: &HashMap<Uuid, Contact>) -> Result<(), AppError> {
+        conn.execute("DELETE FROM contacts", [])?;
+        for contact in contacts.values() {
+            Self::write(conn, contact)?;
+        }
+        Ok(())
+    }
+
+    /// Writes the contacts that differ between `old` and `new` and deletes
+    /// the ones `new` dropped, so a `modify` that edits one contact touches
+    /// one contact's rows.
+    fn write_changes(
+        conn: &Connection,
+        old: &HashMap<Uuid, Contact>,
+        new: &HashMap<Uuid, Contact>,
+    ) -> Result<(), AppError> {
+        for (id, contact) in new {
+            if old
+                .get(id)
+                .is_none_or(|previous| differs(previous, contact))
+            {
+                Self::write(conn, contact)?;
+            }
+        }
+        for id in old.keys().filter(|id| !new.contains_key(id)) {
+            conn.execute("DELETE FROM contacts WHERE id = ?1", [id.to_string()])?;
+        }
+        Ok(())
+    }
+}
+
+fn parse_uuid(value: &str) -> Result<Uuid, AppError> {
+    Uuid::parse_str(value).map_err(|e| AppError::Parse(format!("Invalid id {}: {}", value, e)))
+}
+
+fn parse_timestamp(value: &str) -> Result<DateTime<Utc>, AppError> {
+    DateTime::parse_from_rfc3339(value)
+        .map(|t| t.with_timezone(&Utc))
+        .map_err(|e| AppError::Parse(format!("Invalid timestamp {}: {}", value, e)))
+}
+
+impl ContactStore for SqliteStore {
+    fn load(&self) -> Result<HashMap<Uuid, Contact>, AppError> {
+        let conn = self.conn.lock().unwrap();
+        Ok(Self::query(&conn, "1 = 1", &[])?
+            .into_iter()
+            .map(|c| (c.id, c))
+            .collect())
+    }
+
+    fn save(&self, contacts: HashMap<Uuid, Contact>) -> Result<(), AppError> {
+        let mut conn = self.conn.lock().unwrap();
+        let tx = conn.transaction()?;
+        Self::write_all(&tx, &contacts)?;
+        tx.commit()?;
+        Ok(())
+    }
+
+    fn modify(&self, f: &mut ModifyFn) -> Result<(), AppError> {
+        let mut conn = self.conn.lock().unwrap();
+        let tx = conn.transaction()?;
+        let mut contacts: HashMap<Uuid, Contact> = Self::query(&tx, "1 = 1", &[])?
+            .into_iter()
+            .map(|c| (c.id, c))
+            .collect();
+        let before = contacts.clone();
+        f(&mut contacts)?;
+        Self::write_changes(&tx, &before, &contacts)?;
+        tx.commit()?;
+        Ok(())
+    }
+
+    fn get(&self, id: Uuid) -> Result<Option<Contact>, AppError> {
+        Self::fetch(&self.conn.lock().unwrap(), id)
+    }
+
+    fn insert(&self, contact: Contact) -> Result<(), AppError> {
+        let mut conn = self.conn.lock().unwrap();
+        let tx = conn.transaction()?;
+        Self::write(&tx, &contact)?;
+        tx.commit()?;
+        Ok(())
+    }
+
+    fn update(&self, id: Uuid, patch: &ContactPatch) -> Result<(Contact, Contact), AppError> {
+        let mut conn = self.conn.lock().unwrap();
+        let tx = conn.transaction()?;
+        let before = Self::fetch(&tx, id)?
+            .filter(|c| !c.is_deleted())
+            .ok_or(AppError::NotFound("Contact not found".to_string()))?;
+        patch.check_revision(&before)?;
+        let mut contact = before.clone();
+        patch.apply(&mut contact);
+        Self::write(&tx, &contact)?;
+        tx.commit()?;
+        Ok((before, contact))
+    }
+
+    fn replace(&self, contact: Contact, expected_revision: u64) -> Result<(), AppError> {
+        let mut conn = self.conn.lock().unwrap();
+        let tx = conn.transaction()?;
+        check_stored_revision(Self::fetch(&tx, contact.id)?.as_ref(), expected_revision)?;
+        Self::write(&tx, &contact)?;
+        tx.commit()?;
+        Ok(())
+    }
+
+    fn remove(&self, id: Uuid) -> Result<Option<Contact>, AppError> {
+        let conn = self.conn.lock().unwrap();
+        let removed = Self::fetch(&conn, id)?;
+        if removed.is_some() {
+            conn.execute("DELETE FROM contacts WHERE id = ?1", [id.to_string()])?;
+        }
+        Ok(removed)
+    }
+
+    fn list(&self, filter: &ContactFilter) -> Result<Vec<Contact>, AppError> {
+        let mut conditions = vec![if filter.trashed {
+            "c.deleted_at IS NOT NULL".to_string()
+        } else {
+            "c.deleted_at IS NULL".to_string()
+        }];
+        let mut params: Vec<&dyn ToSql> = Vec::new();
+
+        if let Some(name) = &filter.name {
+            params.push(name);
+            conditions.push(format!("({})", name_condition(params.len())));
+        }
+        let domain = filter.domain.as_ref().map(|d| d.to_lowercase());
+        if let Some(domain) = &domain {
+            params.push(domain);
+            conditions.push(format!(
+                "EXISTS (SELECT 1 FROM emails e WHERE e.contact_id = c.id AND e.domain = ?{})",
+                params.len()
+            ));
+        }
+        let phone_key = filter.phone.as_ref().map(|p| Phone::new(p).key());
+        if let Some(key) = &phone_key {
+            params.push(key);
+            conditions.push(format!(
+                "EXISTS (SELECT 1 FROM phones p WHERE p.contact_id = c.id AND p.key = ?{})",
+                params.len()
+            ));
+        }
+        if let Some(company) = &filter.company {
+            params.push(company);
+            conditions.push(format!(
+                "instr(lower(c.organization), lower(trim(?{}))) > 0",
+                params.len()
+            ));
+        }
+        if let Some(city) = &filter.city {
+            params.push(city);
+            conditions.push(format!(
+                "EXISTS (SELECT 1 FROM addresses a WHERE a.contact_id = c.id
+                    AND lower(a.city) = lower(trim(?{})))",
+                params.len()
+            ));
+        }
+        if let Some(note) = &filter.note {
+            params.push(note);
+            conditions.push(format!(
+                "instr(lower(c.notes), lower(trim(?{}))) > 0",
+                params.len()
+            ));
+        }
+        if let Some(tag) = &filter.tag {
+            params.push(tag);
+            conditions.push(format!(
+                "EXISTS (SELECT 1 FROM tags t WHERE t.contact_id = c.id \
+                 AND (t.tag = ?{0} OR substr(t.tag, 1, length(?{0}) + 1) = ?{0} || '/'))",
+                params.len()
+            ));
+        }
+
+        Self::query(
+            &self.conn.lock().unwrap(),
+            &conditions.join(" AND "),
+            &params,
+        )
+    }
+    fn load_groups(&self) -> Result<HashMap<Uuid, Group>, AppError> {
+        Self::query_groups(&self.conn.lock().unwrap())
+    }
 
-        Self {
-            // contacts: std::cell::RefCell::new(HashMap::new()),
-            contacts: std::cell::RefCell::new(contacts_hashmap),
-        }
+    fn save_groups(&self, groups: HashMap<Uuid, Group>) -> Result<(), AppError> {
+        let mut conn = self.conn.lock().unwrap();
+        let tx = conn.transaction()?;
+        Self::write_groups(&tx, &groups)?;
+        tx.commit()?;
+        Ok(())
     }
-}
 
-impl ContactStore for MemStore {
-    fn load(&self) -> Result<HashMap<Uuid, Contact>, AppError> {
-        Ok(self.contacts.borrow().clone())
+    fn modify_groups(&self, f: &mut ModifyGroupsFn) -> Result<(), AppError> {
+        let mut conn = self.conn.lock().unwrap();
+        let tx = conn.transaction()?;
+        let mut groups = Self::query_groups(&tx)?;
+        f(&mut groups)?;
+        Self::write_groups(&tx, &groups)?;
+        tx.commit()?;
+        Ok(())
     }
+}
 
-    fn save(&self, contacts: HashMap<Uuid, Contact>) -> Result<(), AppError> {
-        let mut contacts_hashmap = self.contacts.borrow_mut();
-        contacts_hashmap.clear();
+/// One line of the journal file.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "op", rename_all = "snake_case")]
+pub enum JournalEntry {
+    Add {
+        at: DateTime<Utc>,
+        contact: Contact,
+    },
+    Update {
+        at: DateTime<Utc>,
+        contact: Contact,
+    },
+    Delete {
+        at: DateTime<Utc>,
+        id: Uuid,
+    },
+    /// Every group, as of this change; groups are few and rarely written.
+    Groups {
+        at: DateTime<Utc>,
+        groups: Vec<Group>,
+    },
+    /// Written by compaction; replaces everything before it.
+    Snapshot {
+        at: DateTime<Utc>,
+        contacts: Vec<Contact>,
+        #[serde(default, skip_serializing_if = "Vec::is_empty")]
+        groups: Vec<Group>,
+    },
+}
 
-        for (_key, contact) in contacts {
-            contacts_hashmap.insert(contact.id, contact);
+impl JournalEntry {
+    fn apply(self, contacts: &mut HashMap<Uuid, Contact>, groups: &mut HashMap<Uuid, Group>) {
+        match self {
+            JournalEntry::Add { contact, .. } | JournalEntry::Update { contact, .. } => {
+                contacts.insert(contact.id, contact);
+            }
+            JournalEntry::Delete { id, .. } => {
+                contacts.remove(&id);
+            }
+            JournalEntry::Groups {
+                groups: current, ..
+            } => {
+                *groups = current.into_iter().map(|g| (g.id, g)).collect();
+            }
+            JournalEntry::Snapshot {
+                contacts: snapshot,
+                groups: snapshot_groups,
+                ..
+            } => {
+                *contacts = snapshot.into_iter().map(|c| (c.id, c)).collect();
+                *groups = snapshot_groups.into_iter().map(|g| (g.id, g)).collect();
+            }
         }
-        Ok(())
     }
 }
 
+/// Append-only store: every change is one JSON line in the journal and `load`
+/// replays the file. Writes cost one append instead of a full rewrite, and the
+/// journal doubles as a change log until `compact` folds it into a snapshot.
 #[derive(Clone)]
-pub struct FileStore {
+pub struct JournalStore {
     path: PathBuf,
-    lock: Arc<Mutex<()>>, // file write lock
+    lock: Arc<Mutex<()>>,
+    lock_timeout: Duration,
 }
 
-impl FileStore {
+impl JournalStore {
     pub fn new(path: impl Into<PathBuf>) -> Self {
         Self {
             path: path.into(),
             lock: Arc::new(Mutex::new(())),
+            lock_timeout: Duration::ZERO,
         }
     }
-}
 
-impl ContactStore for FileStore {
-    fn load(&self) -> Result<HashMap<Uuid, Contact>, AppError> {
+    pub fn with_lock_timeout(mut self, timeout: Duration) -> Self {
+        self.lock_timeout = timeout;
+        self
+    }
+
+    fn acquire(&self) -> Result<FileLock<'_>, AppError> {
+        FileLock::acquire(&self.lock, &self.path, self.lock_timeout)
+    }
+
+    /// Every entry in the journal, oldest first.
+    pub fn entries(&self) -> Result<Vec<JournalEntry>, AppError> {
         if !self.path.exists() {
-            return Ok(HashMap::new());
+            return Ok(Vec::new());
         }
+
         let data = fs::read_to_string(&self.path)?;
+        let lines: Vec<&str> = data.lines().filter(|l| !l.trim().is_empty()).collect();
+        let mut entries = Vec::with_capacity(lines.len());
 
-        let contacts: Vec<ContactRaw> = serde_json::from_str(&data)
-            .map_err(|e| AppError::Parse(format!("Error, JSON... : {}", e)))?;
+        for (i, line) in lines.iter().enumerate() {
+            match serde_json::from_str::<JournalEntry>(line) {
+                Ok(entry) => entries.push(entry),
+                // A crash mid-append can only tear the last line
+                Err(e) if i == lines.len() - 1 => {
+                    eprintln!("⚠️ Ignoring incomplete last journal entry: {}", e);
+                }
+                Err(e) => {
+                    return Err(AppError::Parse(format!(
+                        "Journal line {} is invalid: {}",
+                        i + 1,
+                        e
+                    )));
+                }
+            }
+        }
+        Ok(entries)
+    }
 
-        let migrated_contacts: Vec<Contact> = contacts.into_iter().map(Contact::from).collect();
+    fn replay(&self) -> Result<HashMap<Uuid, Contact>, AppError> {
+        Ok(self.replay_all()?.0)
+    }
 
-        let mut contacts_hashmap: HashMap<Uuid, Contact> = HashMap::new();
-        for contact in migrated_contacts {
-            contacts_hashmap.insert(contact.id, contact);
+    fn replay_all(&self) -> Result<StoredData, AppError> {
+        let (mut contacts, mut groups) = (HashMap::new(), HashMap::new());
+        for entry in self.entries()? {
+            entry.apply(&mut contacts, &mut groups);
         }
-        Ok(contacts_hashmap)
+        Ok((contacts, groups))
     }
 
-    fn save(&self, contacts: HashMap<Uuid, Contact>) -> Result<(), AppError> {
-        let _guard = self.lock.lock();
+    fn groups_entry(groups: HashMap<Uuid, Group>) -> JournalEntry {
+        let mut groups: Vec<Group> = groups.into_values().collect();
+        groups.sort_by_key(|g| g.created_at);
+        JournalEntry::Groups {
+            at: Utc::now(),
+            groups,
+        }
+    }
 
-        let contacts_vec: Vec<Contact> = contacts.values().cloned().collect();
+    fn append(&self, entries: &[JournalEntry]) -> Result<(), AppError> {
+        if entries.is_empty() {
+            return Ok(());
+        }
 
-        let data = serde_json::to_string_pretty(&contacts_vec)
-            .map_err(|e| AppError::Parse(format!("Saving error...: {}", e)))?;
+        let mut data = String::new();
+        for entry in entries {
+            let line = serde_json::to_string(entry)
+                .map_err(|e| AppError::Parse(format!("Journal error: {}", e)))?;
+            data.push_str(&line);
+            data.push('\n');
+        }
+
+        let mut file = OpenOptions::new()
+            .create(true)
+            .truncate(false)
+            .read(true)
+            .write(true)
+            .open(&self.path)?;
+        Self::drop_torn_line(&mut file)?;
+        file.seek(SeekFrom::End(0))?;
+        file.write_all(data.as_bytes())?;
+        file.sync_data()?;
+        Ok(())
+    }
 
-        fs::write(&self.path, data)?;
+    /// Cuts an incomplete last line (left by a crash mid-append) back to the
+    /// last newline, so the next entry starts a line of its own instead of
+    /// being glued onto the torn one.
+    fn drop_torn_line(file: &mut File) -> Result<(), AppError> {
+        let len = file.metadata()?.len();
+        if len == 0 {
+            return Ok(());
+        }
+        let mut last = [0u8];
+        file.seek(SeekFrom::End(-1))?;
+        file.read_exact(&mut last)?;
+        if last[0] == b'\n' {
+            return Ok(());
+        }
+
+        let mut data = Vec::new();
+        file.seek(SeekFrom::Start(0))?;
+        file.read_to_end(&mut data)?;
+        let keep = data.iter().rposition(|&b| b == b'\n').map_or(0, |i| i + 1);
+        eprintln!(
+            "⚠️ Dropping incomplete last journal entry ({} bytes)",
+            len - keep as u64
+        );
+        file.set_len(keep as u64)?;
         Ok(())
     }
+
+    /// Journal entries that turn `old` into `new`.
+    fn diff(old: &HashMap<Uuid, Contact>, new: &HashMap<Uuid, Contact>) -> Vec<JournalEntry> {
+        let at = Utc::now();
+        let mut entries = Vec::new();
+
+        for (id, contact) in new {
+            match old.get(id) {
+                None => entries.push(JournalEntry::Add {
+                    at,
+                    contact: contact.clone(),
+                }),
+                Some(previous) if differs(previous, contact) => {
+                    entries.push(JournalEntry::Update {
+                        at,
+                        contact: contact.clone(),
+                    })
+                }
+                Some(_) => {}
+            }
+        }
+        for id in old.keys().filter(|id| !new.contains_key(id)) {
+            entries.push(JournalEntry::Delete { at, id: *id });
+        }
+        entries
+    }
+}
+
+impl ContactStore for JournalStore {
+    fn load(&self) -> Result<HashMap<Uuid, Contact>, AppError> {
+        self.replay()
+    }
+
+    fn save(&self, contacts: HashMap<Uuid, Contact>) -> Result<(), AppError> {
+        let _lock = self.acquire()?;
+        let entries = Self::diff(&self.replay()?, &contacts);
+        self.append(&entries)
+    }
+
+    fn insert(&self, contact: Contact) -> Result<(), AppError> {
+        let _lock = self.acquire()?;
+        self.append(&[JournalEntry::Add {
+            at: Utc::now(),
+            contact,
+        }])
+    }
+
+    fn update(&self, id: Uuid, patch: &ContactPatch) -> Result<(Contact, Contact), AppError> {
+        let _lock = self.acquire()?;
+        let (before, contact) = apply_patch(&mut self.replay()?, id, patch)?;
+        self.append(&[JournalEntry::Update {
+            at: Utc::now(),
+            contact: contact.clone(),
+        }])?;
+        Ok((before, contact))
+    }
+
+    fn replace(&self, contact: Contact, expected_revision: u64) -> Result<(), AppError> {
+        let _lock = self.acquire()?;
+        check_stored_revision(self.replay()?.get(&contact.id), expected_revision)?;
+        self.append(&[JournalEntry::Update {
+            at: Utc::now(),
+            contact,
+        }])
+    }
+
+    fn remove(&self, id: Uuid) -> Result<Option<Contact>, AppError> {
+        let _lock = self.acquire()?;
+        let removed = self.replay()?.remove(&id);
+        if removed.is_some() {
+            self.append(&[JournalEntry::Delete { at: Utc::now(), id }])?;
+        }
+        Ok(removed)
+    }
+
+    fn modify(&self, f: &mut ModifyFn) -> Result<(), AppError> {
+        let _lock = self.acquire()?;
+        let old = self.replay()?;
+        let mut contacts = old.clone();
+        f(&mut contacts)?;
+        self.append(&Self::diff(&old, &contacts))
+    }
+
+    fn compact(&self) -> Result<(), AppError> {
+        let _lock = self.acquire()?;
+        let (contacts, groups) = self.replay_all()?;
+        let snapshot = JournalEntry::Snapshot {
+            at: Utc::now(),
+            contacts: contacts.into_values().collect(),
+            groups: groups.into_values().collect(),
+        };
+        let mut data = serde_json::to_string(&snapshot)
+            .map_err(|e| AppError::Parse(format!("Journal error: {}", e)))?;
+        data.push('\n');
+        write_atomic(&self.path, data.as_bytes())
+    }
+
+    fn load_groups(&self) -> Result<HashMap<Uuid, Group>, AppError> {
+        Ok(self.replay_all()?.1)
+    }
+
+    fn save_groups(&self, groups: HashMap<Uuid, Group>) -> Result<(), AppError> {
+        let _lock = self.acquire()?;
+        self.append(&[Self::groups_entry(groups)])
+    }
+
+    fn modify_groups(&self, f: &mut ModifyGroupsFn) -> Result<(), AppError> {
+        let _lock = self.acquire()?;
+        let mut groups = self.replay_all()?.1;
+        f(&mut groups)?;
+        self.append(&[Self::groups_entry(groups)])
+    }
 }
 
 pub struct RemoteStore {
@@ -194,19 +1964,709 @@ impl ContactStore for RemoteStore {
     }
 }
 
-#[derive(Debug, Clone)]
+/// What a merge does with a contact that is already in the store.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, clap::ValueEnum)]
 pub enum MergePolicy {
+    /// Leave the stored contact alone
+    #[default]
     Keep,
+    /// Replace it with the incoming one
     Overwrite,
+    /// Add the incoming one as a new contact
     Duplicate,
 }
 
-impl MergePolicy {
-    pub fn policy_check(s: &str) -> Self {
-        match s.to_lowercase().as_str() {
-            "overwrite" => MergePolicy::Overwrite,
-            "duplicate" => MergePolicy::Duplicate,
-            _ => MergePolicy::Keep,
+impl fmt::Display for MergePolicy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MergePolicy::Keep => write!(f, "keep"),
+            MergePolicy::Overwrite => write!(f, "overwrite"),
+            MergePolicy::Duplicate => write!(f, "duplicate"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_mem_store_shared_between_threads() {
+        let store: Arc<dyn ContactStore> = Arc::new(MemStore::new());
+
+        let handles: Vec<_> = (0..4)
+            .map(|i| {
+                let store = Arc::clone(&store);
+                thread::spawn(move || {
+                    let mut contacts = store.load().unwrap();
+                    let contact = Contact::new(
+                        &format!("User{}", i),
+                        "0987654321",
+                        "user@example.com",
+                        vec![],
+                        Utc::now(),
+                        Utc::now(),
+                    );
+                    contacts.insert(contact.id, contact);
+                    store.save(contacts).unwrap();
+                })
+            })
+            .collect();
+
+        for handle in handles {
+            handle.join().unwrap();
+        }
+
+        assert!(!store.load().unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_file_store_concurrent_inserts_all_persist() {
+        // The API shares one store between request threads with no lock of
+        // its own; every insert has to survive the others' load/save
+        let dir = tempfile::tempdir().unwrap();
+        let store: Arc<dyn ContactStore> =
+            Arc::new(FileStore::new(dir.path().join("contacts.json")));
+
+        let handles: Vec<_> = (0..8)
+            .map(|i| {
+                let store = Arc::clone(&store);
+                thread::spawn(move || {
+                    for j in 0..5 {
+                        let contact = Contact::new(
+                            &format!("User{}-{}", i, j),
+                            "0987654321",
+                            "user@example.com",
+                            vec![],
+                            Utc::now(),
+                            Utc::now(),
+                        );
+                        store.insert(contact).unwrap();
+                    }
+                })
+            })
+            .collect();
+
+        for handle in handles {
+            handle.join().unwrap();
+        }
+
+        assert_eq!(store.load().unwrap().len(), 40);
+    }
+
+    #[test]
+    fn test_file_store_record_operations() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = FileStore::new(dir.path().join("contacts.json"));
+
+        let contact = Contact::new(
+            "Alice",
+            "08123456789",
+            "alice@work.com",
+            vec!["work".into()],
+            Utc::now(),
+            Utc::now(),
+        );
+        let id = contact.id;
+        store.insert(contact).unwrap();
+
+        let patch = ContactPatch {
+            email: Some(vec!["alice@home.com".into()]),
+            ..Default::default()
+        };
+        let (before, updated) = store.update(id, &patch).unwrap();
+        assert_eq!(before.primary_email(), "alice@work.com");
+        assert_eq!(updated.primary_email(), "alice@home.com");
+        assert_eq!(
+            store.get(id).unwrap().unwrap().primary_email(),
+            "alice@home.com"
+        );
+
+        let filter = ContactFilter {
+            domain: Some("home.com".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(store.list(&filter).unwrap().len(), 1);
+
+        assert!(store.remove(id).unwrap().is_some());
+        assert!(store.get(id).unwrap().is_none());
+        assert!(matches!(
+            store.update(id, &patch),
+            Err(AppError::NotFound(_))
+        ));
+    }
+
+    #[test]
+    fn test_file_store_save_keeps_backup() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("contacts.json");
+        let store = FileStore::new(&path);
+
+        let first = Contact::new(
+            "Alice",
+            "08123456789",
+            "alice@work.com",
+            vec![],
+            Utc::now(),
+            Utc::now(),
+        );
+        store.insert(first.clone()).unwrap();
+        assert!(!store.backup_path().exists());
+
+        let second = Contact::new(
+            "Bob",
+            "08123456780",
+            "bob@work.com",
+            vec![],
+            Utc::now(),
+            Utc::now(),
+        );
+        store.insert(second).unwrap();
+
+        // The backup holds the state before the last save
+        let (backup, _) = store.read(&store.backup_path()).unwrap();
+        assert_eq!(backup.len(), 1);
+        assert!(backup.contains_key(&first.id));
+        assert!(!with_suffix(&path, ".tmp").exists());
+    }
+
+    #[test]
+    fn test_file_store_keeps_revision() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("contacts.json");
+
+        let mut contact = Contact::new(
+            "Alice",
+            "08123456789",
+            "alice@work.com",
+            vec![],
+            Utc::now(),
+            Utc::now(),
+        );
+        contact.revision = 3;
+        FileStore::new(&path).insert(contact.clone()).unwrap();
+
+        let stored = FileStore::new(&path).get(contact.id).unwrap().unwrap();
+        assert_eq!(stored.revision, 3);
+    }
+
+    #[test]
+    fn test_file_store_recovers_from_backup() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = FileStore::new(dir.path().join("contacts.json"));
+
+        let contact = Contact::new(
+            "Alice",
+            "08123456789",
+            "alice@work.com",
+            vec![],
+            Utc::now(),
+            Utc::now(),
+        );
+        store.insert(contact.clone()).unwrap();
+        store.insert(contact.clone()).unwrap();
+
+        // Simulate a torn write of the main file
+        fs::write(dir.path().join("contacts.json"), "[{\"id\": ").unwrap();
+
+        let contacts = store.load().unwrap();
+        assert!(contacts.contains_key(&contact.id));
+    }
+
+    #[test]
+    fn test_file_store_empty_file_keeps_the_backup() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("contacts.json");
+        let store = FileStore::new(&path);
+
+        let alice = Contact::new(
+            "Alice",
+            "08123456789",
+            "alice@work.com",
+            vec![],
+            Utc::now(),
+            Utc::now(),
+        );
+        let bob = Contact::new(
+            "Bob",
+            "08123456780",
+            "bob@work.com",
+            vec![],
+            Utc::now(),
+            Utc::now(),
+        );
+        store.insert(alice.clone()).unwrap();
+        store.insert(bob.clone()).unwrap();
+
+        // An emptied file is not an empty store: it is recovered from the backup
+        fs::write(&path, "").unwrap();
+        assert!(store.load().unwrap().contains_key(&alice.id));
+
+        // ...and writing over it must not replace the backup with the empty file
+        store.insert(bob.clone()).unwrap();
+        let backup = FileStore::new(store.backup_path()).load().unwrap();
+        assert!(backup.contains_key(&alice.id));
+        assert_eq!(store.load().unwrap().len(), 2);
+
+        // With no backup to fall back to, it is an error
+        fs::write(&path, "").unwrap();
+        fs::remove_file(store.backup_path()).unwrap();
+        assert!(matches!(store.load(), Err(AppError::Parse(_))));
+        fs::write(&path, "Alice,08123456789,alice@work.com\n").unwrap();
+        assert!(store.load().is_err());
+        assert_eq!(store.with_legacy_text().load().unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_file_store_jsonl_keeps_a_contact_per_line() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("contacts.jsonl");
+        let store = FileStore::new(&path);
+
+        let alice = Contact::new(
+            "Alice",
+            "08123456789",
+            "alice@work.com",
+            vec![],
+            Utc::now(),
+            Utc::now(),
+        );
+        let bob = Contact::new(
+            "Bob",
+            "08123456780",
+            "bob@work.com",
+            vec![],
+            Utc::now(),
+            Utc::now(),
+        );
+        store.insert(alice.clone()).unwrap();
+        store.insert(bob.clone()).unwrap();
+        store
+            .modify_groups(&mut |groups| {
+                crate::group::create(groups, "Family", None)?;
+                Ok(())
+            })
+            .unwrap();
+
+        let data = fs::read_to_string(&path).unwrap();
+        let lines: Vec<&str> = data.lines().collect();
+        assert_eq!(lines.len(), 3);
+        assert!(lines[0].starts_with(r#"{"schema_version":6,"groups":[{"#));
+        // Oldest first, so new contacts only add lines at the end
+        assert!(lines[1].contains(&alice.id.to_string()));
+        assert!(lines[2].contains(&bob.id.to_string()));
+
+        assert_eq!(store.load().unwrap().len(), 2);
+        assert_eq!(store.load_groups().unwrap().len(), 1);
+        assert!(store.check_schema().unwrap().is_current());
+
+        // A torn last line makes the file unreadable rather than one short
+        fs::write(&path, &data[..data.len() - 10]).unwrap();
+        assert_eq!(store.load().unwrap().len(), 2);
+        assert!(store.read(&path).is_err());
+
+        // So does an empty one, or one that lost its header line
+        fs::write(&path, "").unwrap();
+        assert!(store.read(&path).is_err());
+        fs::write(&path, data.split_once('\n').unwrap().1).unwrap();
+        assert!(store.read(&path).is_err());
+        let backup = fs::read(store.backup_path()).unwrap();
+        store.insert(alice.clone()).unwrap();
+        assert_eq!(fs::read(store.backup_path()).unwrap(), backup);
+    }
+
+    #[test]
+    fn test_file_store_lock_blocks_other_writers() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("contacts.json");
+
+        // Two stores on the same file behave like two processes
+        let holder = FileStore::new(&path);
+        let other = FileStore::new(&path).with_lock_timeout(Duration::from_millis(200));
+
+        let contact = Contact::new(
+            "Alice",
+            "08123456789",
+            "alice@work.com",
+            vec![],
+            Utc::now(),
+            Utc::now(),
+        );
+
+        let lock = holder.acquire().unwrap();
+        assert!(matches!(
+            other.insert(contact.clone()),
+            Err(AppError::Locked(_))
+        ));
+
+        drop(lock);
+        other.insert(contact).unwrap();
+        assert_eq!(holder.load().unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_stale_revision_is_rejected() {
+        let store = MemStore::new();
+        let contact = store.list(&ContactFilter::default()).unwrap().remove(0);
+        assert_eq!(contact.revision, 0);
+
+        let patch = ContactPatch {
+            name: Some("renamed".to_string()),
+            expected_revision: Some(0),
+            ..Default::default()
+        };
+        let (_, updated) = store.update(contact.id, &patch).unwrap();
+        assert_eq!(updated.revision, 1);
+
+        // Same patch again is now stale
+        assert!(matches!(
+            store.update(contact.id, &patch),
+            Err(AppError::Conflict(_))
+        ));
+        assert!(matches!(
+            store.replace(contact.clone(), 0),
+            Err(AppError::Conflict(_))
+        ));
+        store.replace(contact, 1).unwrap();
+    }
+
+    #[test]
+    fn test_sqlite_store_round_trip_and_filters() {
+        let store = SqliteStore::open_in_memory().unwrap();
+
+        let mut alice = Contact::new(
+            "Alice",
+            "08123456789",
+            "alice@Work.com",
+            vec!["work".into(), "friend".into()],
+            Utc::now(),
+            Utc::now(),
+        );
+        let bob = Contact::new(
+            "Bob",
+            "08123456780",
+            "bob@home.com",
+            vec!["gym/weights".into()],
+            Utc::now(),
+            Utc::now(),
+        );
+        alice.organization = Some("Acme Corp".to_string());
+        alice.add_note("Prefers email");
+        alice
+            .custom_fields
+            .insert("timezone".to_string(), "WAT".to_string());
+        alice.addresses.push(Address {
+            city: Some("Lagos".to_string()),
+            ..Default::default()
+        });
+        let scan = Attachment {
+            hash: "ab".repeat(32),
+            name: "card.pdf".to_string(),
+            media_type: "application/pdf".to_string(),
+            size: 42,
+        };
+        alice.photo = Some(Attachment {
+            hash: "cd".repeat(32),
+            name: "me.png".to_string(),
+            ..scan.clone()
+        });
+        alice.attachments.push(scan);
+        store.insert(alice.clone()).unwrap();
+        store.insert(bob.clone()).unwrap();
+
+        let stored = store.get(alice.id).unwrap().unwrap();
+        assert_eq!(stored.phone, alice.phone);
+        assert_eq!(stored.tags, alice.tags);
+        assert_eq!(stored.addresses, alice.addresses);
+        assert_eq!(stored.organization, alice.organization);
+        assert_eq!(stored.custom_fields, alice.custom_fields);
+        assert_eq!(stored.photo, alice.photo);
+        assert_eq!(stored.attachments, alice.attachments);
+
+        let by_note = ContactFilter {
+            note: Some("email".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(store.list(&by_note).unwrap()[0].id, alice.id);
+
+        let by_work = ContactFilter {
+            company: Some("acme".to_string()),
+            city: Some("LAGOS".to_string()),
+            ..Default::default()
+        };
+        let found = store.list(&by_work).unwrap();
+        assert_eq!(found.len(), 1);
+        assert!(found.iter().all(|c| by_work.matches(c)));
+
+        let by_name = ContactFilter {
+            name: Some("ALICE".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(store.list(&by_name).unwrap()[0].id, alice.id);
+
+        let by_domain = ContactFilter {
+            domain: Some("work.com".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(store.list(&by_domain).unwrap().len(), 1);
+
+        let by_tag = ContactFilter {
+            tag: Some("gym".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(store.list(&by_tag).unwrap()[0].id, bob.id);
+        let by_similar_tag = ContactFilter {
+            tag: Some("gy".to_string()),
+            ..Default::default()
+        };
+        assert!(store.list(&by_similar_tag).unwrap().is_empty());
+
+        let patch = ContactPatch {
+            phone: Some(vec![Phone::new("08000000000")]),
+            email: Some(vec!["bob@home.com".into(), "bob@work.com".into()]),
+            expected_revision: Some(0),
+            ..Default::default()
+        };
+        let (_, updated) = store.update(bob.id, &patch).unwrap();
+        assert_eq!(updated.revision, 1);
+        assert_eq!(store.list(&by_domain).unwrap().len(), 2);
+        let stored = store.get(bob.id).unwrap().unwrap();
+        assert_eq!(stored.primary_email(), "bob@home.com");
+        assert_eq!(stored.email.len(), 2);
+        let by_phone = ContactFilter {
+            phone: Some("0800 000 0000".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(store.list(&by_phone).unwrap()[0].id, bob.id);
+        // Typed differently from how it was stored, and not normalizable
+        let mut carol = Contact::new(
+            "Carol",
+            "12-34 56",
+            "carol@work.com",
+            vec![],
+            Utc::now(),
+            Utc::now(),
+        );
+        carol.phone = vec![Phone::new("12-34 56")];
+        store.insert(carol.clone()).unwrap();
+        let by_digits = ContactFilter {
+            phone: Some("123456".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(store.list(&by_digits).unwrap()[0].id, carol.id);
+        store.remove(carol.id).unwrap();
+        assert!(matches!(
+            store.update(bob.id, &patch),
+            Err(AppError::Conflict(_))
+        ));
+
+        let tombstone = store.trash(bob.id).unwrap().unwrap();
+        assert!(tombstone.is_deleted());
+        assert!(store.list(&by_tag).unwrap().is_empty());
+        let trashed = ContactFilter {
+            trashed: true,
+            ..Default::default()
+        };
+        assert_eq!(
+            store.list(&trashed).unwrap()[0].deleted_at,
+            tombstone.deleted_at
+        );
+
+        assert!(store.remove(alice.id).unwrap().is_some());
+        assert_eq!(store.load().unwrap().len(), 1);
+
+        // A modify that edits one contact rewrites only that contact's rows
+        store.insert(alice.clone()).unwrap();
+        let changes = || store.conn.lock().unwrap().total_changes();
+        let start = changes();
+        store.insert(alice.clone()).unwrap();
+        let one_contact = changes() - start;
+
+        let start = changes();
+        store
+            .modify(&mut |contacts| {
+                contacts.get_mut(&alice.id).unwrap().title = Some("CTO".to_string());
+                Ok(())
+            })
+            .unwrap();
+        assert_eq!(changes() - start, one_contact);
+        assert_eq!(
+            store.get(alice.id).unwrap().unwrap().title.as_deref(),
+            Some("CTO")
+        );
+        assert!(store.get(bob.id).unwrap().unwrap().is_deleted());
+
+        store.save(HashMap::new()).unwrap();
+        assert!(store.list(&ContactFilter::default()).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_sqlite_name_filter_uses_indexes() {
+        let store = SqliteStore::open_in_memory().unwrap();
+        let conn = store.conn.lock().unwrap();
+        let sql = format!(
+            "EXPLAIN QUERY PLAN SELECT c.id FROM contacts c WHERE c.deleted_at IS NULL AND ({})",
+            name_condition(1)
+        );
+        let plan: Vec<String> = conn
+            .prepare(&sql)
+            .unwrap()
+            .query_map(["ada"], |row| row.get(3))
+            .unwrap()
+            .collect::<Result<_, _>>()
+            .unwrap();
+        assert!(
+            plan.iter().all(|step| !step.starts_with("SCAN")),
+            "{:?}",
+            plan
+        );
+    }
+
+    #[test]
+    fn test_journal_store_replays_and_compacts() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("contacts.journal");
+        let store = JournalStore::new(&path);
+
+        let alice = Contact::new(
+            "Alice",
+            "08123456789",
+            "alice@work.com",
+            vec![],
+            Utc::now(),
+            Utc::now(),
+        );
+        let bob = Contact::new(
+            "Bob",
+            "08123456780",
+            "bob@work.com",
+            vec![],
+            Utc::now(),
+            Utc::now(),
+        );
+        store.insert(alice.clone()).unwrap();
+        store.insert(bob.clone()).unwrap();
+        let patch = ContactPatch {
+            email: Some(vec!["alice@home.com".into()]),
+            ..Default::default()
+        };
+        store.update(alice.id, &patch).unwrap();
+        store.remove(bob.id).unwrap();
+
+        let entries = store.entries().unwrap();
+        assert_eq!(entries.len(), 4);
+        assert!(matches!(entries[3], JournalEntry::Delete { id, .. } if id == bob.id));
+
+        // A fresh handle sees the same state by replaying the file
+        let contacts = JournalStore::new(&path).load().unwrap();
+        assert_eq!(contacts.len(), 1);
+        assert_eq!(contacts[&alice.id].primary_email(), "alice@home.com");
+
+        store.compact().unwrap();
+        assert_eq!(store.entries().unwrap().len(), 1);
+        assert_eq!(
+            store.load().unwrap()[&alice.id].primary_email(),
+            "alice@home.com"
+        );
+
+        // save() only appends what changed
+        let mut contacts = store.load().unwrap();
+        contacts.insert(bob.id, bob);
+        store.save(contacts).unwrap();
+        assert!(matches!(
+            store.entries().unwrap().last(),
+            Some(JournalEntry::Add { .. })
+        ));
+        assert_eq!(store.entries().unwrap().len(), 2);
+    }
+
+    #[test]
+    fn test_journal_store_appends_after_a_torn_line() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("contacts.journal");
+        let store = JournalStore::new(&path);
+
+        let alice = Contact::new(
+            "Alice",
+            "08123456789",
+            "alice@work.com",
+            vec![],
+            Utc::now(),
+            Utc::now(),
+        );
+        let bob = Contact::new(
+            "Bob",
+            "08123456780",
+            "bob@work.com",
+            vec![],
+            Utc::now(),
+            Utc::now(),
+        );
+        store.insert(alice.clone()).unwrap();
+
+        // A crash mid-append leaves half a line with no newline
+        let mut file = OpenOptions::new().append(true).open(&path).unwrap();
+        file.write_all(br#"{"op":"add","at":"2024-"#).unwrap();
+        drop(file);
+
+        store.insert(bob.clone()).unwrap();
+        assert_eq!(store.entries().unwrap().len(), 2);
+        let contacts = store.load().unwrap();
+        assert!(contacts.contains_key(&alice.id) && contacts.contains_key(&bob.id));
+        // Every line is whole again, so the next torn line can't take Bob with it
+        for line in fs::read_to_string(&path).unwrap().lines() {
+            serde_json::from_str::<JournalEntry>(line).unwrap();
+        }
+    }
+
+    #[test]
+    fn test_groups_survive_contact_writes() {
+        let dir = tempfile::tempdir().unwrap();
+        let stores: Vec<(&str, Box<dyn ContactStore>)> = vec![
+            ("mem", Box::new(MemStore::new())),
+            (
+                "file",
+                Box::new(FileStore::new(dir.path().join("contacts.json"))),
+            ),
+            (
+                "journal",
+                Box::new(JournalStore::new(dir.path().join("contacts.journal"))),
+            ),
+            (
+                "sqlite",
+                Box::new(SqliteStore::open(dir.path().join("contacts.db")).unwrap()),
+            ),
+        ];
+
+        for (kind, store) in stores {
+            let alice = Contact::new(
+                "Alice",
+                "08123456789",
+                "alice@work.com",
+                vec![],
+                Utc::now(),
+                Utc::now(),
+            );
+            store.insert(alice.clone()).unwrap();
+            store
+                .modify_groups(&mut |groups| {
+                    let family = crate::group::create(groups, "Family", None)?;
+                    groups.get_mut(&family.id).unwrap().add_member(alice.id);
+                    Ok(())
+                })
+                .unwrap();
+
+            // Writing contacts keeps the groups, and the other way round
+            store.modify(&mut |_| Ok(())).unwrap();
+            store.insert(alice.clone()).unwrap();
+            let groups = store.load_groups().unwrap();
+            let family = groups.values().next().unwrap();
+            assert_eq!(family.name, "Family", "{}", kind);
+            assert!(family.members.contains(&alice.id), "{}", kind);
+            assert!(store.get(alice.id).unwrap().is_some(), "{}", kind);
+
+            store.compact().ok();
+            assert_eq!(store.load_groups().unwrap().len(), 1, "{}", kind);
         }
     }
 }
@@ -0,0 +1,747 @@
+//! Reading and writing contact files one contact at a time, so a file of any
+//! size is never held in memory whole: JSON (our envelope or a bare array),
+//! NDJSON (one contact per line) and CSV. Long runs report their progress
+//! on stderr.
+
+use std::{
+    cell::Cell,
+    fmt,
+    fs::File,
+    io::{self, BufRead, BufReader, IsTerminal, Read, Seek, SeekFrom, Write},
+    path::Path,
+    rc::Rc,
+    str::FromStr,
+    time::{Duration, Instant},
+};
+
+use serde::{
+    Deserialize, Serialize,
+    de::{self, DeserializeSeed, Deserializer, IgnoredAny, MapAccess, SeqAccess, Visitor},
+};
+use serde_json::Value;
+
+use crate::{
+    csv_profile::CsvProfile,
+    domain::Contact,
+    error::AppError,
+    group::Group,
+    migrations::{self, CURRENT_SCHEMA_VERSION},
+};
+
+/// Called with each contact as it is read; an error stops the read.
+pub type OnContact<'a> = dyn FnMut(Contact) -> Result<(), AppError> + 'a;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    /// A contacts file, or a bare JSON array of contacts.
+    Json,
+    /// One JSON contact per line (`.jsonl`, `.ndjson`).
+    Ndjson,
+    Csv,
+}
+
+impl Format {
+    /// Guessed from the extension; anything unknown is JSON.
+    pub fn from_path(path: impl AsRef<Path>) -> Self {
+        let extension = path
+            .as_ref()
+            .extension()
+            .and_then(|e| e.to_str())
+            .unwrap_or_default()
+            .to_lowercase();
+        match extension.as_str() {
+            "jsonl" | "ndjson" => Format::Ndjson,
+            "csv" => Format::Csv,
+            _ => Format::Json,
+        }
+    }
+}
+
+impl FromStr for Format {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_lowercase().as_str() {
+            "json" => Ok(Format::Json),
+            "ndjson" | "jsonl" => Ok(Format::Ndjson),
+            "csv" => Ok(Format::Csv),
+            other => Err(format!(
+                "Unknown format '{}'. Use: json | ndjson | csv",
+                other
+            )),
+        }
+    }
+}
+
+impl fmt::Display for Format {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Format::Json => "json",
+            Format::Ndjson => "ndjson",
+            Format::Csv => "csv",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// Shows how far a long read or write has got, on stderr and only when that
+/// is a terminal. Nothing is shown for runs that finish within a second.
+pub struct Progress {
+    label: String,
+    count: usize,
+    /// Bytes read so far, shared with `ProgressReader`, and the file size.
+    bytes: Option<(Rc<Cell<u64>>, u64)>,
+    total: Option<usize>,
+    started: Instant,
+    last: Instant,
+    shown: bool,
+    enabled: bool,
+}
+
+impl Progress {
+    const QUIET_FOR: Duration = Duration::from_secs(1);
+    const EVERY: Duration = Duration::from_millis(200);
+
+    pub fn new(label: impl Into<String>) -> Self {
+        Self {
+            label: label.into(),
+            count: 0,
+            bytes: None,
+            total: None,
+            started: Instant::now(),
+            last: Instant::now(),
+            shown: false,
+            enabled: io::stderr().is_terminal(),
+        }
+    }
+
+    /// For writes, where the number of contacts is known up front.
+    pub fn with_total(mut self, total: usize) -> Self {
+        self.total = Some(total);
+        self
+    }
+
+    /// Wraps the reader of a `size`-byte file, so progress is shown as a
+    /// share of the bytes read.
+    pub fn reader<R: Read>(&mut self, inner: R, size: u64) -> ProgressReader<R> {
+        let read = Rc::new(Cell::new(0));
+        self.bytes = Some((read.clone(), size));
+        ProgressReader { inner, read }
+    }
+
+    pub fn count(&self) -> usize {
+        self.count
+    }
+
+    /// Counts one more contact.
+    pub fn tick(&mut self) {
+        self.count += 1;
+        if self.enabled
+            && self.started.elapsed() >= Self::QUIET_FOR
+            && self.last.elapsed() >= Self::EVERY
+        {
+            self.last = Instant::now();
+            self.show();
+        }
+    }
+
+    fn show(&mut self) {
+        let percent = match (&self.bytes, self.total) {
+            (Some((read, size)), _) if *size > 0 => Some(read.get() * 100 / size),
+            (_, Some(total)) if total > 0 => Some((self.count * 100 / total) as u64),
+            _ => None,
+        };
+        match percent {
+            Some(percent) => eprint!(
+                "\r⏳ {}: {} contacts ({}%)",
+                self.label,
+                self.count,
+                percent.min(100)
+            ),
+            None => eprint!("\r⏳ {}: {} contacts", self.label, self.count),
+        }
+        self.shown = true;
+    }
+
+    /// Ends the progress line, if one was shown; returns the count.
+    pub fn finish(mut self) -> usize {
+        if self.shown {
+            if let Some((read, size)) = &self.bytes {
+                read.set(*size);
+            }
+            self.show();
+            eprintln!();
+        }
+        self.count
+    }
+}
+
+/// Counts the bytes read through it for `Progress`.
+pub struct ProgressReader<R> {
+    inner: R,
+    read: Rc<Cell<u64>>,
+}
+
+impl<R: Read> Read for ProgressReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        self.read.set(self.read.get() + n as u64);
+        Ok(n)
+    }
+}
+
+impl<R: Seek> Seek for ProgressReader<R> {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        let at = self.inner.seek(pos)?;
+        self.read.set(at);
+        Ok(at)
+    }
+}
+
+/// Opens `path` for reading with a progress line labelled `label`.
+pub fn open(
+    path: impl AsRef<Path>,
+    label: impl Into<String>,
+) -> Result<(BufReader<ProgressReader<File>>, Progress), AppError> {
+    let file = File::open(path)?;
+    let size = file.metadata()?.len();
+    let mut progress = Progress::new(label);
+    let reader = BufReader::new(progress.reader(file, size));
+    Ok((reader, progress))
+}
+
+/// Reads every contact of a JSON or NDJSON file (by extension) and returns
+/// how many there were.
+pub fn read_contacts(
+    path: impl AsRef<Path>,
+    on_contact: &mut OnContact,
+) -> Result<usize, AppError> {
+    read_contacts_as(&path, Format::from_path(&path), on_contact)
+}
+
+/// Like `read_contacts`, whatever the file is called.
+pub fn read_contacts_as(
+    path: impl AsRef<Path>,
+    format: Format,
+    on_contact: &mut OnContact,
+) -> Result<usize, AppError> {
+    let path = path.as_ref();
+    let (reader, mut progress) = open(path, format!("Reading {}", path.display()))?;
+    let mut counted = |contact: Contact| {
+        progress.tick();
+        on_contact(contact)
+    };
+    match format {
+        Format::Ndjson => {
+            read_ndjson(reader, &mut counted)?;
+        }
+        Format::Json => {
+            read_json(reader, &mut counted)?;
+        }
+        Format::Csv => {
+            return Err(AppError::Parse(
+                "CSV files are read with a column profile; use import-csv".to_string(),
+            ));
+        }
+    }
+    Ok(progress.finish())
+}
+
+/// First line of a `.jsonl` contacts store; the contacts follow, one per line.
+#[derive(Serialize, Deserialize)]
+struct NdjsonHeader<G> {
+    schema_version: u64,
+    #[serde(default = "Vec::new")]
+    groups: Vec<G>,
+}
+
+/// One contact per line; blank lines are skipped and errors name the line.
+/// A `{"schema_version": …, "groups": […]}` first line, as our own `.jsonl`
+/// stores have, gives the layout of the lines and the groups; without one
+/// the contacts are taken to be current. Returns the groups.
+pub fn read_ndjson(
+    reader: impl BufRead,
+    on_contact: &mut OnContact,
+) -> Result<Vec<Group>, AppError> {
+    read_ndjson_lines(reader, on_contact, false)
+}
+
+/// Like `read_ndjson`, for one of our `.jsonl` stores: every save writes the
+/// header line, so a file without one (an empty one included) is damaged
+/// rather than a store with no contacts.
+pub fn read_ndjson_store(
+    reader: impl BufRead,
+    on_contact: &mut OnContact,
+) -> Result<Vec<Group>, AppError> {
+    read_ndjson_lines(reader, on_contact, true)
+}
+
+fn read_ndjson_lines(
+    mut reader: impl BufRead,
+    on_contact: &mut OnContact,
+    require_header: bool,
+) -> Result<Vec<Group>, AppError> {
+    let mut line = String::new();
+    let mut number = 0;
+    let mut version = None;
+    let mut groups = Vec::new();
+    loop {
+        line.clear();
+        if reader.read_line(&mut line)? == 0 {
+            if require_header && version.is_none() {
+                return Err(AppError::Parse("Contacts file is empty".to_string()));
+            }
+            return Ok(groups);
+        }
+        number += 1;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let bad = |e: &dyn fmt::Display| AppError::Parse(format!("Line {}: {}", number, e));
+
+        let contact = match version {
+            Some(CURRENT_SCHEMA_VERSION) => serde_json::from_str(&line).map_err(|e| bad(&e))?,
+            Some(version) => {
+                let value = serde_json::from_str(&line).map_err(|e| bad(&e))?;
+                migrations::upgrade_contact(value, version).map_err(|e| bad(&e))?
+            }
+            None => {
+                let value: Value = serde_json::from_str(&line).map_err(|e| bad(&e))?;
+                if value.get("schema_version").is_some() {
+                    let header: NdjsonHeader<Group> =
+                        serde_json::from_value(value).map_err(|e| bad(&e))?;
+                    version = Some(migrations::supported_version(header.schema_version)?);
+                    groups = header.groups;
+                    continue;
+                }
+                if require_header {
+                    return Err(bad(&"missing the schema_version header line"));
+                }
+                version = Some(CURRENT_SCHEMA_VERSION);
+                serde_json::from_value(value).map_err(|e| bad(&e))?
+            }
+        };
+        on_contact(contact)?;
+    }
+}
+
+/// The schema version of an NDJSON file: its header's, else the current one.
+pub fn ndjson_version(mut reader: impl BufRead) -> Result<u64, AppError> {
+    let mut line = String::new();
+    while reader.read_line(&mut line)? > 0 {
+        if line.trim().is_empty() {
+            line.clear();
+            continue;
+        }
+        let value: Value =
+            serde_json::from_str(&line).map_err(|e| AppError::Parse(format!("Line 1: {}", e)))?;
+        if let Some(version) = value.get("schema_version") {
+            let version = version
+                .as_u64()
+                .ok_or(AppError::Parse("Bad schema_version".to_string()))?;
+            return migrations::supported_version(version);
+        }
+        break;
+    }
+    Ok(CURRENT_SCHEMA_VERSION)
+}
+
+/// Writes a `.jsonl` contacts store: the header line, then one contact per
+/// line in the order given.
+pub fn write_ndjson_document(
+    mut out: impl Write,
+    contacts: &[&Contact],
+    groups: &[&Group],
+) -> Result<(), AppError> {
+    let json_error = |e: serde_json::Error| AppError::Parse(format!("Saving error...: {}", e));
+    let header = NdjsonHeader {
+        schema_version: CURRENT_SCHEMA_VERSION,
+        groups: groups.to_vec(),
+    };
+    serde_json::to_writer(&mut out, &header).map_err(json_error)?;
+    out.write_all(b"\n")?;
+    for contact in contacts {
+        serde_json::to_writer(&mut out, contact).map_err(json_error)?;
+        out.write_all(b"\n")?;
+    }
+    Ok(out.flush()?)
+}
+
+/// A contacts file in any schema version, or a bare array of contacts;
+/// returns the groups. Contacts of older layouts are upgraded one by one.
+/// Envelopes with `contacts` ahead of `schema_version` are loaded whole;
+/// the old text format is not read here (see `migrations::load_legacy`). Empty input is an error, not an
+/// empty list, so a truncated file is never taken for one with no contacts.
+pub fn read_json(
+    mut reader: impl BufRead + Seek,
+    on_contact: &mut OnContact,
+) -> Result<Vec<Group>, AppError> {
+    let mut failed = None;
+    let mut out_of_order = false;
+    let result = match first_byte(&mut reader)? {
+        Some(b'[') => {
+            let mut de = serde_json::Deserializer::from_reader(&mut reader);
+            Contacts {
+                version: 1,
+                on_contact: &mut *on_contact,
+                failed: &mut failed,
+            }
+            .deserialize(&mut de)
+            .and_then(|()| de.end())
+            .map(|()| Vec::new())
+        }
+        Some(b'{') => {
+            let mut de = serde_json::Deserializer::from_reader(&mut reader);
+            Envelope {
+                on_contact: &mut *on_contact,
+                failed: &mut failed,
+                out_of_order: &mut out_of_order,
+            }
+            .deserialize(&mut de)
+            .and_then(|groups| de.end().map(|()| groups))
+        }
+        None => return Err(AppError::Parse("Contacts file is empty".to_string())),
+        _ => return read_whole(reader, on_contact),
+    };
+
+    match result {
+        Ok(groups) => Ok(groups),
+        Err(_) if out_of_order => {
+            reader.seek(SeekFrom::Start(0))?;
+            read_whole(reader, on_contact)
+        }
+        // What went wrong inside a callback, rather than serde's copy of it
+        Err(e) => Err(failed.unwrap_or(AppError::Parse(format!("Error, JSON... : {}", e)))),
+    }
+}
+
+fn read_whole(mut reader: impl Read, on_contact: &mut OnContact) -> Result<Vec<Group>, AppError> {
+    let mut data = String::new();
+    reader.read_to_string(&mut data)?;
+    let (document, _) = migrations::load_document(&data)?;
+    drop(data);
+    for contact in document.contacts {
+        on_contact(contact)?;
+    }
+    Ok(document.groups)
+}
+
+/// Skips leading whitespace and returns the first byte without consuming it.
+fn first_byte(reader: &mut impl BufRead) -> Result<Option<u8>, AppError> {
+    loop {
+        let buf = reader.fill_buf()?;
+        let Some(&byte) = buf.first() else {
+            return Ok(None);
+        };
+        if byte.is_ascii_whitespace() {
+            reader.consume(1);
+        } else {
+            return Ok(Some(byte));
+        }
+    }
+}
+
+/// The contacts array, handed to `on_contact` element by element.
+struct Contacts<'a, 'b> {
+    version: u64,
+    on_contact: &'a mut OnContact<'b>,
+    failed: &'a mut Option<AppError>,
+}
+
+impl<'de> DeserializeSeed<'de> for Contacts<'_, '_> {
+    type Value = ();
+
+    fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> Result<(), D::Error> {
+        deserializer.deserialize_seq(self)
+    }
+}
+
+impl<'de> Visitor<'de> for Contacts<'_, '_> {
+    type Value = ();
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "a list of contacts")
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<(), A::Error> {
+        loop {
+            let contact = if self.version == CURRENT_SCHEMA_VERSION {
+                seq.next_element::<Contact>()?
+            } else {
+                match seq.next_element::<Value>()? {
+                    Some(value) => Some(
+                        migrations::upgrade_contact(value, self.version)
+                            .map_err(de::Error::custom)?,
+                    ),
+                    None => None,
+                }
+            };
+            let Some(contact) = contact else {
+                return Ok(());
+            };
+            if let Err(e) = (self.on_contact)(contact) {
+                let message = e.to_string();
+                *self.failed = Some(e);
+                return Err(de::Error::custom(message));
+            }
+        }
+    }
+}
+
+/// `{ "schema_version": …, "contacts": […], "groups": […] }`; we always
+/// write `schema_version` first, so the layout of the contacts is known
+/// before they arrive.
+struct Envelope<'a, 'b> {
+    on_contact: &'a mut OnContact<'b>,
+    failed: &'a mut Option<AppError>,
+    out_of_order: &'a mut bool,
+}
+
+impl<'de> DeserializeSeed<'de> for Envelope<'_, '_> {
+    type Value = Vec<Group>;
+
+    fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> Result<Vec<Group>, D::Error> {
+        deserializer.deserialize_map(self)
+    }
+}
+
+impl<'de> Visitor<'de> for Envelope<'_, '_> {
+    type Value = Vec<Group>;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "a contacts file")
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<Vec<Group>, A::Error> {
+        let mut version = None;
+        let mut groups = Vec::new();
+        while let Some(key) = map.next_key::<String>()? {
+            match key.as_str() {
+                "schema_version" => {
+                    let value = map.next_value()?;
+                    migrations::supported_version(value).map_err(de::Error::custom)?;
+                    version = Some(value);
+                }
+                "contacts" => {
+                    let Some(version) = version else {
+                        *self.out_of_order = true;
+                        return Err(de::Error::custom("contacts before schema_version"));
+                    };
+                    map.next_value_seed(Contacts {
+                        version,
+                        on_contact: &mut *self.on_contact,
+                        failed: &mut *self.failed,
+                    })?;
+                }
+                "groups" => groups = map.next_value()?,
+                _ => {
+                    map.next_value::<IgnoredAny>()?;
+                }
+            }
+        }
+        if version.is_none() {
+            return Err(de::Error::custom("Missing schema_version"));
+        }
+        Ok(groups)
+    }
+}
+
+enum Sink<W: Write> {
+    Json(W),
+    Ndjson(W),
+    Csv(Box<csv::Writer<W>>, CsvProfile),
+}
+
+/// Writes contacts to a JSON array, NDJSON or CSV one at a time.
+pub struct ContactWriter<W: Write> {
+    sink: Sink<W>,
+    progress: Progress,
+}
+
+impl ContactWriter<io::BufWriter<File>> {
+    /// Creates `path` for `total` contacts in `format`; CSV uses `profile`.
+    pub fn create(
+        path: impl AsRef<Path>,
+        format: Format,
+        profile: &CsvProfile,
+        total: usize,
+    ) -> Result<Self, AppError> {
+        let path = path.as_ref();
+        let out = io::BufWriter::new(File::create(path)?);
+        let progress = Progress::new(format!("Writing {}", path.display())).with_total(total);
+        match format {
+            Format::Json => Self::json(out, progress),
+            Format::Ndjson => Ok(Self::ndjson(out, progress)),
+            Format::Csv => Self::csv(out, profile, progress),
+        }
+    }
+}
+
+impl<W: Write> ContactWriter<W> {
+    pub fn json(mut out: W, progress: Progress) -> Result<Self, AppError> {
+        out.write_all(b"[")?;
+        Ok(Self {
+            sink: Sink::Json(out),
+            progress,
+        })
+    }
+
+    pub fn ndjson(out: W, progress: Progress) -> Self {
+        Self {
+            sink: Sink::Ndjson(out),
+            progress,
+        }
+    }
+
+    /// Writes the profile's header straight away.
+    pub fn csv(out: W, profile: &CsvProfile, progress: Progress) -> Result<Self, AppError> {
+        let mut wtr = csv::WriterBuilder::new()
+            .delimiter(profile.delimiter())
+            .from_writer(out);
+        wtr.write_record(profile.header())
+            .map_err(|e| AppError::Parse(e.to_string()))?;
+        Ok(Self {
+            sink: Sink::Csv(Box::new(wtr), profile.clone()),
+            progress,
+        })
+    }
+
+    pub fn write(&mut self, contact: &Contact) -> Result<(), AppError> {
+        self.write_with_groups(contact, &[])
+    }
+
+    /// `groups` fills the group columns of a CSV file; JSON keeps none.
+    pub fn write_with_groups(
+        &mut self,
+        contact: &Contact,
+        groups: &[&str],
+    ) -> Result<(), AppError> {
+        let json_error = |e: serde_json::Error| AppError::Parse(format!("Saving error...: {}", e));
+        match &mut self.sink {
+            Sink::Json(out) => {
+                out.write_all(if self.progress.count() == 0 {
+                    b"\n  "
+                } else {
+                    b",\n  "
+                })?;
+                serde_json::to_writer(&mut *out, contact).map_err(json_error)?;
+            }
+            Sink::Ndjson(out) => {
+                serde_json::to_writer(&mut *out, contact).map_err(json_error)?;
+                out.write_all(b"\n")?;
+            }
+            Sink::Csv(wtr, profile) => wtr
+                .write_record(profile.write_contact(contact, groups))
+                .map_err(|e| AppError::Parse(e.to_string()))?,
+        }
+        self.progress.tick();
+        Ok(())
+    }
+
+    /// Closes the array and flushes; returns how many contacts were written.
+    pub fn finish(self) -> Result<usize, AppError> {
+        match self.sink {
+            Sink::Json(mut out) => {
+                out.write_all(b"\n]\n")?;
+                out.flush()?;
+            }
+            Sink::Ndjson(mut out) => out.flush()?,
+            Sink::Csv(mut wtr, _) => wtr.flush()?,
+        }
+        Ok(self.progress.finish())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use chrono::Utc;
+
+    use super::*;
+
+    fn contact(name: &str, phone: &str) -> Contact {
+        Contact::new(name, phone, "", vec![], Utc::now(), Utc::now())
+    }
+
+    fn read_all(data: &str) -> Result<(Vec<Contact>, Vec<Group>), AppError> {
+        let mut contacts = Vec::new();
+        let groups = read_json(Cursor::new(data), &mut |c| {
+            contacts.push(c);
+            Ok(())
+        })?;
+        Ok((contacts, groups))
+    }
+
+    #[test]
+    fn test_json_is_read_in_any_layout() {
+        let alice = contact("Alice Johnson", "08123456789");
+        let saved = migrations::to_document(&[&alice]).unwrap();
+        let (contacts, _) = read_all(&saved).unwrap();
+        assert_eq!(contacts, vec![alice.clone()]);
+
+        // Older layouts are upgraded contact by contact
+        let bare = r#"[{"id": "7", "name": "Bob Smith", "phone": ["1"], "email": "b@x.com"}]"#;
+        let (contacts, _) = read_all(bare).unwrap();
+        assert!(!contacts[0].id.is_nil());
+        assert_eq!(contacts[0].family_name.as_deref(), Some("Smith"));
+        assert_eq!(contacts[0].primary_email(), "b@x.com");
+
+        // Key order we don't write ourselves falls back to a whole-file load
+        let reordered = format!(
+            r#"{{"contacts": [{}], "schema_version": 6, "groups": []}}"#,
+            serde_json::to_string(&alice).unwrap()
+        );
+        assert_eq!(read_all(&reordered).unwrap().0, vec![alice]);
+
+        assert!(read_all(r#"{"schema_version": 99, "contacts": []}"#).is_err());
+        assert!(read_all(r#"[{"name": "Broken""#).is_err());
+        assert!(matches!(read_all(""), Err(AppError::Parse(_))));
+        assert!(matches!(read_all(" \n"), Err(AppError::Parse(_))));
+
+        // The callback's own error comes back unchanged
+        let err = read_json(Cursor::new(saved), &mut |_| {
+            Err(AppError::Conflict("stop".to_string()))
+        });
+        assert!(matches!(err, Err(AppError::Conflict(_))));
+    }
+
+    #[test]
+    fn test_writers_round_trip() {
+        let contacts = [
+            contact("Alice Johnson", "08123456789"),
+            contact("Bob Smith", "+1-202-555-0147"),
+        ];
+        for format in [Format::Json, Format::Ndjson] {
+            let mut out = Vec::new();
+            let mut writer = match format {
+                Format::Json => ContactWriter::json(&mut out, Progress::new("test")).unwrap(),
+                _ => ContactWriter::ndjson(&mut out, Progress::new("test")),
+            };
+            for c in &contacts {
+                writer.write(c).unwrap();
+            }
+            assert_eq!(writer.finish().unwrap(), 2);
+
+            let mut read = Vec::new();
+            let mut on_contact = |c| {
+                read.push(c);
+                Ok(())
+            };
+            match format {
+                Format::Json => {
+                    read_json(Cursor::new(&out), &mut on_contact).unwrap();
+                }
+                _ => {
+                    read_ndjson(Cursor::new(&out), &mut on_contact).unwrap();
+                }
+            }
+            assert_eq!(read, contacts, "{}", format);
+        }
+
+        let err = read_ndjson(Cursor::new("{}\n\nnot json\n"), &mut |_| Ok(()));
+        assert!(err.unwrap_err().to_string().contains("Line 1"));
+        let err = read_ndjson(Cursor::new("\n\nnot json\n"), &mut |_| Ok(()));
+        assert!(err.unwrap_err().to_string().contains("Line 3"));
+    }
+}
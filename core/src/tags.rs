@@ -0,0 +1,75 @@
+//! Namespaced tags: `work/project-x` is a child of `work`, and filtering on a
+//! tag also matches its children.
+
+/// Separates the levels of a tag.
+pub const TAG_SEPARATOR: char = '/';
+
+/// Trims every level and drops empty ones: ` work//project-x/ ` -> `work/project-x`.
+pub fn normalize(tag: &str) -> String {
+    tag.split(TAG_SEPARATOR)
+        .map(str::trim)
+        .filter(|level| !level.is_empty())
+        .collect::<Vec<_>>()
+        .join(&TAG_SEPARATOR.to_string())
+}
+
+/// Normalizes every tag, dropping empty and repeated ones.
+pub fn normalize_all(tags: &[String]) -> Vec<String> {
+    let mut normalized: Vec<String> = Vec::with_capacity(tags.len());
+    for tag in tags.iter().map(|t| normalize(t)) {
+        if !tag.is_empty() && !normalized.contains(&tag) {
+            normalized.push(tag);
+        }
+    }
+    normalized
+}
+
+/// Whether `tag` is `filter` or one of its children.
+pub fn matches(tag: &str, filter: &str) -> bool {
+    let filter = filter.trim_end_matches(TAG_SEPARATOR);
+    tag == filter
+        || tag
+            .strip_prefix(filter)
+            .is_some_and(|rest| rest.starts_with(TAG_SEPARATOR))
+}
+
+/// `work`, `work/project-x` for `work/project-x`: the tag and every parent.
+pub fn ancestors(tag: &str) -> impl Iterator<Item = &str> {
+    tag.match_indices(TAG_SEPARATOR)
+        .map(|(i, _)| &tag[..i])
+        .chain(std::iter::once(tag))
+}
+
+/// `tag` with its `old` prefix replaced by `new`, if `tag` is `old` or a child.
+pub fn rename(tag: &str, old: &str, new: &str) -> Option<String> {
+    matches(tag, old).then(|| format!("{}{}", new, &tag[old.len()..]))
+}
+
+/// Levels below the top one, for indenting a tag tree.
+pub fn depth(tag: &str) -> usize {
+    tag.matches(TAG_SEPARATOR).count()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_tag_hierarchy() {
+        assert_eq!(normalize(" work//project-x/ "), "work/project-x");
+        assert!(matches("work/project-x", "work"));
+        assert!(matches("work", "work"));
+        assert!(!matches("workshop", "work"));
+        assert!(!matches("work", "work/project-x"));
+
+        let levels: Vec<_> = ancestors("work/project-x/qa").collect();
+        assert_eq!(levels, vec!["work", "work/project-x", "work/project-x/qa"]);
+
+        assert_eq!(
+            rename("work/project-x", "work", "job").as_deref(),
+            Some("job/project-x")
+        );
+        assert_eq!(rename("workshop", "work", "job"), None);
+        assert_eq!(depth("work/project-x"), 1);
+    }
+}
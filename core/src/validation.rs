@@ -37,13 +37,24 @@ pub fn check_contact_duplicates(name: String, mut contact_list: Vec<Contact>) ->
     contact_list.len() > 1
 }
 
+/// Letters and spaces, plus the punctuation real names use: `O'Brien`,
+/// `Jean-Luc`, `Dr. Smith Jr.`
 pub fn validate_name(name: &str) -> bool {
-    !name.trim().is_empty() && name.chars().all(|c| c.is_alphabetic() || c.is_whitespace())
+    name.chars().any(char::is_alphabetic)
+        && name
+            .chars()
+            .all(|c| c.is_alphabetic() || c.is_whitespace() || "'’-.,".contains(c))
 }
 
+/// Accepts 10 to 15 digits with an optional leading `+`; spaces, dashes,
+/// dots and parentheses are ignored.
 pub fn validate_phone_number(phone: &str) -> bool {
-    let re = Regex::new(r"^\d{10,}$").unwrap();
-    re.is_match(phone)
+    let stripped: String = phone
+        .chars()
+        .filter(|c| !matches!(c, ' ' | '-' | '.' | '(' | ')'))
+        .collect();
+    let re = Regex::new(r"^\+?\d{10,15}$").unwrap();
+    re.is_match(&stripped)
 }
 
 pub fn validate_email(email: &str) -> bool {
@@ -51,6 +62,26 @@ pub fn validate_email(email: &str) -> bool {
     re.is_match(email)
 }
 
+/// Everything wrong with a contact read from a file, one reason per problem;
+/// empty when it can be imported.
+pub fn contact_problems(contact: &Contact) -> Vec<String> {
+    let mut problems = Vec::new();
+    if !validate_name(&contact.name) {
+        problems.push(format!("invalid name '{}'", contact.name));
+    }
+    for phone in contact
+        .phone
+        .iter()
+        .filter(|p| !validate_phone_number(&p.raw))
+    {
+        problems.push(format!("invalid phone number '{}'", phone.raw));
+    }
+    for email in contact.email.iter().filter(|e| !validate_email(&e.address)) {
+        problems.push(format!("invalid email '{}'", email.address));
+    }
+    problems
+}
+
 #[cfg(test)]
 mod tests {
 
@@ -59,13 +90,20 @@ mod tests {
     #[test]
     fn test_validate_name() {
         assert!(validate_name("Alice"));
+        assert!(validate_name("Conan O'Brien"));
+        assert!(validate_name("Jean-Luc Picard"));
+        assert!(validate_name("Dr. Martin Luther King Jr."));
         assert!(!validate_name("123Bob"));
+        assert!(!validate_name("--"));
     }
 
     #[test]
     fn test_validate_phone_number() {
         assert!(validate_phone_number("08123456789"));
         assert!(!validate_phone_number("1234"));
+        assert!(validate_phone_number("+1-202-555-0147"));
+        assert!(validate_phone_number("(0801) 234 5678"));
+        assert!(!validate_phone_number("0801-CALL-NOW"));
     }
 
     #[test]
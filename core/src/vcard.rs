@@ -0,0 +1,730 @@
+//! vCard 3.0 and 4.0 files (`.vcf`) as spoken by phone and mail clients.
+//! Several cards may follow each other in one file.
+
+use std::{collections::HashMap, fmt, fs, str::FromStr};
+
+use base64::{Engine, engine::general_purpose::STANDARD as BASE64};
+use chrono::{DateTime, NaiveDate, NaiveDateTime, Utc};
+use uuid::Uuid;
+
+use crate::{
+    address::{Address, AddressLabel},
+    attachment::AttachmentStore,
+    dates::PartialDate,
+    domain::Contact,
+    email::{self, Email, EmailLabel},
+    error::AppError,
+    group::{self, Group},
+    phone::{Phone, PhoneLabel},
+    tags,
+};
+
+/// Longest line before folding, in octets (RFC 6350 §3.2).
+const LINE_LIMIT: usize = 75;
+
+/// A parsed card; the photo is returned as bytes so the caller can store it.
+#[derive(Debug, Clone)]
+pub struct VCard {
+    pub contact: Contact,
+    pub photo: Option<Vec<u8>>,
+}
+
+/// Which vCard a card is written as. 3.0 is what most phones still expect;
+/// 4.0 (RFC 6350) is read by newer clients and CardDAV servers.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Version {
+    #[default]
+    V3,
+    V4,
+}
+
+impl FromStr for Version {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim() {
+            "3" | "3.0" => Ok(Version::V3),
+            "4" | "4.0" => Ok(Version::V4),
+            other => Err(format!("Unknown vCard version '{}'. Use: 3.0 | 4.0", other)),
+        }
+    }
+}
+
+impl fmt::Display for Version {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Version::V3 => write!(f, "3.0"),
+            Version::V4 => write!(f, "4.0"),
+        }
+    }
+}
+
+/// One `BEGIN:VCARD` … `END:VCARD` block for `contact`. `photo` is the
+/// content of `contact.photo`; `groups` are added to `CATEGORIES`.
+pub fn to_vcard(
+    contact: &Contact,
+    photo: Option<&[u8]>,
+    groups: &[&str],
+    version: Version,
+) -> String {
+    let v4 = version == Version::V4;
+    let mut lines = vec![
+        "BEGIN:VCARD".to_string(),
+        format!("VERSION:{}", version),
+        if v4 {
+            format!("UID:urn:uuid:{}", contact.id)
+        } else {
+            format!("UID:{}", contact.id)
+        },
+        format!("FN:{}", escape(&contact.name)),
+        format!(
+            "N:{}",
+            [
+                &contact.family_name,
+                &contact.given_name,
+                &contact.middle_name,
+                &contact.prefix,
+                &contact.suffix,
+            ]
+            .map(|part| escape(part.as_deref().unwrap_or_default()))
+            .join(";")
+        ),
+    ];
+    if let Some(nickname) = &contact.nickname {
+        lines.push(format!("NICKNAME:{}", escape(nickname)));
+    }
+    if contact.organization.is_some() || contact.department.is_some() {
+        lines.push(format!(
+            "ORG:{};{}",
+            escape(contact.organization.as_deref().unwrap_or_default()),
+            escape(contact.department.as_deref().unwrap_or_default())
+        ));
+    }
+    if let Some(title) = &contact.title {
+        lines.push(format!("TITLE:{}", escape(title)));
+    }
+
+    // `X-OTHER` is ours: neither version has a type for "other", and the
+    // plain `VOICE` it comes with reads back as the default label
+    for phone in &contact.phone {
+        let kind = match phone.label {
+            PhoneLabel::Mobile => "CELL",
+            PhoneLabel::Work => "WORK",
+            PhoneLabel::Home => "HOME",
+            PhoneLabel::Fax => "FAX",
+            PhoneLabel::Other => "VOICE,X-OTHER",
+        };
+        if v4 {
+            // A tel: URI can't hold spaces, only `-` as a visual separator
+            let number = phone.raw.replace(' ', "-");
+            lines.push(format!("TEL;VALUE=uri;TYPE={}:tel:{}", kind, number));
+        } else {
+            lines.push(format!("TEL;TYPE={}:{}", kind, escape(&phone.raw)));
+        }
+    }
+    for address in &contact.email {
+        let kind = match address.label {
+            EmailLabel::Work => "WORK",
+            EmailLabel::Personal => "HOME",
+            EmailLabel::Other => "X-OTHER",
+        };
+        let line = match (v4, address.primary) {
+            (true, true) => format!("EMAIL;TYPE={};PREF=1", kind),
+            (true, false) => format!("EMAIL;TYPE={}", kind),
+            (false, true) => format!("EMAIL;TYPE=INTERNET,{},PREF", kind),
+            (false, false) => format!("EMAIL;TYPE=INTERNET,{}", kind),
+        };
+        lines.push(format!("{}:{}", line, escape(&address.address)));
+    }
+    for address in &contact.addresses {
+        let kind = match address.label {
+            AddressLabel::Home => "HOME",
+            AddressLabel::Work => "WORK",
+            AddressLabel::Other => "POSTAL",
+        };
+        let parts = [
+            &address.street,
+            &address.city,
+            &address.region,
+            &address.postal_code,
+            &address.country,
+        ]
+        .map(|part| escape(part.as_deref().unwrap_or_default()));
+        lines.push(format!("ADR;TYPE={}:;;{}", kind, parts.join(";")));
+    }
+
+    if let Some(birthday) = &contact.birthday {
+        lines.push(format!("BDAY:{}", date_value(birthday, version)));
+    }
+    if let Some(anniversary) = &contact.anniversary {
+        let name = if v4 { "ANNIVERSARY" } else { "X-ANNIVERSARY" };
+        lines.push(format!("{}:{}", name, date_value(anniversary, version)));
+    }
+    if let Some(notes) = &contact.notes {
+        lines.push(format!("NOTE:{}", escape(notes)));
+    }
+
+    let categories: Vec<String> = contact
+        .tags
+        .iter()
+        .map(String::as_str)
+        .chain(groups.iter().copied())
+        .map(escape)
+        .collect();
+    if !categories.is_empty() {
+        lines.push(format!("CATEGORIES:{}", categories.join(",")));
+    }
+
+    if let (Some(attachment), Some(bytes)) = (&contact.photo, photo) {
+        if v4 {
+            lines.push(format!(
+                "PHOTO:data:{};base64,{}",
+                attachment.media_type,
+                BASE64.encode(bytes)
+            ));
+        } else {
+            let kind = attachment
+                .media_type
+                .trim_start_matches("image/")
+                .to_uppercase();
+            lines.push(format!(
+                "PHOTO;ENCODING=b;TYPE={}:{}",
+                kind,
+                BASE64.encode(bytes)
+            ));
+        }
+    }
+    lines.push(format!(
+        "REV:{}",
+        contact.updated_at.format("%Y%m%dT%H%M%SZ")
+    ));
+    lines.push("END:VCARD".to_string());
+
+    lines.iter().map(|line| fold(line)).collect()
+}
+
+/// 4.0 only has the basic ISO 8601 forms, `19900517` and `--0517`.
+fn date_value(date: &PartialDate, version: Version) -> String {
+    match version {
+        Version::V3 => date.to_string(),
+        Version::V4 => match date.year {
+            Some(_) => date.to_string().replace('-', ""),
+            None => format!("--{}", date.to_string().replace('-', "")),
+        },
+    }
+}
+
+/// Every card in `data`.
+pub fn parse(data: &str) -> Result<Vec<VCard>, AppError> {
+    let mut cards = Vec::new();
+    let mut properties: Option<Vec<Property>> = None;
+
+    for (number, line) in unfold(data).into_iter().enumerate() {
+        if line.trim().is_empty() {
+            continue;
+        }
+        let property = Property::parse(&line).ok_or(AppError::Parse(format!(
+            "vCard line {}: expected NAME:VALUE, got '{}'",
+            number + 1,
+            line
+        )))?;
+
+        match (property.name.as_str(), &mut properties) {
+            ("BEGIN", None) if property.value.eq_ignore_ascii_case("VCARD") => {
+                properties = Some(Vec::new());
+            }
+            ("END", Some(_)) if property.value.eq_ignore_ascii_case("VCARD") => {
+                let card = card_from(properties.take().unwrap_or_default())
+                    .map_err(|e| AppError::Parse(format!("vCard #{}: {}", cards.len() + 1, e)))?;
+                cards.push(card);
+            }
+            ("BEGIN", Some(_)) => {
+                return Err(AppError::Parse(format!(
+                    "vCard #{} is not closed before the next BEGIN",
+                    cards.len() + 1
+                )));
+            }
+            (_, Some(card)) => card.push(property),
+            (_, None) => {
+                return Err(AppError::Parse(format!(
+                    "vCard line {} is outside BEGIN:VCARD/END:VCARD",
+                    number + 1
+                )));
+            }
+        }
+    }
+
+    if properties.is_some() {
+        return Err(AppError::Parse(format!(
+            "vCard #{} has no END:VCARD",
+            cards.len() + 1
+        )));
+    }
+    Ok(cards)
+}
+
+/// Writes `contacts` to `path` as `version` cards with their photos
+/// embedded; `groups` (may be empty) are added to each card's `CATEGORIES`.
+pub fn export_vcf(
+    path: &str,
+    contacts: &[Contact],
+    attachments: &AttachmentStore,
+    groups: &HashMap<Uuid, Group>,
+    version: Version,
+) -> Result<(), AppError> {
+    let mut data = String::new();
+    for contact in contacts {
+        let photo = match &contact.photo {
+            Some(photo) => match attachments.get(&photo.hash) {
+                Ok(bytes) => Some(bytes),
+                Err(e) => {
+                    println!("⚠️ Leaving out the photo of {}: {}", contact.name, e);
+                    None
+                }
+            },
+            None => None,
+        };
+        let groups = group::names_of(groups.values(), contact.id);
+        data.push_str(&to_vcard(contact, photo.as_deref(), &groups, version));
+    }
+    fs::write(path, data)?;
+    Ok(())
+}
+
+/// Reads every card in `path`, storing their photos in `attachments`.
+pub fn import_vcf(path: &str, attachments: &AttachmentStore) -> Result<Vec<Contact>, AppError> {
+    let data = fs::read_to_string(path)?;
+    let mut contacts = Vec::new();
+    for card in parse(&data)? {
+        let mut contact = card.contact;
+        if let Some(bytes) = card.photo {
+            let extension = crate::attachment::media_type("", &bytes).trim_start_matches("image/");
+            match attachments.put(&format!("photo.{}", extension), &bytes) {
+                Ok(photo) => contact.photo = Some(photo),
+                Err(e) => println!("⚠️ Leaving out the photo of {}: {}", contact.name, e),
+            }
+        }
+        contacts.push(contact);
+    }
+
+    if contacts.is_empty() {
+        return Err(AppError::Parse("Error: No vCard data".to_string()));
+    }
+    Ok(contacts)
+}
+
+/// `NAME;PARAM=A,B:value`, with any `item1.` group prefix dropped.
+struct Property {
+    name: String,
+    /// Lower-cased `TYPE` values plus bare 2.1-style parameters such as `CELL`.
+    types: Vec<String>,
+    encoding: Option<String>,
+    value: String,
+}
+
+impl Property {
+    fn parse(line: &str) -> Option<Self> {
+        let mut in_quotes = false;
+        let colon = line.char_indices().find_map(|(i, c)| {
+            match c {
+                '"' => in_quotes = !in_quotes,
+                ':' if !in_quotes => return Some(i),
+                _ => {}
+            }
+            None
+        })?;
+        let (head, value) = (&line[..colon], &line[colon + 1..]);
+
+        let mut params = head.split(';');
+        let name = params.next()?.trim();
+        let name = name.rsplit('.').next().unwrap_or(name).to_uppercase();
+        if name.is_empty() {
+            return None;
+        }
+
+        let mut types = Vec::new();
+        let mut encoding = None;
+        for param in params {
+            match param.split_once('=') {
+                Some((key, values)) if key.eq_ignore_ascii_case("TYPE") => types.extend(
+                    values
+                        .trim_matches('"')
+                        .split(',')
+                        .map(|t| t.trim().to_lowercase()),
+                ),
+                Some((key, value)) if key.eq_ignore_ascii_case("ENCODING") => {
+                    encoding = Some(value.trim().to_lowercase());
+                }
+                // 4.0's `PREF=1` for 3.0's `TYPE=PREF`
+                Some((key, _)) if key.eq_ignore_ascii_case("PREF") => {
+                    types.push("pref".to_string())
+                }
+                Some(_) => {}
+                None => types.push(param.trim().to_lowercase()),
+            }
+        }
+
+        Some(Self {
+            name,
+            types,
+            encoding,
+            value: value.to_string(),
+        })
+    }
+
+    fn has_type(&self, kind: &str) -> bool {
+        self.types.iter().any(|t| t == kind)
+    }
+}
+
+fn card_from(properties: Vec<Property>) -> Result<VCard, String> {
+    let mut contact = Contact {
+        id: Uuid::new_v4(),
+        created_at: Utc::now(),
+        updated_at: Utc::now(),
+        ..Default::default()
+    };
+    let mut full_name = None;
+    let mut has_parts = false;
+    let mut photo = None;
+
+    for property in properties {
+        let value = property.value.as_str();
+        match property.name.as_str() {
+            "UID" => {
+                let uid = value.trim().trim_start_matches("urn:uuid:");
+                if let Ok(id) = uid.parse() {
+                    contact.id = id;
+                }
+            }
+            "FN" => full_name = Some(unescape(value)).filter(|n| !n.trim().is_empty()),
+            "N" => {
+                let mut parts = split_unescaped(value, ';')
+                    .into_iter()
+                    .map(|p| Some(p).filter(|p| !p.trim().is_empty()));
+                contact.family_name = parts.next().flatten();
+                contact.given_name = parts.next().flatten();
+                contact.middle_name = parts.next().flatten();
+                contact.prefix = parts.next().flatten();
+                contact.suffix = parts.next().flatten();
+                has_parts = contact.given_name.is_some() || contact.family_name.is_some();
+            }
+            "NICKNAME" => contact.nickname = Some(unescape(value)).filter(|n| !n.is_empty()),
+            "ORG" => {
+                let mut parts = split_unescaped(value, ';')
+                    .into_iter()
+                    .map(|p| Some(p).filter(|p| !p.trim().is_empty()));
+                contact.organization = parts.next().flatten();
+                contact.department = parts.next().flatten();
+            }
+            "TITLE" => contact.title = Some(unescape(value)).filter(|t| !t.is_empty()),
+            "TEL" => {
+                let label = if property.has_type("fax") {
+                    PhoneLabel::Fax
+                } else if property.has_type("cell") {
+                    PhoneLabel::Mobile
+                } else if property.has_type("work") {
+                    PhoneLabel::Work
+                } else if property.has_type("home") {
+                    PhoneLabel::Home
+                } else if property.has_type("x-other") || property.has_type("other") {
+                    PhoneLabel::Other
+                } else {
+                    PhoneLabel::default()
+                };
+                let number = unescape(value.trim_start_matches("tel:"));
+                if !number.trim().is_empty() {
+                    contact.phone.push(Phone::with_label(number.trim(), label));
+                }
+            }
+            "EMAIL" => {
+                let label = if property.has_type("work") {
+                    EmailLabel::Work
+                } else if property.has_type("x-other") || property.has_type("other") {
+                    EmailLabel::Other
+                } else {
+                    EmailLabel::Personal
+                };
+                let mut address = Email::with_label(unescape(value).trim(), label);
+                address.primary = property.has_type("pref");
+                if !address.address.is_empty() {
+                    contact.email.push(address);
+                }
+            }
+            "ADR" => {
+                let mut parts = split_unescaped(value, ';')
+                    .into_iter()
+                    .skip(2)
+                    .map(|p| Some(p).filter(|p| !p.trim().is_empty()));
+                let address = Address {
+                    label: if property.has_type("work") {
+                        AddressLabel::Work
+                    } else if property.has_type("home") {
+                        AddressLabel::Home
+                    } else {
+                        AddressLabel::Other
+                    },
+                    street: parts.next().flatten(),
+                    city: parts.next().flatten(),
+                    region: parts.next().flatten(),
+                    postal_code: parts.next().flatten(),
+                    country: parts.next().flatten(),
+                };
+                if !address.is_empty() {
+                    contact.addresses.push(address);
+                }
+            }
+            "BDAY" => contact.birthday = parse_date(value),
+            "ANNIVERSARY" | "X-ANNIVERSARY" => contact.anniversary = parse_date(value),
+            "NOTE" => contact.notes = Some(unescape(value)).filter(|n| !n.is_empty()),
+            "CATEGORIES" => contact.tags.extend(split_unescaped(value, ',')),
+            "REV" => {
+                if let Some(rev) = parse_timestamp(value) {
+                    contact.updated_at = rev;
+                }
+            }
+            "PHOTO" => photo = decode_photo(&property),
+            _ => {}
+        }
+    }
+
+    contact.tags = tags::normalize_all(&contact.tags);
+    email::ensure_primary(&mut contact.email);
+    match full_name {
+        Some(name) if has_parts => contact.name = name.trim().to_string(),
+        Some(name) => contact.set_name(name.trim()),
+        None if has_parts => contact.name = contact.display_name(),
+        None => return Err("has neither FN nor N".to_string()),
+    }
+
+    Ok(VCard { contact, photo })
+}
+
+/// Inline photos only: `ENCODING=b` (3.0) or a `data:` URI (4.0).
+fn decode_photo(property: &Property) -> Option<Vec<u8>> {
+    let data = match property.value.strip_prefix("data:") {
+        Some(uri) => uri.split_once(";base64,")?.1,
+        None if matches!(property.encoding.as_deref(), Some("b" | "base64")) => &property.value,
+        None => return None,
+    };
+    let data: String = data.chars().filter(|c| !c.is_whitespace()).collect();
+    BASE64.decode(data).ok()
+}
+
+/// `1990-05-17`, `19900517`, `--05-17` or `--0517`, ignoring any time part.
+fn parse_date(value: &str) -> Option<PartialDate> {
+    let date = value.trim().split('T').next().unwrap_or_default();
+    let date = match date.strip_prefix("--") {
+        Some(md) if md.len() == 4 => format!("--{}-{}", &md[..2], &md[2..]),
+        _ if date.len() == 8 && date.bytes().all(|b| b.is_ascii_digit()) => {
+            format!("{}-{}-{}", &date[..4], &date[4..6], &date[6..])
+        }
+        _ => date.to_string(),
+    };
+    date.parse().ok()
+}
+
+fn parse_timestamp(value: &str) -> Option<DateTime<Utc>> {
+    let value = value.trim();
+    DateTime::parse_from_rfc3339(value)
+        .map(|t| t.with_timezone(&Utc))
+        .ok()
+        .or_else(|| {
+            NaiveDateTime::parse_from_str(value, "%Y%m%dT%H%M%SZ")
+                .ok()
+                .map(|t| t.and_utc())
+        })
+        .or_else(|| {
+            ["%Y%m%d", "%Y-%m-%d"].iter().find_map(|format| {
+                NaiveDate::parse_from_str(value, format)
+                    .ok()
+                    .and_then(|d| d.and_hms_opt(0, 0, 0))
+                    .map(|t| t.and_utc())
+            })
+        })
+}
+
+fn escape(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace(',', "\\,")
+        .replace(';', "\\;")
+        .replace("\r\n", "\\n")
+        .replace('\n', "\\n")
+}
+
+fn unescape(value: &str) -> String {
+    let mut result = String::with_capacity(value.len());
+    let mut chars = value.chars();
+    while let Some(c) = chars.next() {
+        match (c, chars.clone().next()) {
+            ('\\', Some('n' | 'N')) => {
+                result.push('\n');
+                chars.next();
+            }
+            ('\\', Some(next)) => {
+                result.push(next);
+                chars.next();
+            }
+            _ => result.push(c),
+        }
+    }
+    result
+}
+
+/// Splits on `separator` where it is not escaped, unescaping each part.
+fn split_unescaped(value: &str, separator: char) -> Vec<String> {
+    let mut parts = Vec::new();
+    let mut start = 0;
+    let mut escaped = false;
+    for (i, c) in value.char_indices() {
+        match c {
+            _ if escaped => escaped = false,
+            '\\' => escaped = true,
+            _ if c == separator => {
+                parts.push(unescape(&value[start..i]));
+                start = i + c.len_utf8();
+            }
+            _ => {}
+        }
+    }
+    parts.push(unescape(&value[start..]));
+    parts
+}
+
+/// Splits `line` into CRLF-terminated lines of at most `LINE_LIMIT` octets;
+/// continuation lines start with a space.
+fn fold(line: &str) -> String {
+    let mut folded = String::with_capacity(line.len() + line.len() / LINE_LIMIT * 3 + 2);
+    let mut width = 0;
+    for c in line.chars() {
+        if width + c.len_utf8() > LINE_LIMIT {
+            folded.push_str("\r\n ");
+            width = 1;
+        }
+        folded.push(c);
+        width += c.len_utf8();
+    }
+    folded.push_str("\r\n");
+    folded
+}
+
+fn unfold(data: &str) -> Vec<String> {
+    let mut lines: Vec<String> = Vec::new();
+    for line in data.lines() {
+        match (line.strip_prefix([' ', '\t']), lines.last_mut()) {
+            (Some(rest), Some(previous)) => previous.push_str(rest),
+            _ => lines.push(line.to_string()),
+        }
+    }
+    lines
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::attachment::Attachment;
+
+    #[test]
+    fn test_vcard_round_trip() {
+        let mut contact = Contact::new(
+            "Dr. Ada Lovelace",
+            "08123456789",
+            "ada@work.com",
+            vec!["work/analytics".into(), "friends".into()],
+            Utc::now(),
+            Utc::now(),
+        );
+        contact.organization = Some("Analytical Engines; Ltd".to_string());
+        contact.notes = Some("Met at the conference,\nprefers email".to_string());
+        contact.birthday = Some("--12-10".parse().unwrap());
+        contact
+            .email
+            .push(Email::with_label("ada@home.com", EmailLabel::Personal));
+
+        let card = to_vcard(&contact, None, &["Family"], Version::V3);
+        assert!(card.lines().all(|l| l.len() <= LINE_LIMIT));
+        assert!(card.contains("CATEGORIES:work/analytics,friends,Family\r\n"));
+
+        let parsed = parse(&format!("{}{}", card, card)).unwrap();
+        assert_eq!(parsed.len(), 2);
+        let back = &parsed[0].contact;
+        assert_eq!(back.id, contact.id);
+        assert_eq!(back.name, "Dr. Ada Lovelace");
+        assert_eq!(back.prefix.as_deref(), Some("Dr."));
+        assert_eq!(back.phone, contact.phone);
+        assert_eq!(back.email, contact.email);
+        assert_eq!(back.organization, contact.organization);
+        assert_eq!(back.notes, contact.notes);
+        assert_eq!(back.birthday, contact.birthday);
+        assert_eq!(back.tags, vec!["work/analytics", "friends", "Family"]);
+        assert_eq!(back.updated_at.timestamp(), contact.updated_at.timestamp());
+    }
+
+    #[test]
+    fn test_vcard_4_round_trip() {
+        let mut contact = Contact::new(
+            "Grace Hopper",
+            "+1-202-555-0147",
+            "grace@navy.mil",
+            vec![],
+            Utc::now(),
+            Utc::now(),
+        );
+        contact
+            .phone
+            .push(Phone::with_label("0301234567", PhoneLabel::Other));
+        contact
+            .email
+            .push(Email::with_label("grace@old.example", EmailLabel::Other));
+        contact.birthday = Some("1906-12-09".parse().unwrap());
+        contact.anniversary = Some("--06-15".parse().unwrap());
+        contact.photo = Some(Attachment {
+            name: "grace.png".to_string(),
+            media_type: "image/png".to_string(),
+            hash: "unused".to_string(),
+            size: 8,
+        });
+
+        let card = to_vcard(&contact, Some(b"\x89PNG\r\n\x1a\n"), &[], Version::V4);
+        assert!(card.contains("VERSION:4.0\r\n"));
+        assert!(card.contains(&format!("UID:urn:uuid:{}\r\n", contact.id)));
+        assert!(card.contains("TEL;VALUE=uri;TYPE=CELL:tel:+1-202-555-0147\r\n"));
+        assert!(card.contains("EMAIL;TYPE=HOME;PREF=1:grace@navy.mil\r\n"));
+        assert!(card.contains("BDAY:19061209\r\n"));
+        assert!(card.contains("ANNIVERSARY:--0615\r\n"));
+        assert!(card.contains("PHOTO:data:image/png;base64,"));
+
+        let parsed = parse(&card).unwrap();
+        let back = &parsed[0].contact;
+        assert_eq!(back.id, contact.id);
+        assert_eq!(back.phone, contact.phone);
+        assert_eq!(back.email, contact.email);
+        assert_eq!(back.birthday, contact.birthday);
+        assert_eq!(back.anniversary, contact.anniversary);
+        assert_eq!(parsed[0].photo.as_deref(), Some(&b"\x89PNG\r\n\x1a\n"[..]));
+
+        // The labels without a vCard type come back from 3.0 cards too
+        let parsed = parse(&to_vcard(&contact, None, &[], Version::V3)).unwrap();
+        assert_eq!(parsed[0].contact.phone, contact.phone);
+        assert_eq!(parsed[0].contact.email, contact.email);
+    }
+
+    #[test]
+    fn test_parse_other_clients() {
+        let data = "BEGIN:VCARD\nVERSION:2.1\nN:Okafor;Chidi;;;\nitem1.TEL;CELL:+234 801 234 5678\n\
+                    EMAIL;TYPE=INTERNET;TYPE=WORK:chidi@acme.ng\nBDAY:19850203\n\
+                    PHOTO;ENCODING=BASE64;TYPE=PNG:iVBORw0K\n Ggo=\nEND:VCARD\n";
+        let cards = parse(data).unwrap();
+        let chidi = &cards[0].contact;
+        assert_eq!(chidi.name, "Chidi Okafor");
+        assert_eq!(chidi.phone[0].label, PhoneLabel::Mobile);
+        assert_eq!(chidi.email[0].label, EmailLabel::Work);
+        assert!(chidi.email[0].primary);
+        assert_eq!(chidi.birthday, Some("1985-02-03".parse().unwrap()));
+        assert_eq!(cards[0].photo.as_deref(), Some(&b"\x89PNG\r\n\x1a\n"[..]));
+
+        assert!(parse("BEGIN:VCARD\nTEL:123\nEND:VCARD\n").is_err());
+        assert!(parse("BEGIN:VCARD\nFN:Open\n").is_err());
+    }
+}
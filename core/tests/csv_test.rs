@@ -0,0 +1,199 @@
+use std::{collections::HashMap, fs};
+
+use chrono::Utc;
+use rolodex_core::{
+    address::{Address, AddressLabel},
+    csv_profile::CsvProfile,
+    domain::{
+        Contact, export_csv, export_csv_with_groups, export_csv_with_profile, import_csv,
+        import_csv_with_profile, read_csv_rows,
+    },
+    email::{Email, EmailLabel},
+    group,
+    phone::{Phone, PhoneLabel},
+};
+use tempfile::tempdir;
+
+#[test]
+fn test_csv_round_trip_keeps_name_parts_and_lists() {
+    let dir = tempdir().unwrap();
+    let path = dir.path().join("contacts.csv");
+    let path = path.to_str().unwrap();
+
+    let mut contact = Contact::new(
+        "Jean-Luc Picard",
+        "08123456789",
+        "picard@enterprise.org",
+        vec!["work".into(), "starfleet".into()],
+        Utc::now(),
+        Utc::now(),
+    );
+    contact.nickname = Some("JL".to_string());
+    contact
+        .email
+        .push(Email::with_label("jl@starfleet.org", EmailLabel::Work));
+    contact.organization = Some("Starfleet".to_string());
+    contact.title = Some("Captain".to_string());
+    contact.addresses.push(Address {
+        label: AddressLabel::Work,
+        street: Some("1 Starfleet Plaza".to_string()),
+        city: Some("San Francisco".to_string()),
+        country: Some("US".to_string()),
+        ..Default::default()
+    });
+    contact
+        .phone
+        .push(Phone::with_label("+1-202-555-0147", PhoneLabel::Work));
+    contact.birthday = Some("2305-07-13".parse().unwrap());
+    contact.anniversary = Some("--04-05".parse().unwrap());
+    contact.dates.push("command=2364-01-01".parse().unwrap());
+
+    export_csv(path, std::slice::from_ref(&contact)).unwrap();
+    let imported = import_csv(path).unwrap();
+
+    assert_eq!(imported[0].id, contact.id);
+    assert_eq!(imported[0].family_name.as_deref(), Some("Picard"));
+    assert_eq!(imported[0].nickname.as_deref(), Some("JL"));
+    assert_eq!(imported[0].phone, contact.phone);
+    assert_eq!(imported[0].phone[1].label, PhoneLabel::Work);
+    assert_eq!(imported[0].email, contact.email);
+    assert_eq!(imported[0].primary_email(), "picard@enterprise.org");
+    assert_eq!(imported[0].email[1].label, EmailLabel::Work);
+    assert_eq!(imported[0].addresses, contact.addresses);
+    assert_eq!(
+        imported[0].job_line().as_deref(),
+        Some("Captain, Starfleet")
+    );
+    assert_eq!(imported[0].birthday, contact.birthday);
+    assert_eq!(imported[0].anniversary, contact.anniversary);
+    assert_eq!(imported[0].dates, contact.dates);
+    assert_eq!(imported[0].tags, contact.tags);
+}
+
+#[test]
+fn test_csv_import_minimal_columns() {
+    let dir = tempdir().unwrap();
+    let path = dir.path().join("contacts.csv");
+    fs::write(
+        &path,
+        "name,phone,email,tags\nAlice Johnson,+1-202-555-0147,alice@example.com,\"friend;work\"\n",
+    )
+    .unwrap();
+
+    let imported = import_csv(path.to_str().unwrap()).unwrap();
+
+    assert_eq!(imported[0].given_name.as_deref(), Some("Alice"));
+    assert_eq!(imported[0].family_name.as_deref(), Some("Johnson"));
+    assert_eq!(imported[0].tags, vec!["friend", "work"]);
+    assert_eq!(imported[0].primary_email(), "alice@example.com");
+}
+
+#[test]
+fn test_csv_export_with_group_membership() {
+    let dir = tempdir().unwrap();
+    let path = dir.path().join("contacts.csv");
+    let path = path.to_str().unwrap();
+
+    let contact = Contact::new(
+        "Alice Johnson",
+        "+1-202-555-0147",
+        "alice@example.com",
+        vec![],
+        Utc::now(),
+        Utc::now(),
+    );
+    let mut groups = HashMap::new();
+    for name in ["Family", "Book club"] {
+        let group = group::create(&mut groups, name, None).unwrap();
+        groups.get_mut(&group.id).unwrap().add_member(contact.id);
+    }
+
+    export_csv_with_groups(path, std::slice::from_ref(&contact), &groups).unwrap();
+    let data = fs::read_to_string(path).unwrap();
+    assert!(
+        data.lines()
+            .next()
+            .unwrap()
+            .ends_with(",groups,created_at,updated_at")
+    );
+    assert!(data.contains("Book club;Family"));
+
+    // Membership is not part of the contact, so import skips the column
+    assert_eq!(import_csv(path).unwrap()[0].id, contact.id);
+}
+
+#[test]
+fn test_csv_google_profile() {
+    let dir = tempdir().unwrap();
+    let path = dir.path().join("google.csv");
+    let path = path.to_str().unwrap();
+    fs::write(
+        path,
+        "First Name,Last Name,Birthday,Labels,E-mail 1 - Type,E-mail 1 - Value,Phone 1 - Value\n\
+         Ada,Lovelace,1815-12-10,* myContacts ::: Math,Home,ada@example.org ::: ada@work.org,+44 20 7946 0018\n",
+    )
+    .unwrap();
+    let google = CsvProfile::preset("google").unwrap();
+
+    let imported = import_csv_with_profile(path, &google).unwrap();
+    assert_eq!(imported[0].name, "Ada Lovelace");
+    assert_eq!(imported[0].tags, vec!["Math"]);
+    assert_eq!(imported[0].email.len(), 2);
+    assert_eq!(imported[0].primary_email(), "ada@example.org");
+    assert_eq!(imported[0].birthday, Some("1815-12-10".parse().unwrap()));
+
+    // Our own layout only finds `Birthday`, and a row needs a name
+    assert!(import_csv(path).is_err());
+
+    export_csv_with_profile(path, &imported, &HashMap::new(), &google).unwrap();
+    let data = fs::read_to_string(path).unwrap();
+    assert!(data.starts_with("First Name,Middle Name,Last Name"));
+    assert!(data.contains(",ada@example.org,ada@work.org,"));
+    assert_eq!(
+        import_csv_with_profile(path, &google).unwrap()[0].email,
+        imported[0].email
+    );
+}
+
+#[test]
+fn test_csv_bad_rows_are_set_aside() {
+    let dir = tempdir().unwrap();
+    let path = dir.path().join("contacts.csv");
+    let path = path.to_str().unwrap();
+    fs::write(
+        path,
+        "name,phone,email,birthday\n\
+         Alice Johnson,+1-202-555-0147,alice@example.com,\n\
+         Bob Smith,12,bob@,\n\
+         Carol White,08123456789,carol@example.com,someday\n\
+         Dave Brown,08123456780,dave@example.com,1990-05-17\n",
+    )
+    .unwrap();
+    let profile = CsvProfile::default();
+
+    let rows = read_csv_rows(path, &profile).unwrap();
+    assert_eq!(rows.contacts.len(), 2);
+    assert_eq!(rows.rejected.len(), 2);
+    assert_eq!(rows.rejected[0].line, 3);
+    assert_eq!(
+        rows.rejected[0].reason,
+        "invalid phone number '12', invalid email 'bob@'"
+    );
+    assert_eq!(rows.rejected[1].line, 4);
+    assert!(rows.rejected[1].reason.contains("birthday"));
+
+    // Strict import names every bad line
+    let err = import_csv_with_profile(path, &profile)
+        .unwrap_err()
+        .to_string();
+    assert!(err.contains("line 3") && err.contains("line 4"));
+
+    let rejects = dir.path().join("rejects.csv");
+    let rejects = rejects.to_str().unwrap();
+    rows.write_rejects(rejects, &profile).unwrap();
+    let data = fs::read_to_string(rejects).unwrap();
+    assert!(data.starts_with("name,phone,email,birthday,error\n"));
+    assert!(data.contains("Bob Smith,12,bob@,,"));
+    // The error column is ignored, so fixed rows can be imported again
+    assert_eq!(read_csv_rows(rejects, &profile).unwrap().rejected.len(), 2);
+}
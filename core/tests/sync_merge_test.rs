@@ -6,6 +6,9 @@ mod merge_sync_tests {
     // use super::*;
     use chrono::{Duration, Utc};
     use rolodex_core::domain::{Contact, Contacts};
+    use rolodex_core::email::Email;
+    use rolodex_core::phone::Phone;
+    use rolodex_core::relation::{Relation, RelationKind};
     use rolodex_core::store::MergePolicy;
     use std::collections::HashMap;
     use std::fs;
@@ -26,18 +29,20 @@ mod merge_sync_tests {
         days_ago_updated: i64,
     ) -> Contact {
         let now = Utc::now();
-        Contact {
+        let mut contact = Contact {
             id: Uuid::new_v4(),
             name: name.to_string(),
-            phone: phone.iter().map(|s| s.to_string()).collect(),
-            email: email.to_string(),
+            phone: phone.iter().map(|s| Phone::from(*s)).collect(),
             tags: tags.iter().map(|s| s.to_string()).collect(),
             created_at: now - Duration::days(days_ago_created),
             updated_at: now - Duration::days(days_ago_updated),
-        }
+            ..Default::default()
+        };
+        contact.set_primary_email(Email::new(email));
+        contact
     }
 
-    /// Create a contact with specific ID
+    // Create a contact with specific ID
     // fn create_contact_with_id(
     //     id: Uuid,
     //     name: &str,
@@ -196,7 +201,7 @@ mod merge_sync_tests {
 
         // Contact was overwritten with newer data
         let stored = contacts.items.values().next().unwrap();
-        assert_eq!(stored.email, "john.new@example.com");
+        assert_eq!(stored.primary_email(), "john.new@example.com");
         assert_eq!(stored.tags, vec!["work"]);
         // ID should be preserved
         assert_eq!(stored.id, original_id);
@@ -237,7 +242,7 @@ mod merge_sync_tests {
 
         // Local contact unchanged
         let stored = contacts.items.values().next().unwrap();
-        assert_eq!(stored.email, "john.new@example.com");
+        assert_eq!(stored.primary_email(), "john.new@example.com");
         assert_eq!(stored.tags, vec!["work"]);
     }
 
@@ -321,4 +326,150 @@ mod merge_sync_tests {
     //     let stored = contacts.items.values().next().unwrap();
     //     assert_eq!(stored.id, original_id);
     // }
+
+    #[test]
+    fn test_merge_overwrite_keeps_every_email() {
+        // Local is more complete, the import is newer: the two get merged
+        let local_contact = create_contact(
+            "John Doe",
+            vec!["1234567890"],
+            "john@example.com",
+            vec!["work", "friend", "gym"],
+            10,
+            5,
+        );
+        let id = local_contact.id;
+        let mut contacts = create_test_contacts(vec![local_contact]);
+
+        let import_contact = create_contact(
+            "John Doe",
+            vec!["1234567890"],
+            "john@work.com",
+            vec![],
+            8,
+            1,
+        );
+        let import_file = write_contacts_to_file(vec![import_contact]);
+
+        let result =
+            contacts.merge_from_file(import_file.path().to_str().unwrap(), MergePolicy::Overwrite);
+
+        assert_eq!(result.unwrap(), 1);
+        let stored = &contacts.items[&id];
+        assert_eq!(stored.primary_email(), "john@work.com");
+        assert_eq!(stored.email.len(), 2);
+        assert!(stored.has_domain("example.com"));
+        assert_eq!(contacts.index.lookup_domain("example.com").len(), 1);
+    }
+
+    #[test]
+    fn test_merge_remaps_relations_to_local_ids() {
+        // Jane exists locally; Max is new and gets a fresh id on import
+        let local_jane = create_contact(
+            "Jane Roe",
+            vec!["5550001"],
+            "jane@example.com",
+            vec![],
+            10,
+            5,
+        );
+        let jane_id = local_jane.id;
+        let mut contacts = create_test_contacts(vec![local_jane]);
+
+        let remote_jane = create_contact(
+            "Jane Roe",
+            vec!["5550001"],
+            "jane@example.com",
+            vec![],
+            10,
+            5,
+        );
+        let mut remote_max =
+            create_contact("Max Roe", vec!["5550002"], "max@example.com", vec![], 3, 1);
+        remote_max.relations.push(Relation {
+            kind: RelationKind::Spouse,
+            contact_id: remote_jane.id,
+        });
+        let mut remote_ann =
+            create_contact("Ann Lee", vec!["5550003"], "ann@example.com", vec![], 3, 1);
+        remote_ann.relations.push(Relation {
+            kind: RelationKind::ReferredBy,
+            contact_id: remote_max.id,
+        });
+        let import_file = write_contacts_to_file(vec![remote_ann, remote_jane, remote_max]);
+
+        contacts
+            .merge_from_file(import_file.path().to_str().unwrap(), MergePolicy::Keep)
+            .unwrap();
+
+        let find = |name: &str| contacts.items.values().find(|c| c.name == name).unwrap();
+        let max = find("Max Roe").clone();
+        assert_eq!(max.relations[0].contact_id, jane_id);
+        assert_eq!(find("Ann Lee").relations[0].contact_id, max.id);
+
+        // The history records the remapped links too
+        let changes = contacts.take_changes();
+        let recorded = changes.iter().find(|r| r.contact_id == max.id).unwrap();
+        assert_eq!(recorded.after.as_ref().unwrap().relations, max.relations);
+    }
+
+    // ============================================================================
+    // TOMBSTONES
+    // ============================================================================
+
+    #[test]
+    fn test_merge_applies_remote_deletion() {
+        let local_contact = create_contact(
+            "John Doe",
+            vec!["1234567890"],
+            "john@example.com",
+            vec![],
+            10,
+            5,
+        );
+        let id = local_contact.id;
+        let mut contacts = create_test_contacts(vec![local_contact.clone()]);
+
+        // The other device deleted John after our last edit
+        let mut tombstone = local_contact;
+        tombstone.deleted_at = Some(Utc::now() - Duration::days(1));
+        let import_file = write_contacts_to_file(vec![tombstone]);
+
+        let result =
+            contacts.merge_from_file(import_file.path().to_str().unwrap(), MergePolicy::Keep);
+
+        assert_eq!(result.unwrap(), 1);
+        assert!(contacts.items[&id].is_deleted());
+        assert!(
+            contacts
+                .search(Some("John Doe".into()), None, None, None)
+                .unwrap()
+                .is_empty()
+        );
+    }
+
+    #[test]
+    fn test_merge_does_not_resurrect_deleted_contact() {
+        let local_contact = create_contact(
+            "John Doe",
+            vec!["1234567890"],
+            "john@example.com",
+            vec![],
+            10,
+            5,
+        );
+        let id = local_contact.id;
+        let mut contacts = create_test_contacts(vec![local_contact.clone()]);
+        contacts.delete(id).unwrap();
+
+        // An older live copy from another device
+        let import_file = write_contacts_to_file(vec![local_contact]);
+
+        let result =
+            contacts.merge_from_file(import_file.path().to_str().unwrap(), MergePolicy::Keep);
+
+        assert_eq!(result.unwrap(), 0);
+        assert_eq!(contacts.items.len(), 1);
+        assert!(contacts.items[&id].is_deleted());
+    }
 }
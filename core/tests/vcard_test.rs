@@ -0,0 +1,57 @@
+use std::collections::HashMap;
+
+use chrono::Utc;
+use rolodex_core::{
+    attachment::AttachmentStore,
+    domain::{Contact, Contacts},
+    group,
+    store::MergePolicy,
+    vcard::{Version, export_vcf, import_vcf},
+};
+use tempfile::tempdir;
+
+#[test]
+fn test_vcf_round_trip_with_photo_and_merge() {
+    let dir = tempdir().unwrap();
+    let path = dir.path().join("contacts.vcf");
+    let path = path.to_str().unwrap();
+    let local = AttachmentStore::new(dir.path().join("local"));
+    let other = AttachmentStore::new(dir.path().join("other"));
+
+    let mut alice = Contact::new(
+        "Alice Johnson",
+        "+1-202-555-0147",
+        "alice@example.com",
+        vec!["work".into()],
+        Utc::now(),
+        Utc::now(),
+    );
+    alice.photo = Some(local.put("alice.png", b"\x89PNG\r\n\x1a\nalice").unwrap());
+    let bob = Contact::new(
+        "Bob Smith",
+        "08123456789",
+        "bob@example.com",
+        vec![],
+        Utc::now(),
+        Utc::now(),
+    );
+    let mut groups = HashMap::new();
+    let family = group::create(&mut groups, "Family", None).unwrap();
+    groups.get_mut(&family.id).unwrap().add_member(bob.id);
+
+    let contacts = [alice.clone(), bob.clone()];
+    export_vcf(path, &contacts, &local, &groups, Version::V3).unwrap();
+    let imported = import_vcf(path, &other).unwrap();
+
+    assert_eq!(imported.len(), 2);
+    let photo = imported[0].photo.as_ref().unwrap();
+    assert_eq!(other.get(&photo.hash).unwrap(), b"\x89PNG\r\n\x1a\nalice");
+    assert_eq!(imported[1].tags, vec!["Family"]);
+
+    // Imports take the same path as `sync`: known contacts are not added twice
+    let mut book = Contacts::new(HashMap::from([(alice.id, alice.clone())]));
+    let merged = book.merge_contacts(imported, MergePolicy::Keep).unwrap();
+    assert_eq!(merged, 1);
+    assert_eq!(book.items.len(), 2);
+    assert_eq!(book.take_changes().len(), 1);
+}